@@ -0,0 +1,21 @@
+use std::{env, process::Command};
+
+/// Exposes the commit being built as `GIT_HASH`. Heroku builds without the `.git`
+/// directory but exports the commit as `SOURCE_VERSION`.
+fn main() {
+    let git_hash = env::var("SOURCE_VERSION")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+    println!("cargo:rerun-if-env-changed=SOURCE_VERSION");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
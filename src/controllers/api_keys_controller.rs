@@ -0,0 +1,81 @@
+use super::schema::{ApiKeyCreateRequest, ApiKeyRotateRequest, SerlizedId};
+use crate::middleware::auth_middelware::{Identity, ADMINS};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::api_keys::{self, default_grace_period_hours};
+use actix_web::{
+    delete, get, post, put,
+    web::{self, Json},
+    HttpResponse,
+};
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+
+#[get("apikey/all")]
+async fn get_all_api_keys(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    Ok(HttpResponse::Ok().json(api_keys::list_api_keys(&app_state.container.api_key).await?))
+}
+
+#[post("apikey/create")]
+async fn create_api_key(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    api_key_data: Json<ApiKeyCreateRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    let api_key_data = api_key_data.into_inner();
+    let expires_at = match api_key_data.expires_in_days {
+        Some(days) if days <= 0 => return Err(ContentBuilderCustomResponseError::BadClientData),
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+    let created = api_keys::create_api_key(
+        &app_state.container.api_key,
+        api_key_data.name,
+        api_key_data.scopes,
+        expires_at,
+        None,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(created))
+}
+
+#[put("apikey/rotate")]
+async fn rotate_api_key(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    rotate_data: Json<ApiKeyRotateRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    ObjectId::with_string(&rotate_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let grace_period_hours = rotate_data
+        .grace_period_hours
+        .unwrap_or_else(default_grace_period_hours);
+    if grace_period_hours < 0 {
+        return Err(ContentBuilderCustomResponseError::BadClientData);
+    }
+    let rotated = api_keys::rotate_api_key(
+        &app_state.container.api_key,
+        &rotate_data.id,
+        grace_period_hours,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(rotated))
+}
+
+#[delete("apikey/revoke")]
+async fn revoke_api_key(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    revoke_data: Json<SerlizedId>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    ObjectId::with_string(&revoke_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let revoked = api_keys::revoke_api_key(&app_state.container.api_key, &revoke_data.id).await?;
+    Ok(HttpResponse::Ok().json(revoked))
+}
@@ -0,0 +1,107 @@
+use super::schema::{AssetDeserializeModel, AssetGcRequest, AssetResponseModel, SerlizedId};
+use crate::middleware::auth_middelware::{Identity, ADMINS, CATALOG_READ, PROJECTS_READ, STAFF};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::gc::{collect_garbage, default_grace_period_hours};
+use crate::services::assets::{
+    asset_file, is_private, store_upload, take_file, text_field, AssetKind,
+};
+use crate::services::authorization::authorize_project;
+use actix_web::{
+    post,
+    web::{self, Json},
+    HttpResponse,
+};
+use awmp::Parts;
+use bson::oid::ObjectId;
+
+#[post("asset/upload")]
+async fn upload_asset(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    parts: Parts,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(STAFF)?;
+    let kind = AssetKind::parse(&text_field(&parts, "kind")?)
+        .ok_or(ContentBuilderCustomResponseError::BadClientData)?;
+    let owner = match parts.texts.as_hash_map().get("owner") {
+        Some(owner) => Some(
+            ObjectId::with_string(owner)
+                .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?,
+        ),
+        None => None,
+    };
+    let asset = store_upload(
+        app_state.storage.as_ref(),
+        &app_state.container.asset,
+        kind,
+        kind.policy(),
+        take_file(parts, "file")?,
+        owner,
+    )
+    .await?;
+    let file = asset_file(&asset).signed();
+    Ok(HttpResponse::Ok().json(AssetResponseModel::build_asset(asset, file)))
+}
+
+#[post("asset/get")]
+async fn get_asset_by_id(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    asset_data: Json<SerlizedId>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
+    match app_state
+        .container
+        .asset
+        .find_one_by_id(&asset_data.id)
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<AssetDeserializeModel>(document) {
+            Ok(asset) => {
+                // the signed link of a deliverable is for its project's readers only
+                if is_private(&asset) {
+                    match &asset.owner {
+                        Some(owner) => {
+                            authorize_project(
+                                &app_state.container,
+                                &identity,
+                                &owner.to_hex(),
+                                PROJECTS_READ,
+                            )
+                            .await?
+                        }
+                        None => identity.require(PROJECTS_READ)?,
+                    }
+                }
+                let file = asset_file(&asset).signed();
+                Ok(HttpResponse::Ok().json(AssetResponseModel::build_asset(asset, file)))
+            }
+            Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("asset/gc")]
+async fn collect_orphaned_assets(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    gc_data: Json<AssetGcRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    let grace_period_hours = gc_data
+        .grace_period_hours
+        .unwrap_or_else(default_grace_period_hours);
+    if grace_period_hours < 0 {
+        return Err(ContentBuilderCustomResponseError::BadClientData);
+    }
+    let report = collect_garbage(
+        &app_state.container,
+        app_state.storage.as_ref(),
+        gc_data.dry_run.unwrap_or(true),
+        grace_period_hours,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -1,20 +1,27 @@
 extern crate jsonwebtoken as jwt;
-use super::schema::{Category, CategoryDeserializeModel, CategoryResponseModel, File, SerlizedId};
+use super::schema::{
+    Category, CategoryDeserializeModel, CategoryResponseModel, DeleteRequest, File, SerlizedId,
+};
+use crate::middleware::auth_middelware::{Identity, CATALOG_READ, CATALOG_WRITE};
 use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::models::indexes::is_duplicate_key;
+use crate::services::assets::{asset_file, store_upload, take_file, text_field, AssetKind};
+use crate::services::integrity::release_category;
 use actix_web::{
     delete, get, post, put,
     web::{self, Json},
     HttpResponse,
 };
 use awmp::Parts;
-use bson::Document;
+use bson::{oid::ObjectId, Document};
 use futures::stream::StreamExt;
-use std::path::PathBuf;
 
 #[get("category/all")]
 async fn get_all_categories(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state.container.category.find_all().await {
         Ok(cursor) => {
             let categories: Vec<CategoryResponseModel> = cursor
@@ -41,8 +48,13 @@ async fn get_all_categories(
 #[delete("category/delete")]
 async fn delete_category(
     app_state: web::Data<crate::AppState>,
-    category_data: Json<SerlizedId>,
+    identity: Identity,
+    category_data: Json<DeleteRequest>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let id = ObjectId::with_string(&category_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    release_category(&app_state.container, &id, category_data.policy).await?;
     match app_state
         .container
         .category
@@ -69,8 +81,10 @@ async fn delete_category(
 #[post("category/get")]
 async fn get_category_by_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     category_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state
         .container
         .category
@@ -98,8 +112,10 @@ async fn get_category_by_id(
 #[post("category/create")]
 async fn create_category(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     category:Json<Category>
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&category.into_inner()).and_then(|category_data| {
         match serde_json::from_str::<Category>(&category_data) {
             Ok(category) => Ok(category),
@@ -138,6 +154,9 @@ async fn create_category(
             }
             None => Err(ContentBuilderCustomResponseError::InternalError),
         },
+        Err(mongodb_error) if is_duplicate_key(&mongodb_error) => {
+            Err(ContentBuilderCustomResponseError::AlreadyExists)
+        }
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
     },
@@ -149,8 +168,10 @@ async fn create_category(
 #[put("category/update")]
 async fn update_category(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     category:Json<CategoryResponseModel>
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&category.into_inner()).and_then(|category_data| {
         match serde_json::from_str::<CategoryResponseModel>(&category_data) {
             Ok(category) => Ok(category),
@@ -183,10 +204,54 @@ async fn update_category(
                 }
             }
     }
+        Err(mongodb_error) if is_duplicate_key(&mongodb_error) => {
+            Err(ContentBuilderCustomResponseError::AlreadyExists)
+        }
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
     },
     Err(e)=>Err(ContentBuilderCustomResponseError::InternalError)
     }
 
-}
\ No newline at end of file
+}
+
+#[put("category/image/upload")]
+async fn upload_category_image(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    parts: Parts,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let category_id = text_field(&parts, "id")?;
+    ObjectId::with_string(&category_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state.container.category.find_one_by_id(&category_id).await {
+        Ok(Some(_document)) => {}
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    }
+    let asset = store_upload(
+        app_state.storage.as_ref(),
+        &app_state.container.asset,
+        AssetKind::CategoryImage,
+        AssetKind::CategoryImage.policy(),
+        take_file(parts, "image")?,
+        None,
+    )
+    .await?;
+    match app_state
+        .container
+        .category
+        .update_image(&category_id, asset_file(&asset))
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<CategoryDeserializeModel>(document) {
+            Ok(category) => {
+                Ok(HttpResponse::Ok().json(CategoryResponseModel::build_category(category)))
+            }
+            Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
@@ -1,9 +1,14 @@
 extern crate jsonwebtoken as jwt;
 use super::schema::{
-    Feature, FeatureDeserializeModel, FeatureObject, FeatureResponseModel, File, FileWithId,
-    SerlizedId, UpdateFeatureWireframesModel,
+    DeleteRequest, Feature, FeatureDeserializeModel, FeatureObject, FeatureResponseModel, File,
+    FileWithId, SerlizedId, UpdateFeatureWireframesModel,
 };
+use crate::middleware::auth_middelware::{Identity, CATALOG_READ, CATALOG_WRITE};
 use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::assets::{
+    asset_file, store_upload, take_file, take_files, text_field, AssetKind,
+};
+use crate::services::integrity::release_feature;
 use actix_web::{
     delete, get, post, put,
     web::{self, Json},
@@ -12,12 +17,13 @@ use actix_web::{
 use awmp::Parts;
 use bson::{oid::ObjectId, Document};
 use futures::stream::StreamExt;
-use std::path::PathBuf;
 
 #[get("feature/all")]
 async fn get_all_features(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state.container.feature.find_all().await {
         Ok(cursor) => {
             let features: Vec<FeatureResponseModel> = cursor
@@ -39,197 +45,13 @@ async fn get_all_features(
     }
 }
 
-// #[post("feature/add")]
-// async fn add_feature(
-//     app_state: web::Data<crate::AppState>,
-//     mut parts: Parts,
-// ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
-//     let form_data = parts.texts.as_hash_map();
-//     let image = parts
-//         .files
-//         .take("image")
-//         .pop()
-//         .and_then(|file| {
-//             file.persist_in(PathBuf::from("./static/uploads/features_imgs"))
-//                 .ok()
-//         })
-//         .and_then(|file_path| {
-//             file_path.file_name().and_then(|os_path| {
-//                 os_path
-//                     .to_str()
-//                     .and_then(|sentaized_path| Some(sentaized_path.to_string()))
-//             })
-//         })
-//         .unwrap();
-
-//     let images = parts
-//         .files
-//         .take("wireframes")
-//         .into_iter()
-//         .map(|file| {
-
-//             file.persist_in(PathBuf::from("./static/uploads/wireframes"))
-//                 .ok()
-//         })
-//         .into_iter()
-//         .map(|path| {
-//             let file_name = path.and_then(|file_path|{
-//                 file_path.file_name().and_then(|os_path|{
-//                     os_path.to_str().and_then(|sentaized_path|{
-//                         Some(sentaized_path.to_string())
-//                     })
-//                 })
-//             }).unwrap();
-
-//             FileWithId {
-//                 _id: ObjectId::new(),
-//                 name: format!("{}", file_name.clone()),
-//                 src: format!("https://astrobuild-builder-service-v1.herokuapp.com/media/static/uploads/wireframes/{}", file_name.clone()),
-//             }
-//         })
-//         .collect::<Vec<FileWithId>>();
-
-//     match app_state
-//         .container
-//         .feature
-//         .insert_one(Feature {
-//             name: form_data["name"].to_string(),
-//             description: form_data["description"].to_string(),
-//             feature_type: form_data["feature_type"].to_string(),
-//             image: File {
-//                 name: image.clone(),
-//                 src: format!("/media/static/uploads/features_imgs/{}", image.clone()),
-//             },
-//             wireframes: Some(images),
-//             price: form_data["price"].parse::<f64>().unwrap(),
-//             repo: form_data["repo"].to_string(),
-//         })
-//         .await
-//     {
-//         Ok(feature_id) => match feature_id.inserted_id.as_object_id() {
-//             Some(object_id) => {
-//                 match app_state
-//                     .container
-//                     .feature
-//                     .find_one_by_id(&object_id.to_string())
-//                     .await
-//                     .and_then(|document| {
-//                         Ok(match document {
-//                             Some(document) => document,
-//                             None => Document::new(),
-//                         })
-//                     }) {
-//                     Ok(feature_document) => match feature_document {
-//                         document => {
-//                             match bson::from_document::<FeatureDeserializeModel>(document) {
-//                                 Ok(category) => Ok(HttpResponse::Ok()
-//                                     .json(FeatureResponseModel::build_feature(category))),
-//                                 Err(_bson_de_error) => {
-//                                     Err(ContentBuilderCustomResponseError::InternalError)
-//                                 }
-//                             }
-//                         }
-//                     },
-
-//                     Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
-//                 }
-//             }
-//             None => Err(ContentBuilderCustomResponseError::InternalError),
-//         },
-//         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
-//     }
-//     // Ok(HttpResponse::Ok().body("ok"))
-// }
-
-// #[put("feature/update")]
-// async fn update_feature(
-//     app_state: web::Data<crate::AppState>,
-//     mut parts: Parts,
-// ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
-//     let form_data = parts.texts.as_hash_map();
-//     let image = parts
-//         .files
-//         .take("image")
-//         .pop()
-//         .and_then(|file| {
-//             file.persist_in(PathBuf::from("./static/uploads/features_imgs"))
-//                 .ok()
-//         })
-//         .and_then(|file_path| {
-//             file_path.file_name().and_then(|os_path| {
-//                 os_path
-//                     .to_str()
-//                     .and_then(|sentaized_path| Some(sentaized_path.to_string()))
-//             })
-//         })
-//         .unwrap();
-
-//     let images = parts
-//         .files
-//         .take("wireframes")
-//         .into_iter()
-//         .map(|file| {
-//             file.persist_in(PathBuf::from("./static/uploads/wireframes"))
-//                 .ok()
-//         })
-//         .into_iter()
-//         .map(|path| {
-//             let file_name = path.and_then(|file_path|{
-//                 file_path.file_name().and_then(|os_path|{
-//                     os_path.to_str().and_then(|sentaized_path|{
-//                         Some(sentaized_path.to_string())
-//                     })
-//                 })
-//             }).unwrap();
-//             FileWithId {
-//                 _id: ObjectId::new(),
-//                 name: format!("{}", file_name.clone()),
-//                 src: format!("https://astrobuild-builder-service-v1.herokuapp.com/media/static/uploads/wireframes/{}", file_name.clone()),
-//             }
-//         })
-//         .collect::<Vec<FileWithId>>();
-
-//     match app_state
-//         .container
-//         .feature
-//         .update_one(
-//             &form_data["id"].to_string(),
-//             Feature {
-//                 name: form_data["name"].to_string(),
-//                 description: form_data["description"].to_string(),
-//                 feature_type: form_data["feature_type"].to_string(),
-//                 image: File {
-//                     name: image.clone(),
-//                     src: format!("https://astrobuild-builder-service-v1.herokuapp.com/media/static/uploads/features_imgs/{}", image.clone()),
-//                 },
-//                 wireframes: Some(images),
-//                 price: form_data["price"].parse::<f64>().unwrap(),
-//                 repo: form_data["repo"].to_string(),
-//             },
-//         )
-//         .await
-//     {
-//         Ok(result) => {
-//             if result != None {
-//                 match bson::from_document::<FeatureDeserializeModel>(result.unwrap()) {
-//                     Ok(feature) => {
-//                         Ok(HttpResponse::Ok().json(FeatureResponseModel::build_feature(feature)))
-//                     }
-//                     Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
-//                 }
-//             } else {
-//                 Err(ContentBuilderCustomResponseError::NotFound)
-//             }
-//         }
-//         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
-//     }
-// }
-
 #[post("feature/get")]
 async fn get_feature_by_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     feature_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state
         .container
         .feature
@@ -266,8 +88,13 @@ async fn get_feature_by_id(
 #[delete("feature/delete")]
 async fn delete_feature(
     app_state: web::Data<crate::AppState>,
-    feature_data: Json<SerlizedId>,
+    identity: Identity,
+    feature_data: Json<DeleteRequest>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let id = ObjectId::with_string(&feature_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    release_feature(&app_state.container, &id, feature_data.policy).await?;
     match app_state
         .container
         .feature
@@ -304,8 +131,10 @@ async fn delete_feature(
 #[post("feature/wireframe/add")]
 async fn add_feature_wireframe(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     wireframes: Json<UpdateFeatureWireframesModel>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&wireframes.into_inner()).and_then(|wireframes| {
         match serde_json::from_str::<UpdateFeatureWireframesModel>(&wireframes) {
             Ok(feature) => Ok(feature),
@@ -326,6 +155,9 @@ async fn add_feature_wireframe(
                                 _id: ObjectId::with_string(&wireframe.id).unwrap(),
                                 name: wireframe.name,
                                 src: wireframe.src,
+                                thumbnail: wireframe.thumbnail,
+                                medium: wireframe.medium,
+                                source: wireframe.source,
                             })
                             .unwrap()
                             .as_document()
@@ -354,96 +186,23 @@ async fn add_feature_wireframe(
         }
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
-    // let feature_id = parts.texts.as_hash_map()["id"];
-    // let images = parts
-    //     .files
-    //     .take("wireframes")
-    //     .into_iter()
-    //     .map(|file| {
-    //         file.persist_in(PathBuf::from("./static/uploads/wireframes"))
-    //             .ok()
-    //     })
-    //     .into_iter()
-    //     .map(|path| {
-    //         let file_name =  path.and_then(|file_path|{
-    //             file_path.file_name().and_then(|os_path|{
-    //                 os_path.to_str().and_then(|sentaized_path|{
-    //                     Some(sentaized_path.to_string())
-    //                 })
-    //             })
-    //         }).unwrap();
-    //         FileWithId {
-    //             _id: ObjectId::new(),
-    //             name: format!("{}", file_name.clone()),
-    //             src: format!("https://astrobuild-builder-service-v1.herokuapp.com/media/static/uploads/wireframes/{}", file_name.clone()),
-    //         }
-    //     })
-    //     .collect::<Vec<FileWithId>>();
-
-    // match app_state
-    //     .container
-    //     .feature
-    //     .find_one_by_id(feature_id)
-    //     .await
-    //     .and_then(|document| {
-    //         let feature = match document {
-    //             Some(doc) => doc,
-    //             None => bson::Document::new(),
-    //         };
-    //         Ok(feature)
-    //     }) {
-    //     Ok(feature) => match feature {
-    //         feature => {
-    //             println!("Feature Dezrlized: {:?}", feature);
-    //             if !feature.is_empty() {
-    //                 match bson::from_document::<FeatureDeserializeModel>(feature) {
-    //                     Ok(feature) => {
-    //                         let mut files: Vec<bson::Document> = Vec::new();
-    //                         let feature = FeatureResponseModel::build_feature(feature);
-    //                         for file in images {
-    //                             let doc = match app_state
-    //                                 .container
-    //                                 .feature
-    //                                 .add_wireframe(&feature.id, file)
-    //                                 .await
-    //                             {
-    //                                 Ok(document) => Ok(match document {
-    //                                     Some(doc) => doc,
-    //                                     None => bson::Document::new(),
-    //                                 }),
-    //                                 Err(_mongodb_error) => {
-    //                                     Err(ContentBuilderCustomResponseError::InternalError)
-    //                                 }
-    //                             };
-    //                             files.push(doc?);
-    //                         }
-
-    //                         match bson::from_document::<FeatureDeserializeModel>(
-    //                             files.last().unwrap().clone(),
-    //                         ) {
-    //                             Ok(feature) => Ok(HttpResponse::Ok()
-    //                                 .json(FeatureResponseModel::build_feature(feature))),
-    //                             Err(_bson_de_error) => {
-    //                                 Err(ContentBuilderCustomResponseError::InternalError)
-    //                             }
-    //                         }
-    //                     }
-    //                     Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
-    //                 }
-    //             } else {
-    //                 Err(ContentBuilderCustomResponseError::NotFound)
-    //             }
-    //         }
-    //     },
-    //     Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
-    // }
 }
 
 #[delete("feature/wireframe/delete")]
 async fn delete_feature_wireframe(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     wireframe_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let wireframe_id = ObjectId::with_string(&wireframe_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    app_state
+        .container
+        .prototype
+        .remove_wireframe_hotspots(&wireframe_id)
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
     match app_state
         .container
         .feature
@@ -489,6 +248,9 @@ async fn delete_feature_wireframe(
                                         _id: ObjectId::with_string(&file.id).unwrap(),
                                         name: file.name.clone(),
                                         src: file.src.clone(),
+                                        thumbnail: file.thumbnail.clone(),
+                                        medium: file.medium.clone(),
+                                        source: file.source.clone(),
                                     })
                                 })
                                 .unwrap(),
@@ -521,8 +283,10 @@ async fn delete_feature_wireframe(
 #[post("feature/create")]
 async fn create_feature(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     feature: Json<FeatureObject>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&feature.into_inner()).and_then(|feature_data| {
         match serde_json::from_str::<FeatureObject>(&feature_data) {
             Ok(feature) => Ok(feature),
@@ -545,6 +309,9 @@ async fn create_feature(
                                 _id: ObjectId::with_string(&wireframe.id).unwrap(),
                                 name: wireframe.name,
                                 src: wireframe.src,
+                                thumbnail: wireframe.thumbnail,
+                                medium: wireframe.medium,
+                                source: wireframe.source,
                             })
                             .collect::<Vec<FileWithId>>(),
                         None => vec![],
@@ -597,8 +364,10 @@ async fn create_feature(
 #[put("feature/update")]
 async fn update_feature(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     feature: Json<FeatureResponseModel>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&feature.into_inner()).and_then(|feature_data| {
         match serde_json::from_str::<FeatureResponseModel>(&feature_data) {
             Ok(feature) => Ok(feature),
@@ -623,6 +392,9 @@ async fn update_feature(
                                     _id: ObjectId::with_string(&wireframe.id).unwrap(),
                                     name: wireframe.name,
                                     src: wireframe.src,
+                                    thumbnail: wireframe.thumbnail,
+                                    medium: wireframe.medium,
+                                    source: wireframe.source,
                                 })
                                 .collect::<Vec<FileWithId>>(),
                             None => vec![],
@@ -652,3 +424,99 @@ async fn update_feature(
         Err(e) => Err(ContentBuilderCustomResponseError::InternalError),
     }
 }
+
+/// Checked before an upload so no blob is stored for a feature that cannot take it.
+async fn require_feature(
+    app_state: &crate::AppState,
+    feature_id: &str,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    ObjectId::with_string(feature_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state.container.feature.find_one_by_id(feature_id).await {
+        Ok(Some(_document)) => Ok(()),
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[put("feature/image/upload")]
+async fn upload_feature_image(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    parts: Parts,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let feature_id = text_field(&parts, "id")?;
+    require_feature(&app_state, &feature_id).await?;
+    let asset = store_upload(
+        app_state.storage.as_ref(),
+        &app_state.container.asset,
+        AssetKind::FeatureImage,
+        AssetKind::FeatureImage.policy(),
+        take_file(parts, "image")?,
+        None,
+    )
+    .await?;
+    match app_state
+        .container
+        .feature
+        .update_image(&feature_id, asset_file(&asset))
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<FeatureDeserializeModel>(document) {
+            Ok(feature) => Ok(HttpResponse::Ok().json(FeatureResponseModel::build_feature(feature))),
+            Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("feature/wireframe/upload")]
+async fn upload_feature_wireframes(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    parts: Parts,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let feature_id = text_field(&parts, "id")?;
+    require_feature(&app_state, &feature_id).await?;
+    let files = take_files(parts, "wireframes")?;
+    let mut wireframes: Vec<Document> = Vec::new();
+    for file in files {
+        let asset = store_upload(
+            app_state.storage.as_ref(),
+            &app_state.container.asset,
+            AssetKind::Wireframe,
+            AssetKind::Wireframe.policy(),
+            file,
+            None,
+        )
+        .await?;
+        let file = asset_file(&asset);
+        wireframes.push(
+            bson::to_document(&FileWithId {
+                _id: asset._id,
+                name: file.name,
+                src: file.src,
+                thumbnail: file.thumbnail,
+                medium: file.medium,
+                source: None,
+            })
+            .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::InternalError)?,
+        );
+    }
+    match app_state
+        .container
+        .feature
+        .add_wireframe(&feature_id, wireframes)
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<FeatureDeserializeModel>(document) {
+            Ok(feature) => Ok(HttpResponse::Ok().json(FeatureResponseModel::build_feature(feature))),
+            Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
@@ -0,0 +1,29 @@
+use super::schema::{HealthResponse, VersionResponse};
+use crate::services::health::readiness;
+use actix_web::{get, web, HttpResponse};
+
+/// The process is up and serving, whatever the state of its dependencies.
+#[get("/health")]
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse { status: "ok" })
+}
+
+/// 503 only while the database is unreachable, incomplete indexes are reported as degraded.
+#[get("/ready")]
+async fn ready(app_state: web::Data<crate::AppState>) -> HttpResponse {
+    let report = readiness(&app_state.container.database, &app_state.declared_indexes).await;
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+#[get("/version")]
+async fn version(app_state: web::Data<crate::AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        storage: app_state.storage.name(),
+    })
+}
@@ -0,0 +1,354 @@
+use super::schema::{AssetDeserializeModel, MediaAccessQuery};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::assets::{content_type_for, is_private, is_private_key, verify_access_token};
+use crate::services::storage::{StorageBackend, StorageError};
+use actix_files::{HttpRange, NamedFile};
+use actix_web::{
+    get,
+    http::{
+        header::{self, ContentDisposition, DispositionParam, DispositionType, EntityTag, IfNoneMatch},
+        HeaderValue, StatusCode,
+    },
+    web::{self, Path, Query},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use std::io::ErrorKind;
+
+/// Keys are derived from the content hash, so a stored object never changes behind its url.
+const PUBLIC_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[get("media/{key:.*}")]
+async fn get_media(
+    app_state: web::Data<crate::AppState>,
+    request: HttpRequest,
+    key: Path<String>,
+    access: Query<MediaAccessQuery>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    let asset = match app_state.container.asset.find_one_by_key(&key).await {
+        Ok(Some(document)) => bson::from_document::<AssetDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let cache_control = cache_control(&asset, &key, &access)?;
+    // thumbnails and medium renditions resolve to the asset of their original
+    let (content_type, file_name) = if key.as_str() == asset.key {
+        (asset.content_type.as_str(), asset.name.clone())
+    } else {
+        let rendition = key.rsplit('/').next().unwrap_or_default();
+        (content_type_for(rendition), rendition.to_string())
+    };
+    let content_type = content_type
+        .parse::<mime::Mime>()
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    let content_disposition = ContentDisposition {
+        disposition: match content_type.type_() {
+            mime::IMAGE | mime::TEXT | mime::VIDEO => DispositionType::Inline,
+            _ => DispositionType::Attachment,
+        },
+        parameters: vec![DispositionParam::Filename(file_name)],
+    };
+
+    if let Some(path) = app_state.storage.local_path(&key) {
+        let file = NamedFile::open(path).map_err(|io_error| match io_error.kind() {
+            ErrorKind::NotFound => ContentBuilderCustomResponseError::NotFound,
+            _ => ContentBuilderCustomResponseError::InternalError,
+        })?;
+        let mut response = file
+            .set_content_type(content_type)
+            .set_content_disposition(content_disposition)
+            .into_response(&request)
+            .map_err(|_actix_error| ContentBuilderCustomResponseError::InternalError)?;
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&cache_control)
+                .map_err(|_header_error| ContentBuilderCustomResponseError::InternalError)?,
+        );
+        return Ok(response);
+    }
+
+    stored_response(
+        app_state.storage.as_ref(),
+        &request,
+        &key,
+        content_type,
+        content_disposition,
+        cache_control,
+    )
+    .await
+}
+
+/// The media route is open to everyone, private objects are only served on a signed
+/// link, which is never cached past its expiry.
+fn cache_control(
+    asset: &AssetDeserializeModel,
+    key: &str,
+    access: &MediaAccessQuery,
+) -> Result<String, ContentBuilderCustomResponseError> {
+    if !is_private(asset) && !is_private_key(&asset.key) && !is_private_key(key) {
+        return Ok(PUBLIC_CACHE_CONTROL.to_string());
+    }
+    match (access.expires, &access.token) {
+        (Some(expires), Some(token)) if verify_access_token(&asset.key, expires, token) => Ok(
+            format!("private, max-age={}", expires - Utc::now().timestamp()),
+        ),
+        _ => Err(ContentBuilderCustomResponseError::NotAllowed),
+    }
+}
+
+/// Conditional and range handling for backends without a local file. A range is read
+/// from the backend on its own, the whole object is only read when it is asked for.
+async fn stored_response(
+    storage: &dyn StorageBackend,
+    request: &HttpRequest,
+    key: &str,
+    content_type: mime::Mime,
+    content_disposition: ContentDisposition,
+    cache_control: String,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    let etag = EntityTag::strong(key.to_string());
+    let mut response = HttpResponse::Ok();
+    response
+        .header(header::CONTENT_TYPE, content_type.to_string())
+        .header(header::CONTENT_DISPOSITION, content_disposition.to_string())
+        .header(header::ETAG, etag.to_string())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, cache_control);
+
+    let not_modified = match request.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(&etag)),
+        None => false,
+    };
+    if not_modified {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    let range = match request.headers().get(header::RANGE) {
+        None => {
+            let bytes = storage.get(key).await.map_err(storage_error)?;
+            return Ok(response.body(bytes));
+        }
+        Some(range) => match range.to_str() {
+            Ok(range) => range,
+            Err(_header_error) => return Ok(response.status(StatusCode::BAD_REQUEST).finish()),
+        },
+    };
+    let size = storage.size(key).await.map_err(storage_error)?;
+    // `bytes=` parses to no range at all, `bytes=-0` to an empty one
+    let range = match HttpRange::parse(range, size) {
+        Ok(ranges) => ranges.into_iter().next().filter(|range| range.length > 0),
+        Err(_range_error) => None,
+    };
+    let range = match range {
+        Some(range) => range,
+        None => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .finish())
+        }
+    };
+    let bytes = storage
+        .get_range(key, range.start, range.length)
+        .await
+        .map_err(storage_error)?;
+    Ok(response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(
+            header::CONTENT_RANGE,
+            format!(
+                "bytes {}-{}/{}",
+                range.start,
+                range.start + range.length - 1,
+                size
+            ),
+        )
+        .body(bytes))
+}
+
+fn storage_error(error: StorageError) -> ContentBuilderCustomResponseError {
+    match error {
+        StorageError::NotFound => ContentBuilderCustomResponseError::NotFound,
+        _ => ContentBuilderCustomResponseError::InternalError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::assets::access_token;
+    use crate::services::storage::StoredObject;
+    use actix_web::test::TestRequest;
+    use async_trait::async_trait;
+    use bson::oid::ObjectId;
+
+    /// A single object, whatever the key.
+    struct Stored(Vec<u8>);
+
+    #[async_trait(?Send)]
+    impl StorageBackend for Stored {
+        fn name(&self) -> &'static str {
+            "one object"
+        }
+
+        async fn put(
+            &self,
+            _key: &str,
+            _bytes: Vec<u8>,
+            _content_type: &str,
+        ) -> Result<(), StorageError> {
+            Ok(())
+        }
+
+        async fn get(&self, _key: &str) -> Result<Vec<u8>, StorageError> {
+            Ok(self.0.clone())
+        }
+
+        async fn delete(&self, _key: &str) -> Result<(), StorageError> {
+            Ok(())
+        }
+
+        async fn exists(&self, _key: &str) -> Result<bool, StorageError> {
+            Ok(true)
+        }
+
+        async fn list(&self, _prefix: &str) -> Result<Vec<StoredObject>, StorageError> {
+            Ok(vec![])
+        }
+
+        async fn size(&self, _key: &str) -> Result<u64, StorageError> {
+            Ok(self.0.len() as u64)
+        }
+    }
+
+    async fn respond(stored: &[u8], range: Option<&str>) -> HttpResponse {
+        let mut request = TestRequest::get();
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        stored_response(
+            &Stored(stored.to_vec()),
+            &request.to_http_request(),
+            "wireframes/1/login.png",
+            mime::IMAGE_PNG,
+            ContentDisposition {
+                disposition: DispositionType::Inline,
+                parameters: vec![],
+            },
+            PUBLIC_CACHE_CONTROL.to_string(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn content_range(response: &HttpResponse) -> &str {
+        response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn without_a_range_the_whole_object_is_served() {
+        let response = respond(b"0123456789", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn a_range_is_served_partially() {
+        let response = respond(b"0123456789", Some("bytes=2-4")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(content_range(&response), "bytes 2-4/10");
+    }
+
+    #[actix_rt::test]
+    async fn a_range_list_without_ranges_is_not_satisfiable() {
+        for range in &["bytes=", "bytes=,"] {
+            let response = respond(b"0123456789", Some(range)).await;
+            assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+            assert_eq!(content_range(&response), "bytes */10");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn an_empty_range_is_not_satisfiable() {
+        for stored in &[&b""[..], &b"0123456789"[..]] {
+            let response = respond(stored, Some("bytes=-0")).await;
+            assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn a_range_past_the_end_is_not_satisfiable() {
+        let response = respond(b"0123456789", Some("bytes=20-30")).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    fn asset(kind: &str, key: &str) -> AssetDeserializeModel {
+        AssetDeserializeModel {
+            _id: ObjectId::new(),
+            kind: kind.to_string(),
+            key: key.to_string(),
+            name: "contract.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            size: 10,
+            owner: Some(ObjectId::new()),
+            created_at: Utc::now().into(),
+            hash: None,
+            thumbnail: None,
+            medium: None,
+        }
+    }
+
+    fn signed(key: &str, expires_in: i64) -> MediaAccessQuery {
+        std::env::set_var("MEDIA_SIGNING_KEY", "test-signing-key");
+        let expires = Utc::now().timestamp() + expires_in;
+        MediaAccessQuery {
+            expires: Some(expires),
+            token: Some(access_token(key, expires)),
+        }
+    }
+
+    fn unsigned() -> MediaAccessQuery {
+        MediaAccessQuery {
+            expires: None,
+            token: None,
+        }
+    }
+
+    #[test]
+    fn public_media_needs_no_link() {
+        let wireframe = asset("wireframe", "wireframes/1/login.png");
+        assert_eq!(
+            cache_control(&wireframe, &wireframe.key, &unsigned()).unwrap(),
+            PUBLIC_CACHE_CONTROL
+        );
+    }
+
+    #[test]
+    fn private_keys_need_a_signed_link() {
+        // whatever kind the record claims, a key under the private prefix stays private
+        for kind in &["deliverable", "wireframe"] {
+            let deliverable = asset(kind, "private/1/contract.pdf");
+            let key = deliverable.key.clone();
+            assert!(cache_control(&deliverable, &key, &unsigned()).is_err());
+            assert!(cache_control(&deliverable, &key, &signed("private/2/other.pdf", 60)).is_err());
+            assert!(cache_control(&deliverable, &key, &signed(&key, -60)).is_err());
+            assert!(cache_control(&deliverable, &key, &signed(&key, 60))
+                .unwrap()
+                .starts_with("private, max-age="));
+        }
+    }
+
+    #[test]
+    fn private_renditions_need_the_link_of_their_original() {
+        let deliverable = asset("deliverable", "private/1/contract.png");
+        let thumbnail = "private/1/thumbnail.png";
+        assert!(cache_control(&deliverable, thumbnail, &signed(thumbnail, 60)).is_err());
+        assert!(cache_control(&deliverable, thumbnail, &signed(&deliverable.key, 60)).is_ok());
+    }
+}
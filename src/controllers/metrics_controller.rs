@@ -0,0 +1,40 @@
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::metrics::{refresh_business_gauges, render};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse};
+use std::env;
+
+/// Prometheus scrape target. Open unless `METRICS_TOKEN` is set, then scrapers send it
+/// as a bearer token.
+#[get("/metrics")]
+async fn metrics(
+    app_state: web::Data<crate::AppState>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    if let Ok(token) = env::var("METRICS_TOKEN") {
+        let presented = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !same_token(presented, &token) {
+            return Err(ContentBuilderCustomResponseError::Unauthorized);
+        }
+    }
+    refresh_business_gauges(&app_state.container).await;
+    let body =
+        render().map_err(|_prometheus_error| ContentBuilderCustomResponseError::InternalError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+/// Compares in constant time so the token cannot be guessed byte by byte.
+fn same_token(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (left, right)| difference | (left ^ right))
+            == 0
+}
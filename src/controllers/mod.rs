@@ -1,28 +1,55 @@
+pub mod api_keys_controller;
+pub mod assets_controller;
 pub mod categories_controller;
 pub mod features_controller;
+pub mod health_controller;
+pub mod media_controller;
+pub mod metrics_controller;
 pub mod projects_controller;
 pub mod prototypes_controller;
 pub mod schema;
 pub mod templates_controller;
+pub mod trash_controller;
+
+pub use api_keys_controller::{
+    create_api_key, get_all_api_keys, revoke_api_key, rotate_api_key,
+};
+
+pub use assets_controller::{collect_orphaned_assets, get_asset_by_id, upload_asset};
+
+pub use health_controller::{health, ready, version};
+
+pub use media_controller::get_media;
+
+pub use metrics_controller::metrics;
 
 pub use categories_controller::{
     create_category, delete_category, get_all_categories, get_category_by_id, update_category,
+    upload_category_image,
 };
 
 pub use features_controller::{
     add_feature_wireframe, create_feature, delete_feature, delete_feature_wireframe,
-    get_all_features, get_feature_by_id, update_feature,
+    get_all_features, get_feature_by_id, update_feature, upload_feature_image,
+    upload_feature_wireframes,
 };
 
 pub use projects_controller::{
     add_design_project, add_full_build_project, add_mvp_project, add_project, add_proposal_project,
-    change_project_state, generate_project_specification, get_all_project_by_client_id,
-    get_all_projects, get_project_by_id, update_project,
+    change_project_state, delete_project, generate_project_specification,
+    get_all_project_by_client_id, get_all_projects, get_project_by_id, update_project,
+    upload_project_deliverable,
 };
 
-pub use prototypes_controller::{add_prototype, get_prototype_by_template_id, update_prototype};
+pub use prototypes_controller::{
+    add_prototype, auto_layout_prototype, delete_prototype, diff_prototype_revisions,
+    export_prototype, get_prototype_by_template_id, import_prototype, get_prototype_revisions, restore_prototype_revision,
+    update_prototype, update_prototype_hotspots, update_prototype_layout,
+};
 
 pub use templates_controller::{
     add_template_specification, create_template, delete_template, get_all_templates,
     get_template_by_id, get_templates_by_categories_id, update_template, update_template_feature,
 };
+
+pub use trash_controller::{get_trash, purge_trash, restore_from_trash};
@@ -4,7 +4,12 @@ use super::schema::{
     ProjectRequestModel, ProjectResponseModel, ProjectState, ProjectUpdateModel, SerlizedId,
     TransactionResult,ProjectFile,
 };
+use crate::middleware::auth_middelware::{Identity, PROJECTS_READ, PROJECTS_WRITE};
 use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::assets::{asset_file, store_upload, take_file, text_field, AssetKind};
+use crate::services::authorization::authorize_project;
+use crate::services::directory::require_active_client;
+use crate::services::validation::{DESIGN_POLICY, MVP_POLICY, SPECIFICATION_POLICY};
 use actix_web::{
     delete, get, post, put,
     web::{self, Json},
@@ -13,13 +18,15 @@ use actix_web::{
 use awmp::Parts;
 use bson::{oid::ObjectId, Document};
 use futures::stream::StreamExt;
-use std::path::PathBuf;
 
 #[post("project/add")]
 async fn add_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<ProjectRequestModel>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require_client(&project_data.client_id, PROJECTS_WRITE)?;
+    require_active_client(app_state.directory.as_ref(), &project_data.client_id).await?;
     match app_state
         .container
         .project
@@ -85,8 +92,10 @@ async fn add_project(
 #[post("project/get")]
 async fn get_project_by_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    authorize_project(&app_state.container, &identity, &project_data.id, PROJECTS_READ).await?;
     match app_state
         .container
         .project
@@ -121,8 +130,11 @@ async fn get_project_by_id(
 #[post("project/user/get")]
 async fn get_all_project_by_client_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     client_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require_client(&client_data.id, PROJECTS_READ)?;
+    require_active_client(app_state.directory.as_ref(), &client_data.id).await?;
     match app_state
         .container
         .project
@@ -154,8 +166,10 @@ async fn get_all_project_by_client_id(
 #[delete("project/state")]
 async fn change_project_state(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<ProjectState>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
     match app_state
         .container
         .project
@@ -212,10 +226,31 @@ async fn change_project_state(
     }
 }
 
+#[delete("project/delete")]
+async fn delete_project(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    project_data: Json<SerlizedId>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
+    ObjectId::with_string(&project_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state.container.project.delete_one(&project_data.id).await {
+        Ok(Some(document)) => match document.get_object_id("_id") {
+            Ok(id) => Ok(HttpResponse::Ok().json(SerlizedId { id: id.to_string() })),
+            Err(_bson_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
 #[get("project/all")]
 async fn get_all_projects(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_READ)?;
     match app_state.container.project.find_all().await {
         Ok(cursor) => {
             let projects: Vec<ProjectResponseModel> = cursor
@@ -325,8 +360,10 @@ async fn get_all_projects(
 #[put("project/update")]
 async fn update_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<ProjectUpdateModel>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    authorize_project(&app_state.container, &identity, &project_data.id, PROJECTS_WRITE).await?;
     match app_state
         .container
         .project
@@ -379,16 +416,20 @@ async fn update_project(
 #[post("project/specification/generate")]
 async fn generate_project_specification(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    authorize_project(&app_state.container, &identity, &project_data.id, PROJECTS_READ).await?;
     Ok(HttpResponse::Ok().body("ok"))
 }
 
 #[put("project/full_build/add")]
 async fn add_full_build_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<ProjectFullBuild>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
     match app_state
         .container
         .project
@@ -448,8 +489,10 @@ async fn add_full_build_project(
 #[put("project/proposal/add")]
 async fn add_proposal_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     project_data: Json<ProjectProposal>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
     match app_state
         .container
         .project
@@ -509,8 +552,10 @@ async fn add_proposal_project(
 #[put("project/mvp/add")]
 async fn add_mvp_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     mvp: Json<ProjectFile>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
 
     match app_state
         .container
@@ -520,6 +565,8 @@ async fn add_mvp_project(
             File {
                 name: mvp.name.clone(),
                 src: mvp.src.clone(),
+                thumbnail: None,
+                medium: None,
             },
         )
         .await
@@ -568,8 +615,10 @@ async fn add_mvp_project(
 #[put("project/design/add")]
 async fn add_design_project(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     design:Json<ProjectFile>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
   
 
     match app_state
@@ -580,6 +629,8 @@ async fn add_design_project(
             File {
                 name: design.name.clone(),
                 src:design.src.clone(),
+                thumbnail: None,
+                medium: None,
             },
         )
         .await
@@ -624,3 +675,73 @@ async fn add_design_project(
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
 }
+
+#[put("project/deliverable/upload")]
+async fn upload_project_deliverable(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    parts: Parts,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(PROJECTS_WRITE)?;
+    let project_id = text_field(&parts, "id")?;
+    let deliverable = text_field(&parts, "kind")?;
+    let owner = ObjectId::with_string(&project_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let policy = match deliverable.as_str() {
+        "specification" => &SPECIFICATION_POLICY,
+        "mvp" => &MVP_POLICY,
+        "design" => &DESIGN_POLICY,
+        _ => return Err(ContentBuilderCustomResponseError::BadClientData),
+    };
+    match app_state.container.project.find_one_by_id(&project_id).await {
+        Ok(Some(_document)) => {}
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    }
+    let asset = store_upload(
+        app_state.storage.as_ref(),
+        &app_state.container.asset,
+        AssetKind::Deliverable,
+        policy,
+        take_file(parts, "file")?,
+        Some(owner),
+    )
+    .await?;
+    let file = asset_file(&asset);
+    let project = app_state.container.project.clone();
+    let updated = match deliverable.as_str() {
+        "specification" => project.update_specification_file(&project_id, file).await,
+        "mvp" => project.update_mvp(&project_id, file).await,
+        _ => project.update_design(&project_id, file).await,
+    };
+    match updated {
+        Ok(Some(_document)) => {
+            match app_state
+                .container
+                .project
+                .refactor_one_by_id(&project_id)
+                .await
+            {
+                Ok(cursor) => {
+                    let projects: Vec<ProjectResponseModel> = cursor
+                        .filter_map(|doc| async move {
+                            doc.ok()
+                                .and_then(|doc| {
+                                    bson::from_document::<ProjectDeserializeModel>(doc).ok()
+                                })
+                                .map(ProjectResponseModel::build_project)
+                        })
+                        .collect()
+                        .await;
+                    match projects.last() {
+                        Some(project) => Ok(HttpResponse::Ok().json(project)),
+                        None => Err(ContentBuilderCustomResponseError::NotFound),
+                    }
+                }
+                Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+            }
+        }
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
@@ -1,24 +1,44 @@
 extern crate jsonwebtoken as jwt;
+use crate::middleware::auth_middelware::{Identity, CATALOG_READ, CATALOG_WRITE, PROJECTS_READ};
 use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::models::indexes::is_duplicate_key;
+use crate::services::{
+    authorization::authorize_project,
+    export::{build_bundle, fetch_wireframes},
+    hotspots::validate_hotspots,
+    import::{merge_nodes, plan_import},
+    layout::layered_layout,
+    revisions::diff_revisions,
+};
 use actix_web::{
-    post, put,
+    delete, patch, post, put,
     web::{self, Json},
     HttpResponse,
 };
-use bson::oid::ObjectId;
+use bson::{oid::ObjectId, Bson, Document};
+use chrono::Utc;
 use futures::stream::StreamExt;
+use std::collections::HashMap;
 
 use super::schema::{
-    Connections, ProtoType, ProtoTypeObject, ProtoTypeRefactorDeserializeModel, ProtoTypeRequest,
-    ProtoTypeResponseModel, SerlizedId,
+    AutoLayoutRequest, Connections, DesignImportRequest, DesignImportResponseModel,
+    FeatureDeserializeModel, FileWithId, Hotspot, Project, ProtoType,
+    ProtoTypeDeserializeModel, ProtoTypeDiffRequest, ProtoTypeExportRequest,
+    ProtoTypeHotspotsRequest,
+    ProtoTypeLayoutRequest, ProtoTypeObject, ProtoTypeRefactorDeserializeModel, ProtoTypeRequest,
+    ProtoTypeResponseModel,
+    ProtoTypeRevision, ProtoTypeRevisionDeserializeModel, ProtoTypeRevisionRequest,
+    ProtoTypeRevisionResponseModel, SerlizedId, TemplateDeserializeModel,
 };
 
 #[post("prototype/add")]
 async fn add_prototype(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     prototype_data: Json<ProtoTypeRequest>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
-    println!("{:?}", prototype_data);
+    identity.require(CATALOG_WRITE)?;
+    tracing::debug!(prototype = ?prototype_data, "adding prototype");
     match app_state
         .container
         .prototype
@@ -38,13 +58,23 @@ async fn add_prototype(
                             releations: connections.releations,
                         })
                         .collect::<Vec<Connections>>(),
+                    layout: prototype_object.layout,
+                    hotspots: vec![],
                 })
                 .collect::<Vec<ProtoTypeObject>>(),
+            viewport: prototype_data.viewport,
+            lanes: prototype_data.lanes.clone(),
         })
         .await
     {
         Ok(id) => match id.inserted_id.as_object_id() {
             Some(_id) => {
+                record_revision(
+                    &app_state,
+                    &prototype_data.template_id,
+                    prototype_data.message.clone(),
+                )
+                .await;
                 match app_state
                     .container
                     .prototype
@@ -78,6 +108,10 @@ async fn add_prototype(
             }
             None => Err(ContentBuilderCustomResponseError::InternalError),
         },
+        // the template already has a prototype, it is updated rather than added again
+        Err(mongodb_error) if is_duplicate_key(&mongodb_error) => {
+            Err(ContentBuilderCustomResponseError::AlreadyExists)
+        }
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
 }
@@ -85,8 +119,10 @@ async fn add_prototype(
 #[post("prototype/get")]
 async fn get_prototype_by_template_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     template_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state
         .container
         .prototype
@@ -104,7 +140,6 @@ async fn get_prototype_by_template_id(
                             Err(_mongodb_error) => bson::Document::new(),
                         })
                         .ok();
-                    // println!("Prototype Dezrlized: {:?}", prototype);
                     ProtoTypeResponseModel::build_prototype(prototype.unwrap())
                 })
                 .collect()
@@ -122,9 +157,30 @@ async fn get_prototype_by_template_id(
 #[put("prototype/update")]
 async fn update_prototype(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     prototype_data: Json<ProtoTypeRequest>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
-    // println!("{:?}", prototype_data);
+    identity.require(CATALOG_WRITE)?;
+    ObjectId::with_string(&prototype_data.template_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let stored_nodes: HashMap<String, ProtoTypeObject> = match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(&prototype_data.template_id)
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<ProtoTypeDeserializeModel>(document) {
+            Ok(prototype) => prototype
+                .prototype
+                .into_iter()
+                .map(|node| (node.feature_id.to_string(), node))
+                .collect(),
+            Err(_bson_de_error) => HashMap::new(),
+        },
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    record_baseline_revision(&app_state, &prototype_data.template_id).await;
     match app_state
         .container
         .prototype
@@ -136,24 +192,56 @@ async fn update_prototype(
                     .prototype
                     .clone()
                     .into_iter()
-                    .map(|prototype_object| ProtoTypeObject {
-                        feature_id: ObjectId::with_string(&prototype_object.feature_id).unwrap(),
-                        connections: prototype_object
+                    .map(|prototype_object| {
+                        let stored = stored_nodes.get(&prototype_object.feature_id);
+                        let connections = prototype_object
                             .connections
                             .into_iter()
                             .map(|connections| Connections {
                                 to: ObjectId::with_string(&connections.to).unwrap(),
                                 releations: connections.releations,
                             })
-                            .collect::<Vec<Connections>>(),
+                            .collect::<Vec<Connections>>();
+                        // hotspots survive a save only while their connection still exists
+                        let hotspots = stored
+                            .map(|node| {
+                                node.hotspots
+                                    .iter()
+                                    .filter(|hotspot| {
+                                        connections
+                                            .iter()
+                                            .any(|connection| connection.to == hotspot.to)
+                                    })
+                                    .cloned()
+                                    .collect::<Vec<Hotspot>>()
+                            })
+                            .unwrap_or_default();
+                        ProtoTypeObject {
+                            layout: match prototype_object.layout {
+                                Some(layout) => Some(layout),
+                                None => stored.and_then(|node| node.layout.clone()),
+                            },
+                            feature_id: ObjectId::with_string(&prototype_object.feature_id)
+                                .unwrap(),
+                            connections,
+                            hotspots,
+                        }
                     })
                     .collect::<Vec<ProtoTypeObject>>(),
+                viewport: prototype_data.viewport,
+                lanes: prototype_data.lanes.clone(),
             },
         )
         .await
     {
         Ok(document) => match document {
             Some(_doc) => {
+                record_revision(
+                    &app_state,
+                    &prototype_data.template_id,
+                    prototype_data.message.clone(),
+                )
+                .await;
                 match app_state
                     .container
                     .prototype
@@ -172,7 +260,6 @@ async fn update_prototype(
                                     Err(_mongodb_error) => bson::Document::new(),
                                 })
                                 .ok();
-                                // println!("Prototype Dezrlized: {:?}", prototype);
                                 ProtoTypeResponseModel::build_prototype(prototype.unwrap())
                             })
                             .collect()
@@ -191,3 +278,800 @@ async fn update_prototype(
         Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
     }
 }
+
+#[patch("prototype/layout")]
+async fn update_prototype_layout(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    layout_data: Json<ProtoTypeLayoutRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let layout_data = layout_data.into_inner();
+    ObjectId::with_string(&layout_data.template_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let prototype = match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(&layout_data.template_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<ProtoTypeDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let mut nodes: Vec<(ObjectId, Bson)> = Vec::new();
+    for node in layout_data.nodes {
+        let feature_id = ObjectId::with_string(&node.feature_id)
+            .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+        // two layouts for one node would need conflicting array filters
+        if nodes.iter().any(|(listed, _layout)| *listed == feature_id) {
+            return Err(ContentBuilderCustomResponseError::BadClientData);
+        }
+        // the array filter would match nothing and drop the layout without a word
+        if !prototype
+            .prototype
+            .iter()
+            .any(|stored| stored.feature_id == feature_id)
+        {
+            return Err(ContentBuilderCustomResponseError::NotFound);
+        }
+        let layout = bson::to_bson(&node.layout)
+            .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::BadClientData)?;
+        nodes.push((feature_id, layout));
+    }
+    let viewport = match layout_data.viewport {
+        Some(viewport) => Some(
+            bson::to_bson(&viewport)
+                .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::BadClientData)?,
+        ),
+        None => None,
+    };
+    let lanes = match layout_data.lanes {
+        Some(lanes) => Some(
+            bson::to_bson(&lanes)
+                .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::BadClientData)?,
+        ),
+        None => None,
+    };
+    match app_state
+        .container
+        .prototype
+        .update_layout(&layout_data.template_id, nodes, viewport, lanes)
+        .await
+    {
+        Ok(Some(_document)) => refactored_prototype(&app_state, &layout_data.template_id).await,
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("prototype/layout/auto")]
+async fn auto_layout_prototype(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    layout_data: Json<AutoLayoutRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    ObjectId::with_string(&layout_data.template_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let prototype = match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(&layout_data.template_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<ProtoTypeDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let nodes = layered_layout(&prototype.prototype, layout_data.overwrite)
+        .into_iter()
+        .zip(prototype.prototype.iter())
+        .filter(|(_, node)| layout_data.overwrite || node.layout.is_none())
+        .map(|(layout, node)| {
+            bson::to_bson(&layout)
+                .map(|layout| (node.feature_id.clone(), layout))
+                .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::InternalError)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if nodes.is_empty() {
+        return refactored_prototype(&app_state, &layout_data.template_id).await;
+    }
+    record_baseline_revision(&app_state, &layout_data.template_id).await;
+    match app_state
+        .container
+        .prototype
+        .update_layout(&layout_data.template_id, nodes, None, None)
+        .await
+    {
+        Ok(Some(_document)) => {
+            record_revision(
+                &app_state,
+                &layout_data.template_id,
+                Some("automatic layout".to_string()),
+            )
+            .await;
+            refactored_prototype(&app_state, &layout_data.template_id).await
+        }
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[put("prototype/hotspots")]
+async fn update_prototype_hotspots(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    hotspot_data: Json<ProtoTypeHotspotsRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let hotspot_data = hotspot_data.into_inner();
+    for id in [&hotspot_data.template_id, &hotspot_data.feature_id] {
+        ObjectId::with_string(id)
+            .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    }
+    let prototype = match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(&hotspot_data.template_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<ProtoTypeDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let node = prototype
+        .prototype
+        .iter()
+        .find(|node| node.feature_id.to_string() == hotspot_data.feature_id)
+        .ok_or(ContentBuilderCustomResponseError::NotFound)?;
+    let feature = match app_state
+        .container
+        .feature
+        .find_one_by_id(&hotspot_data.feature_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<FeatureDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let wireframes = feature.wireframes.unwrap_or_default();
+
+    let hotspots = validate_hotspots(hotspot_data.hotspots, node, &wireframes)?;
+    let hotspots = bson::to_bson(&hotspots)
+        .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::InternalError)?;
+    record_baseline_revision(&app_state, &hotspot_data.template_id).await;
+    match app_state
+        .container
+        .prototype
+        .update_hotspots(&hotspot_data.template_id, &hotspot_data.feature_id, hotspots)
+        .await
+    {
+        Ok(Some(_document)) => {
+            record_revision(
+                &app_state,
+                &hotspot_data.template_id,
+                Some("hotspots updated".to_string()),
+            )
+            .await;
+            refactored_prototype(&app_state, &hotspot_data.template_id).await
+        }
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("prototype/export")]
+async fn export_prototype(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    export_data: Json<ProtoTypeExportRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
+    let (title, template_id, project_features) = match (
+        &export_data.project_id,
+        &export_data.template_id,
+    ) {
+        (Some(project_id), _) => {
+            authorize_project(&app_state.container, &identity, project_id, PROJECTS_READ).await?;
+            let project = match app_state.container.project.find_one_by_id(project_id).await {
+                Ok(Some(document)) => bson::from_document::<Project>(document)
+                    .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+                Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+                Err(_mongodb_error) => {
+                    return Err(ContentBuilderCustomResponseError::InternalError)
+                }
+            };
+            (
+                project.name,
+                project.template.to_string(),
+                Some(project.features),
+            )
+        }
+        (None, Some(template_id)) => {
+            ObjectId::with_string(template_id)
+                .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+            let template = match app_state
+                .container
+                .template
+                .find_one_by_id(template_id)
+                .await
+            {
+                Ok(Some(document)) => bson::from_document::<TemplateDeserializeModel>(document)
+                    .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+                Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+                Err(_mongodb_error) => {
+                    return Err(ContentBuilderCustomResponseError::InternalError)
+                }
+            };
+            (template.name, template_id.clone(), None)
+        }
+        (None, None) => return Err(ContentBuilderCustomResponseError::BadClientData),
+    };
+
+    let prototype = match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(&template_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<ProtoTypeDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    // a project only clicks through the features it actually selected
+    let nodes: Vec<ProtoTypeObject> = prototype
+        .prototype
+        .into_iter()
+        .filter(|node| match &project_features {
+            Some(features) if !features.is_empty() => features.contains(&node.feature_id),
+            _ => true,
+        })
+        .collect();
+
+    let features: HashMap<String, FeatureDeserializeModel> = match app_state
+        .container
+        .feature
+        .find_by_ids(nodes.iter().map(|node| node.feature_id.clone()).collect())
+        .await
+    {
+        Ok(cursor) => {
+            cursor
+                .filter_map(|doc| async move {
+                    doc.ok()
+                        .and_then(|doc| bson::from_document::<FeatureDeserializeModel>(doc).ok())
+                        .map(|feature| (feature._id.to_string(), feature))
+                })
+                .collect()
+                .await
+        }
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+
+    let assets = fetch_wireframes(app_state.storage.as_ref(), &features).await;
+    match build_bundle(&title, &nodes, &features, &assets) {
+        Ok(bundle) => Ok(HttpResponse::Ok()
+            .content_type("application/zip")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"prototype-{}.zip\"", template_id),
+            )
+            .body(bundle)),
+        Err(_zip_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("prototype/import")]
+async fn import_prototype(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    import_data: Json<DesignImportRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let import_data = import_data.into_inner();
+    ObjectId::with_string(&import_data.template_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let template = match app_state
+        .container
+        .template
+        .find_one_by_id(&import_data.template_id)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<TemplateDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let mut feature_ids = template.features.unwrap_or_default();
+    for feature_id in import_data.mapping.values() {
+        let feature_id = ObjectId::with_string(feature_id)
+            .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+        if !feature_ids.contains(&feature_id) {
+            feature_ids.push(feature_id);
+        }
+    }
+    let features: Vec<FeatureDeserializeModel> =
+        match app_state.container.feature.find_by_ids(feature_ids).await {
+            Ok(cursor) => {
+                cursor
+                    .filter_map(|doc| async move {
+                        doc.ok().and_then(|doc| {
+                            bson::from_document::<FeatureDeserializeModel>(doc).ok()
+                        })
+                    })
+                    .collect()
+                    .await
+            }
+            Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+        };
+
+    let plan = plan_import(&import_data.export, &import_data.mapping, &features)
+        .ok_or(ContentBuilderCustomResponseError::BadClientData)?;
+    let imported_wireframes: Vec<ObjectId> = plan
+        .wireframes
+        .iter()
+        .map(|(_, wireframe)| wireframe._id.clone())
+        .collect();
+
+    // everything is read and merged before the first write
+    let prototype = if plan.nodes.is_empty() {
+        None
+    } else {
+        let existing = match app_state
+            .container
+            .prototype
+            .find_one_by_template_id(&import_data.template_id)
+            .await
+        {
+            Ok(Some(document)) => Some(
+                bson::from_document::<ProtoTypeDeserializeModel>(document)
+                    .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+            ),
+            Ok(None) => None,
+            Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+        };
+        let (viewport, lanes, existing_nodes) = match existing {
+            Some(prototype) => (prototype.viewport, prototype.lanes, prototype.prototype),
+            None => (None, None, vec![]),
+        };
+        let (nodes, connections_created) =
+            merge_nodes(existing_nodes, plan.nodes, &imported_wireframes);
+        Some((
+            ProtoType {
+                template_id: template._id.clone(),
+                prototype: nodes,
+                viewport,
+                lanes,
+            },
+            connections_created,
+        ))
+    };
+
+    // each feature's wireframes as they are, and as the import leaves them
+    let mut rewrites: Vec<(ObjectId, Vec<FileWithId>, Vec<FileWithId>)> = Vec::new();
+    let mut wireframes_created = 0;
+    let mut wireframes_updated = 0;
+    for (feature_id, wireframe) in plan.wireframes {
+        let index = match rewrites.iter().position(|(id, _, _)| id == &feature_id) {
+            Some(index) => index,
+            None => {
+                let current = features
+                    .iter()
+                    .find(|feature| feature._id == feature_id)
+                    .and_then(|feature| feature.wireframes.clone())
+                    .unwrap_or_default();
+                rewrites.push((feature_id, current.clone(), current));
+                rewrites.len() - 1
+            }
+        };
+        let imported = &mut rewrites[index].2;
+        match imported
+            .iter_mut()
+            .find(|current| current._id == wireframe._id)
+        {
+            Some(current) => {
+                *current = wireframe;
+                wireframes_updated += 1;
+            }
+            None => {
+                imported.push(wireframe);
+                wireframes_created += 1;
+            }
+        }
+    }
+    let mut rewritten: Vec<(ObjectId, Vec<FileWithId>)> = Vec::new();
+    for (feature_id, previous, imported) in rewrites {
+        if let Err(error) = write_wireframes(&app_state, &feature_id, &imported).await {
+            restore_wireframes(&app_state, rewritten).await;
+            return Err(error);
+        }
+        rewritten.push((feature_id, previous));
+    }
+
+    let (prototype, connections_created) = match prototype {
+        Some(prototype) => prototype,
+        None => {
+            return Ok(HttpResponse::Ok().json(DesignImportResponseModel {
+                prototype: None,
+                wireframes_created,
+                wireframes_updated,
+                connections_created: 0,
+                unmapped_frames: plan.unmapped_frames,
+                skipped_links: plan.skipped_links,
+            }))
+        }
+    };
+    record_baseline_revision(&app_state, &import_data.template_id).await;
+    match app_state
+        .container
+        .prototype
+        .replace_by_template_id(&import_data.template_id, prototype)
+        .await
+    {
+        Ok(Some(_document)) => {
+            record_revision(
+                &app_state,
+                &import_data.template_id,
+                Some(match import_data.export.name {
+                    Some(name) => format!("imported from design file {}", name),
+                    None => "imported from design file".to_string(),
+                }),
+            )
+            .await;
+            Ok(HttpResponse::Ok().json(DesignImportResponseModel {
+                prototype: Some(
+                    find_refactored_prototype(&app_state, &import_data.template_id).await?,
+                ),
+                wireframes_created,
+                wireframes_updated,
+                connections_created,
+                unmapped_frames: plan.unmapped_frames,
+                skipped_links: plan.skipped_links,
+            }))
+        }
+        Ok(None) | Err(_) => {
+            restore_wireframes(&app_state, rewritten).await;
+            Err(ContentBuilderCustomResponseError::InternalError)
+        }
+    }
+}
+
+async fn write_wireframes(
+    app_state: &web::Data<crate::AppState>,
+    feature_id: &ObjectId,
+    wireframes: &[FileWithId],
+) -> Result<(), ContentBuilderCustomResponseError> {
+    let documents = wireframes
+        .iter()
+        .map(bson::to_document)
+        .collect::<Result<Vec<Document>, _>>()
+        .map_err(|_bson_ser_error| ContentBuilderCustomResponseError::InternalError)?;
+    match app_state
+        .container
+        .feature
+        .update_wireframes(&feature_id.to_string(), documents)
+        .await
+    {
+        Ok(_document) => Ok(()),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Puts back the wireframes a failed import already rewrote, so it leaves no orphans.
+async fn restore_wireframes(
+    app_state: &web::Data<crate::AppState>,
+    rewritten: Vec<(ObjectId, Vec<FileWithId>)>,
+) {
+    for (feature_id, previous) in rewritten.into_iter().rev() {
+        if write_wireframes(app_state, &feature_id, &previous)
+            .await
+            .is_err()
+        {
+            tracing::error!(
+                feature_id = %feature_id,
+                "wireframes of a failed import could not be restored"
+            );
+        }
+    }
+}
+
+#[delete("prototype/delete")]
+async fn delete_prototype(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    template_data: Json<SerlizedId>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    ObjectId::with_string(&template_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state
+        .container
+        .prototype
+        .delete_by_template_id(&template_data.id)
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<ProtoTypeDeserializeModel>(document) {
+            Ok(prototype) => Ok(HttpResponse::Ok().json(SerlizedId {
+                id: prototype._id.to_string(),
+            })),
+            Err(_bson_de_error) => Err(ContentBuilderCustomResponseError::InternalError),
+        },
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("prototype/revisions")]
+async fn get_prototype_revisions(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    template_data: Json<SerlizedId>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
+    ObjectId::with_string(&template_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state
+        .container
+        .prototype_revision
+        .find_by_template_id(&template_data.id)
+        .await
+    {
+        Ok(cursor) => {
+            let revisions: Vec<ProtoTypeRevisionResponseModel> = cursor
+                .filter_map(|doc| async move {
+                    doc.ok()
+                        .and_then(|doc| {
+                            bson::from_document::<ProtoTypeRevisionDeserializeModel>(doc).ok()
+                        })
+                        .map(ProtoTypeRevisionResponseModel::build_revision)
+                })
+                .collect()
+                .await;
+            Ok(HttpResponse::Ok().json(revisions))
+        }
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+#[post("prototype/revisions/diff")]
+async fn diff_prototype_revisions(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    diff_data: Json<ProtoTypeDiffRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
+    let from = find_revision(&app_state, &diff_data.template_id, diff_data.from).await?;
+    let to = find_revision(&app_state, &diff_data.template_id, diff_data.to).await?;
+    Ok(HttpResponse::Ok().json(diff_revisions(&from, &to)))
+}
+
+#[put("prototype/revisions/restore")]
+async fn restore_prototype_revision(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    revision_data: Json<ProtoTypeRevisionRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let revision = find_revision(
+        &app_state,
+        &revision_data.template_id,
+        revision_data.revision,
+    )
+    .await?;
+    match app_state
+        .container
+        .prototype
+        .replace_by_template_id(&revision_data.template_id, revision.snapshot)
+        .await
+    {
+        Ok(Some(_document)) => {
+            record_revision(
+                &app_state,
+                &revision_data.template_id,
+                Some(format!("restored revision {}", revision.revision)),
+            )
+            .await;
+            refactored_prototype(&app_state, &revision_data.template_id).await
+        }
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+async fn find_revision(
+    app_state: &web::Data<crate::AppState>,
+    template_id: &str,
+    revision: i64,
+) -> Result<ProtoTypeRevisionDeserializeModel, ContentBuilderCustomResponseError> {
+    ObjectId::with_string(template_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match app_state
+        .container
+        .prototype_revision
+        .find_one_by_revision(template_id, revision)
+        .await
+    {
+        Ok(Some(document)) => bson::from_document::<ProtoTypeRevisionDeserializeModel>(document)
+            .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError),
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Attempts at taking the next revision number while other saves keep taking it first.
+const REVISION_ATTEMPTS: usize = 5;
+
+/// Snapshots the stored prototype as the next immutable revision. Layout patches
+/// (node drags) are deliberately not recorded, only whole saves are. The prototype is
+/// already saved when this runs, a revision that cannot be recorded is logged rather
+/// than failing the save.
+async fn record_revision(
+    app_state: &web::Data<crate::AppState>,
+    template_id: &str,
+    message: Option<String>,
+) {
+    let snapshot = match stored_snapshot(app_state, template_id).await {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+    for _attempt in 0..REVISION_ATTEMPTS {
+        let latest = match app_state
+            .container
+            .prototype_revision
+            .find_latest(template_id)
+            .await
+        {
+            Ok(Some(document)) => document.get_i64("revision").unwrap_or(0),
+            Ok(None) => 0,
+            Err(mongodb_error) => {
+                tracing::warn!(template_id, "revision not recorded: {}", mongodb_error);
+                return;
+            }
+        };
+        match insert_revision(app_state, &snapshot, latest + 1, message.clone()).await {
+            Ok(()) => return,
+            // a concurrent save took this number, the unique index refused the second one
+            Err(mongodb_error) if is_duplicate_key(&mongodb_error) => continue,
+            Err(mongodb_error) => {
+                tracing::warn!(template_id, "revision not recorded: {}", mongodb_error);
+                return;
+            }
+        }
+    }
+    tracing::warn!(
+        template_id,
+        "revision not recorded, {} concurrent saves took every number tried",
+        REVISION_ATTEMPTS
+    );
+}
+
+/// Prototypes saved before revisions were recorded have none: their stored state is
+/// kept as revision 1 before the first recorded change overwrites it.
+async fn record_baseline_revision(app_state: &web::Data<crate::AppState>, template_id: &str) {
+    match app_state
+        .container
+        .prototype_revision
+        .find_latest(template_id)
+        .await
+    {
+        Ok(None) => {}
+        Ok(Some(_document)) => return,
+        Err(mongodb_error) => {
+            tracing::warn!(template_id, "baseline revision not recorded: {}", mongodb_error);
+            return;
+        }
+    }
+    let snapshot = match stored_snapshot(app_state, template_id).await {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+    match insert_revision(
+        app_state,
+        &snapshot,
+        1,
+        Some("before the first recorded change".to_string()),
+    )
+    .await
+    {
+        // a duplicate is a concurrent save that recorded it first
+        Ok(()) => {}
+        Err(mongodb_error) if is_duplicate_key(&mongodb_error) => {}
+        Err(mongodb_error) => {
+            tracing::warn!(template_id, "baseline revision not recorded: {}", mongodb_error)
+        }
+    }
+}
+
+async fn stored_snapshot(
+    app_state: &web::Data<crate::AppState>,
+    template_id: &str,
+) -> Option<ProtoType> {
+    match app_state
+        .container
+        .prototype
+        .find_one_by_template_id(template_id)
+        .await
+    {
+        Ok(Some(document)) => match bson::from_document::<ProtoTypeDeserializeModel>(document) {
+            Ok(snapshot) => Some(ProtoType {
+                template_id: snapshot.template_id,
+                prototype: snapshot.prototype,
+                viewport: snapshot.viewport,
+                lanes: snapshot.lanes,
+            }),
+            Err(bson_de_error) => {
+                tracing::warn!(template_id, "revision not recorded: {}", bson_de_error);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(mongodb_error) => {
+            tracing::warn!(template_id, "revision not recorded: {}", mongodb_error);
+            None
+        }
+    }
+}
+
+async fn insert_revision(
+    app_state: &web::Data<crate::AppState>,
+    snapshot: &ProtoType,
+    revision: i64,
+    message: Option<String>,
+) -> Result<(), mongodb::error::Error> {
+    app_state
+        .container
+        .prototype_revision
+        .insert_one(ProtoTypeRevision {
+            template_id: snapshot.template_id.clone(),
+            revision,
+            created_at: Utc::now().into(),
+            message,
+            snapshot: snapshot.clone(),
+        })
+        .await
+        .map(|_id| ())
+}
+
+async fn refactored_prototype(
+    app_state: &web::Data<crate::AppState>,
+    template_id: &str,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    find_refactored_prototype(app_state, template_id)
+        .await
+        .map(|prototype| HttpResponse::Ok().json(prototype))
+}
+
+async fn find_refactored_prototype(
+    app_state: &web::Data<crate::AppState>,
+    template_id: &str,
+) -> Result<ProtoTypeResponseModel, ContentBuilderCustomResponseError> {
+    match app_state
+        .container
+        .prototype
+        .refactor_one_by_id(template_id)
+        .await
+    {
+        Ok(cursor) => {
+            let prototypes: Vec<ProtoTypeResponseModel> = cursor
+                .filter_map(|doc| async move {
+                    doc.ok()
+                        .and_then(|doc| {
+                            bson::from_document::<ProtoTypeRefactorDeserializeModel>(doc).ok()
+                        })
+                        .map(ProtoTypeResponseModel::build_prototype)
+                })
+                .collect()
+                .await;
+            match prototypes.into_iter().last() {
+                Some(prototype) => Ok(prototype),
+                None => Err(ContentBuilderCustomResponseError::NotFound),
+            }
+        }
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
@@ -1,11 +1,28 @@
+use crate::middleware::auth_middelware::Scope;
+use crate::services::assets::signed_src;
 use bson::oid::ObjectId;
 use futures::stream::{Empty, StreamFuture};
 use serde::{self, Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
     pub name: String,
     pub src: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+}
+
+impl File {
+    /// The file as handed to a client, with a fresh signed link when it is private.
+    pub fn signed(self) -> File {
+        File {
+            src: signed_src(&self.src),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +44,14 @@ pub struct FileWithId {
     pub _id: ObjectId,
     pub name: String,
     pub src: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+    /// Design file frame an imported wireframe came from, importing the frame again
+    /// updates the wireframe instead of adding a copy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +59,249 @@ pub struct FileWithOutOId {
     pub id: String,
     pub name: String,
     pub src: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+//////////////////Asset schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetDeserializeModel {
+    pub _id: ObjectId,
+    pub kind: String,
+    pub key: String,
+    pub name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub owner: Option<ObjectId>,
+    pub created_at: bson::DateTime,
+    #[serde(default)]
+    pub hash: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub medium: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetResponseModel {
+    pub id: String,
+    pub kind: String,
+    pub name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub src: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+}
+
+impl AssetResponseModel {
+    pub fn build_asset(asset: AssetDeserializeModel, file: File) -> AssetResponseModel {
+        AssetResponseModel {
+            id: asset._id.to_string(),
+            kind: asset.kind,
+            name: asset.name,
+            content_type: asset.content_type,
+            size: asset.size,
+            src: file.src,
+            thumbnail: file.thumbnail,
+            medium: file.medium,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetGcRequest {
+    pub dry_run: Option<bool>,
+    pub grace_period_hours: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanedAssetModel {
+    pub id: String,
+    pub kind: String,
+    pub key: String,
+    pub name: String,
+    pub size: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetGcReportModel {
+    pub dry_run: bool,
+    pub grace_period_hours: i64,
+    pub scanned_assets: usize,
+    pub referenced_assets: usize,
+    pub pending_assets: usize,
+    pub orphaned_assets: Vec<OrphanedAssetModel>,
+    pub stray_objects: Vec<String>,
+    pub deleted_assets: usize,
+    pub deleted_objects: usize,
+    pub reclaimed_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaAccessQuery {
+    pub expires: Option<i64>,
+    pub token: Option<String>,
+}
+
+//////////////////Trash schema
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashCollection {
+    Categories,
+    Features,
+    Templates,
+    Projects,
+    Prototypes,
+}
+
+impl TrashCollection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrashCollection::Categories => "categories",
+            TrashCollection::Features => "features",
+            TrashCollection::Templates => "templates",
+            TrashCollection::Projects => "projects",
+            TrashCollection::Prototypes => "prototypes",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashItemModel {
+    pub collection: TrashCollection,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub deleted_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashRestoreRequest {
+    pub collection: TrashCollection,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashPurgeRequest {
+    pub retention_days: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrashPurgeReportModel {
+    pub retention_days: i64,
+    pub categories: i64,
+    pub features: i64,
+    pub templates: i64,
+    pub projects: i64,
+    pub prototypes: i64,
+}
+
+//////////////////Migration schema
+/// Written once a migration has run over every document it matched.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MigrationRecord {
+    pub _id: i32,
+    pub name: String,
+    pub applied_at: bson::DateTime,
+    pub migrated_documents: i64,
+}
+
+//////////////////ApiKey schema
+/// Only the hash of the secret is stored, the key itself is shown once on creation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: bson::DateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<bson::DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotated_from: Option<ObjectId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyDeserializeModel {
+    pub _id: ObjectId,
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: bson::DateTime,
+    #[serde(default)]
+    pub expires_at: Option<bson::DateTime>,
+    #[serde(default)]
+    pub revoked_at: Option<bson::DateTime>,
+    #[serde(default)]
+    pub last_used_at: Option<bson::DateTime>,
+    #[serde(default)]
+    pub rotated_from: Option<ObjectId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponseModel {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotated_from: Option<String>,
+}
+
+impl ApiKeyResponseModel {
+    pub fn build_api_key(api_key: ApiKeyDeserializeModel) -> ApiKeyResponseModel {
+        ApiKeyResponseModel {
+            id: api_key._id.to_string(),
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: api_key.scopes,
+            created_at: api_key.created_at.to_rfc3339(),
+            expires_at: api_key.expires_at.map(|expires_at| expires_at.to_rfc3339()),
+            revoked_at: api_key.revoked_at.map(|revoked_at| revoked_at.to_rfc3339()),
+            last_used_at: api_key
+                .last_used_at
+                .map(|last_used_at| last_used_at.to_rfc3339()),
+            rotated_from: api_key.rotated_from.map(|id| id.to_string()),
+        }
+    }
+}
+
+/// The response to a create or a rotation, the only time `key` is ever returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyCreatedModel {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponseModel,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyCreateRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRotateRequest {
+    pub id: String,
+    /// How long the replaced key keeps working, 24 hours when missing.
+    pub grace_period_hours: Option<i64>,
 }
 
 //////////////////Category schema
@@ -42,6 +310,30 @@ pub struct SerlizedId {
     pub id: String,
 }
 
+/// How a delete treats entities that still point at what is being deleted.
+///
+/// `restrict` refuses with a 409 listing the dependents. `cascade` releases them:
+/// a feature is pulled from every template and project, each affected project gets
+/// a notice, and its prototype nodes, connections and hotspots are removed; a
+/// template takes its prototype with it, revision history is kept; a category takes
+/// its templates with it. Projects are never rewritten to drop their template, so a
+/// template or category still used by a project cannot be deleted under either policy.
+/// Deleted entities go to the trash, restoring one does not undo what a cascade released.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletePolicy {
+    #[default]
+    Restrict,
+    Cascade,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteRequest {
+    pub id: String,
+    #[serde(default)]
+    pub policy: DeletePolicy,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Category {
     pub name: String,
@@ -145,6 +437,9 @@ impl FeatureResponseModel {
                         id: file._id.to_string(),
                         name: file.name,
                         src: file.src,
+                        thumbnail: file.thumbnail,
+                        medium: file.medium,
+                        source: file.source,
                     })
                     .collect()
             }),
@@ -366,36 +661,165 @@ pub struct OverallDescription {
 pub struct ProtoType {
     pub template_id: ObjectId,
     pub prototype: Vec<ProtoTypeObject>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewport: Option<Viewport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lanes: Option<Vec<Lane>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtoTypeRequest {
     pub template_id: String,
     pub prototype: Vec<ProtoTypeRequestObject>,
+    #[serde(default)]
+    pub viewport: Option<Viewport>,
+    #[serde(default)]
+    pub lanes: Option<Vec<Lane>>,
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtoTypeRequestObject {
     pub feature_id: String,
     pub connections: Vec<ConnectionsResponseModel>,
+    #[serde(default)]
+    pub layout: Option<NodeLayout>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtoTypeObject {
     pub feature_id: ObjectId,
     pub connections: Vec<Connections>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<NodeLayout>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hotspots: Vec<Hotspot>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtoTypeRefactorObject {
     pub feature: FeatureDeserializeModel,
     pub connections: Vec<Connections>,
+    #[serde(default)]
+    pub layout: Option<NodeLayout>,
+    #[serde(default)]
+    pub hotspots: Vec<Hotspot>,
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct ProtoTypeObjectResponseModel {
     pub feature: FeatureResponseModel,
     pub connections: Vec<ConnectionsResponseModel>,
+    pub layout: Option<NodeLayout>,
+    pub hotspots: Vec<HotspotResponseModel>,
+}
+
+/// Region of a wireframe expressed as fractions (0 to 1) of the image size, so it
+/// survives the image being displayed at any resolution.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hotspot {
+    pub _id: ObjectId,
+    pub wireframe_id: ObjectId,
+    pub to: ObjectId,
+    pub region: Region,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HotspotResponseModel {
+    pub id: String,
+    pub wireframe_id: String,
+    pub to: String,
+    pub region: Region,
+}
+
+impl HotspotResponseModel {
+    pub fn build_hotspot(hotspot: Hotspot) -> HotspotResponseModel {
+        HotspotResponseModel {
+            id: hotspot._id.to_string(),
+            wireframe_id: hotspot.wireframe_id.to_string(),
+            to: hotspot.to.to_string(),
+            region: hotspot.region,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HotspotRequestObject {
+    pub id: Option<String>,
+    pub wireframe_id: String,
+    pub to: String,
+    pub region: Region,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeHotspotsRequest {
+    pub template_id: String,
+    pub feature_id: String,
+    pub hotspots: Vec<HotspotRequestObject>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Size {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeLayout {
+    pub position: Position,
+    pub size: Option<Size>,
+    pub lane: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lane {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeLayoutRequest {
+    pub feature_id: String,
+    pub layout: NodeLayout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeLayoutRequest {
+    pub template_id: String,
+    #[serde(default)]
+    pub nodes: Vec<NodeLayoutRequest>,
+    pub viewport: Option<Viewport>,
+    pub lanes: Option<Vec<Lane>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoLayoutRequest {
+    pub template_id: String,
+    #[serde(default)]
+    pub overwrite: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -421,6 +845,10 @@ pub struct ProtoTypeDeserializeModel {
     pub _id: ObjectId,
     pub template_id: ObjectId,
     pub prototype: Vec<ProtoTypeObject>,
+    #[serde(default)]
+    pub viewport: Option<Viewport>,
+    #[serde(default)]
+    pub lanes: Option<Vec<Lane>>,
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
@@ -428,6 +856,8 @@ pub struct ProtoTypeResponseModel {
     pub id: String,
     pub template: String,
     pub prototype: Vec<ProtoTypeObjectResponseModel>,
+    pub viewport: Option<Viewport>,
+    pub lanes: Option<Vec<Lane>>,
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
@@ -435,6 +865,10 @@ pub struct ProtoTypeRefactorDeserializeModel {
     pub _id: ObjectId,
     pub template: ObjectId,
     pub prototype: Vec<ProtoTypeRefactorObject>,
+    #[serde(default)]
+    pub viewport: Option<Viewport>,
+    #[serde(default)]
+    pub lanes: Option<Vec<Lane>>,
 }
 
 impl ProtoTypeResponseModel {
@@ -455,12 +889,182 @@ impl ProtoTypeResponseModel {
                             releations: connections.releations,
                         })
                         .collect::<Vec<ConnectionsResponseModel>>(),
+                    layout: prototype_object.layout,
+                    hotspots: prototype_object
+                        .hotspots
+                        .into_iter()
+                        .map(HotspotResponseModel::build_hotspot)
+                        .collect::<Vec<HotspotResponseModel>>(),
                 })
                 .collect::<Vec<ProtoTypeObjectResponseModel>>(),
+            viewport: prototype.viewport,
+            lanes: prototype.lanes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeExportRequest {
+    pub template_id: Option<String>,
+    pub project_id: Option<String>,
+}
+
+////////////////////////design import schema
+/// Design tool export accepted by `prototype/import`:
+///
+/// ```json
+/// {
+///   "file_key": "hY5wWx3kTfLq",
+///   "name": "Onboarding",
+///   "frames": [
+///     { "id": "1:2", "name": "Login", "width": 375, "height": 812,
+///       "image": { "name": "login.png", "src": "https://cdn.example.com/login.png" } }
+///   ],
+///   "links": [
+///     { "from": "1:2", "to": "1:7", "back": false,
+///       "region": { "x": 24, "y": 700, "width": 327, "height": 48 } }
+///   ]
+/// }
+/// ```
+///
+/// Link regions are in frame pixels and become wireframe hotspots when the frame
+/// size is known. `file_key`, the id of the design file in its tool, lets a later
+/// import of the same file replace the wireframes it brought in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesignExport {
+    #[serde(default)]
+    pub file_key: Option<String>,
+    pub name: Option<String>,
+    pub frames: Vec<DesignFrame>,
+    #[serde(default)]
+    pub links: Vec<DesignLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesignFrame {
+    pub id: String,
+    pub name: String,
+    pub image: File,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesignLink {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub back: bool,
+    pub region: Option<Region>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesignImportRequest {
+    pub template_id: String,
+    pub export: DesignExport,
+    /// Explicit frame id to feature id mapping, checked before matching by name.
+    #[serde(default)]
+    pub mapping: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnmappedFrame {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesignImportResponseModel {
+    pub prototype: Option<ProtoTypeResponseModel>,
+    pub wireframes_created: usize,
+    pub wireframes_updated: usize,
+    pub connections_created: usize,
+    pub unmapped_frames: Vec<UnmappedFrame>,
+    pub skipped_links: usize,
+}
+
+////////////////////////prototype revision schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeRevision {
+    pub template_id: ObjectId,
+    pub revision: i64,
+    pub created_at: bson::DateTime,
+    pub message: Option<String>,
+    pub snapshot: ProtoType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeRevisionDeserializeModel {
+    pub _id: ObjectId,
+    pub template_id: ObjectId,
+    pub revision: i64,
+    pub created_at: bson::DateTime,
+    pub message: Option<String>,
+    pub snapshot: ProtoType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeRevisionResponseModel {
+    pub id: String,
+    pub template: String,
+    pub revision: i64,
+    pub created_at: String,
+    pub message: Option<String>,
+    pub nodes: usize,
+    pub connections: usize,
+}
+
+impl ProtoTypeRevisionResponseModel {
+    pub fn build_revision(
+        revision: ProtoTypeRevisionDeserializeModel,
+    ) -> ProtoTypeRevisionResponseModel {
+        ProtoTypeRevisionResponseModel {
+            id: revision._id.to_string(),
+            template: revision.template_id.to_string(),
+            revision: revision.revision,
+            created_at: revision.created_at.0.to_rfc3339(),
+            message: revision.message,
+            nodes: revision.snapshot.prototype.len(),
+            connections: revision
+                .snapshot
+                .prototype
+                .iter()
+                .map(|node| node.connections.len())
+                .sum(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeRevisionRequest {
+    pub template_id: String,
+    pub revision: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeDiffRequest {
+    pub template_id: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionDiff {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtoTypeDiffResponseModel {
+    pub template: String,
+    pub from: i64,
+    pub to: i64,
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    pub added_connections: Vec<ConnectionDiff>,
+    pub removed_connections: Vec<ConnectionDiff>,
+}
+
 ////////////////////////prototype schema
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
@@ -492,6 +1096,25 @@ pub struct ProjectDeserializeModel {
     pub payment_option: PaymentOption,
     pub delivrable: Option<Delivrable>,
     pub total_price: f64,
+    #[serde(default)]
+    pub notices: Vec<ProjectNotice>,
+}
+
+/// Left on a project when a cascading delete changed it behind the client's back.
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct ProjectNotice {
+    pub kind: String,
+    pub id: ObjectId,
+    pub name: String,
+    pub created_at: bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct ProjectNoticeResponseModel {
+    pub kind: String,
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
@@ -543,6 +1166,8 @@ pub struct ProjectResponseModel {
     pub payment_option: PaymentOption,
     pub delivrable: Option<Delivrable>,
     pub total_price: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notices: Vec<ProjectNoticeResponseModel>,
 }
 
 impl ProjectResponseModel {
@@ -561,9 +1186,20 @@ impl ProjectResponseModel {
                 .collect::<Vec<FeatureResponseModel>>(),
             state: project.state,
             proposal: project.proposal,
-            delivrable: project.delivrable,
+            delivrable: project.delivrable.map(Delivrable::signed),
             total_price: project.total_price,
             payment_option: project.payment_option,
+            notices: project
+                .notices
+                .into_iter()
+                .map(|notice| ProjectNoticeResponseModel {
+                    kind: notice.kind,
+                    id: notice.id.to_string(),
+                    name: notice.name,
+                    created_at: chrono::DateTime::<chrono::Utc>::from(notice.created_at)
+                        .to_rfc3339(),
+                })
+                .collect(),
         }
     }
 }
@@ -604,6 +1240,17 @@ pub struct Delivrable {
     pub design: File,
 }
 
+impl Delivrable {
+    fn signed(self) -> Delivrable {
+        Delivrable {
+            specification: self.specification.signed(),
+            full_build: self.full_build,
+            mvp: self.mvp.signed(),
+            design: self.design.signed(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionResult {
     pub trans_id: String,
@@ -611,3 +1258,15 @@ pub struct TransactionResult {
     pub created: i64,
     pub status: String,
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionResponse {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub storage: &'static str,
+}
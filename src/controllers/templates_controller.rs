@@ -1,11 +1,13 @@
 extern crate jsonwebtoken as jwt;
 use super::schema::{
-    CategoiresIds, FeatureToAnyModel, File, Introduction, NonFunctionalRequirements,
+    CategoiresIds, DeleteRequest, FeatureToAnyModel, File, Introduction, NonFunctionalRequirements,
     OverallDescription, SerlizedId, Specification, Template, TemplateDeserializeModel,
     TemplateObject, TemplateObjectWithId, TemplateReafactorDeserializeModel, TemplateResponseModel,
     TemplateResponseRefactorModel,
 };
+use crate::middleware::auth_middelware::{Identity, CATALOG_READ, CATALOG_WRITE};
 use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::integrity::release_template;
 use actix_web::{
     delete, get, post, put,
     web::{self, Json},
@@ -19,7 +21,9 @@ use std::path::PathBuf;
 #[get("template/all")] // no need
 async fn get_all_templates(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state.container.template.find_all().await {
         Ok(cursor) => {
             let templates: Vec<TemplateResponseRefactorModel> = cursor
@@ -46,8 +50,10 @@ async fn get_all_templates(
 #[post("template/categories/all")] //  no need
 async fn get_templates_by_categories_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     categoires: Json<CategoiresIds>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     let categories_id = categoires
         .categories_id
         .clone()
@@ -160,8 +166,10 @@ async fn get_templates_by_categories_id(
 #[post("template/create")] // no need
 async fn create_template(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     template: Json<TemplateObject>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&template.into_inner()).and_then(|template| {
         match serde_json::from_str::<TemplateObject>(&template) {
             Ok(template) => Ok(template),
@@ -213,7 +221,7 @@ async fn create_template(
                                             },
                                         )
                                         .ok();
-                                        println!("Tempalte Dezrlized: {:?}", template);
+                                        tracing::debug!(?template, "template deserialized");
                                         TemplateResponseRefactorModel::build_template(
                                             template.unwrap(),
                                         )
@@ -241,8 +249,10 @@ async fn create_template(
 #[put("template/update")] //no need
 async fn update_template(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     template: Json<TemplateObjectWithId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     match serde_json::to_string(&template.into_inner()).and_then(|template| {
         match serde_json::from_str::<TemplateObjectWithId>(&template) {
             Ok(template) => Ok(template),
@@ -323,8 +333,13 @@ async fn update_template(
 #[delete("template/delete")]
 async fn delete_template(
     app_state: web::Data<crate::AppState>,
-    feature_data: Json<SerlizedId>,
+    identity: Identity,
+    feature_data: Json<DeleteRequest>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
+    let id = ObjectId::with_string(&feature_data.id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    release_template(&app_state.container, &id, feature_data.policy).await?;
     match app_state
         .container
         .template
@@ -359,8 +374,10 @@ async fn delete_template(
 #[put("template/feature/update")] // no need
 async fn update_template_feature(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     data: Json<FeatureToAnyModel>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     let features_id = data
         .features_id
         .clone()
@@ -389,7 +406,7 @@ async fn update_template_feature(
                                 Err(_mongodb_error) => bson::Document::new(),
                             })
                             .ok();
-                        println!("Tempalte Dezrlized: {:?}", template);
+                        tracing::debug!(?template, "template deserialized");
                         TemplateResponseRefactorModel::build_template(template.unwrap())
                     })
                     .collect()
@@ -407,8 +424,10 @@ async fn update_template_feature(
 #[post("template/get")] //no need
 async fn get_template_by_id(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     template_data: Json<SerlizedId>,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_READ)?;
     match app_state
         .container
         .template
@@ -419,12 +438,9 @@ async fn get_template_by_id(
             let templates: Vec<TemplateResponseRefactorModel> = cursor
                 .map(|doc| {
                     let doc = match doc {
-                        Ok(document) => {
-                            println!("document :{:?}", document);
-                            document
-                        }
+                        Ok(document) => document,
                         Err(e) => {
-                            println!("error :{:?}", e);
+                            tracing::error!(error = %e, "cannot read template");
 
                             bson::Document::new()
                         }
@@ -453,8 +469,10 @@ async fn get_template_by_id(
 #[put("template/specification/add")] // no need
 async fn add_template_specification(
     app_state: web::Data<crate::AppState>,
+    identity: Identity,
     mut parts: Parts,
 ) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(CATALOG_WRITE)?;
     let form_data = parts.texts.as_hash_map();
 
     let specs = Specification {
@@ -484,7 +502,7 @@ async fn add_template_specification(
         analysis_models: form_data["analysis_models"].to_string(),
         issues_list: form_data["issues_list"].to_string(),
     };
-    println!("{:?}", specs);
+    tracing::debug!(specification = ?specs, "updating template specification");
     match app_state
         .container
         .template
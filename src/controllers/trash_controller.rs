@@ -0,0 +1,47 @@
+use super::schema::{SerlizedId, TrashPurgeRequest, TrashRestoreRequest};
+use crate::middleware::auth_middelware::{Identity, ADMINS, STAFF};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::trash::{default_retention_days, list_trash, purge_expired, restore};
+use actix_web::{
+    delete, get, put,
+    web::{self, Json},
+    HttpResponse,
+};
+
+#[get("trash/all")]
+async fn get_trash(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(STAFF)?;
+    Ok(HttpResponse::Ok().json(list_trash(&app_state.container).await?))
+}
+
+#[put("trash/restore")]
+async fn restore_from_trash(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    restore_data: Json<TrashRestoreRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(STAFF)?;
+    restore(&app_state.container, restore_data.collection, &restore_data.id).await?;
+    Ok(HttpResponse::Ok().json(SerlizedId {
+        id: restore_data.id.clone(),
+    }))
+}
+
+#[delete("trash/purge")]
+async fn purge_trash(
+    app_state: web::Data<crate::AppState>,
+    identity: Identity,
+    purge_data: Json<TrashPurgeRequest>,
+) -> Result<HttpResponse, ContentBuilderCustomResponseError> {
+    identity.require(ADMINS)?;
+    let retention_days = purge_data
+        .retention_days
+        .unwrap_or_else(default_retention_days);
+    if retention_days < 0 {
+        return Err(ContentBuilderCustomResponseError::BadClientData);
+    }
+    Ok(HttpResponse::Ok().json(purge_expired(&app_state.container, retention_days).await?))
+}
@@ -1,52 +1,142 @@
 mod controllers;
 mod middleware;
 mod models;
-use actix_files as fs;
+mod services;
+use actix_rt::time::delay_for;
 use actix_web::{
+    guard,
     web::{scope, JsonConfig, ServiceConfig},
     App, HttpServer,
 };
+use awmp::PartsConfig;
 use load_dotenv::load_dotenv;
 use middleware::{
-    cors_middelware::init_cors,
-    logging_middelware::{get_subscriber, init_subscriber},
+    auth_middelware::{AuthConfig, Authentication},
+    cors_middelware::{is_public_route, CorsPolicy},
+    logging_middelware::{get_subscriber, init_subscriber, shutdown_telemetry},
+    metrics_middelware::RequestMetrics,
+    rate_limit_middelware::{
+        prune_periodically, InMemoryRateLimitStore, RateLimitConfig, RateLimitStore, RateLimiter,
+        Stage,
+    },
+    request_id_middelware::RequestTracing,
 };
 use models::{
-    CategoriesCollection, FeaturesCollection, ProjectsCollection, PrototypesCollection,
-    TemplatesCollection,
+    ApiKeysCollection, AssetsCollection, CategoriesCollection, FeaturesCollection, MigrationsCollection,
+    ProjectsCollection, PrototypeRevisionsCollection, PrototypesCollection, TemplatesCollection,
 };
-use mongodb::{options::ClientOptions, Client};
-use std::env;
-use tracing_actix_web::TracingLogger;
+use models::indexes::IndexSpec;
+use mongodb::{options::ClientOptions, Client, Database};
+use services::directory::{DirectoryConfig, UserDirectory};
+use services::metrics::CommandMetrics;
+use services::storage::{StorageBackend, StorageConfig};
+use services::validation::MAX_UPLOAD_SIZE;
+use std::{env, sync::Arc, time::Duration};
+
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 pub struct CollectionsContainer {
+    api_key: ApiKeysCollection,
+    asset: AssetsCollection,
     #[allow(dead_code)]
     category: CategoriesCollection,
     feature: FeaturesCollection,
+    migration: MigrationsCollection,
     project: ProjectsCollection,
     prototype: PrototypesCollection,
+    prototype_revision: PrototypeRevisionsCollection,
     template: TemplatesCollection,
+    database: Database,
 }
 
 pub struct AppState {
     #[allow(dead_code)]
     container: CollectionsContainer,
+    storage: Box<dyn StorageBackend>,
+    directory: Box<dyn UserDirectory>,
+    declared_indexes: Vec<(&'static str, Vec<IndexSpec>)>,
 }
 
-async fn establish_connection() -> CollectionsContainer {
+/// Every worker checks these are all there before reporting ready.
+fn declared_indexes() -> Vec<(&'static str, Vec<IndexSpec>)> {
+    load_dotenv!();
+    vec![
+        (env!("API_KEYS_COLLECTION"), ApiKeysCollection::indexes()),
+        (env!("ASSETS_COLLECTION"), AssetsCollection::indexes()),
+        (env!("CATEGORIES_COLLECTION"), CategoriesCollection::indexes()),
+        (env!("FEATURES_COLLECTION"), FeaturesCollection::indexes()),
+        (env!("PROJECTS_COLLECTION"), ProjectsCollection::indexes()),
+        (env!("PROTOTYPES_COLLECTION"), PrototypesCollection::indexes()),
+        (
+            env!("PROTOTYPE_REVISIONS_COLLECTION"),
+            PrototypeRevisionsCollection::indexes(),
+        ),
+        (env!("TEMPLATES_COLLECTION"), TemplatesCollection::indexes()),
+    ]
+}
+
+async fn connect() -> Result<Database, mongodb::error::Error> {
     load_dotenv!();
-    let client_options = ClientOptions::parse(env!("BUILDER_DATABASE_URL"))
-        .await
-        .unwrap();
-    let client = Client::with_options(client_options).unwrap();
+    let mut client_options = ClientOptions::parse(env!("BUILDER_DATABASE_URL")).await?;
+    client_options.command_event_handler = Some(Arc::new(CommandMetrics::default()));
+    let client = Client::with_options(client_options)?;
     let db = client.database(env!("BUILDER_DATABASE"));
+    // the driver connects lazily, only a round trip proves the database is there
+    services::health::ping(&db).await?;
+    Ok(db)
+}
+
+/// Retries `DATABASE_CONNECT_ATTEMPTS` times (10 by default), doubling the wait from
+/// `DATABASE_CONNECT_BACKOFF_MS` (500 by default) up to 30 seconds, then exits.
+async fn establish_connection() -> CollectionsContainer {
+    load_dotenv!();
+    let attempts: u32 = env::var("DATABASE_CONNECT_ATTEMPTS")
+        .ok()
+        .and_then(|attempts| attempts.parse().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(10);
+    let mut backoff = Duration::from_millis(
+        env::var("DATABASE_CONNECT_BACKOFF_MS")
+            .ok()
+            .and_then(|backoff| backoff.parse().ok())
+            .unwrap_or(500),
+    );
+    let mut attempt = 1;
+    let db = loop {
+        match connect().await {
+            Ok(db) => break db,
+            Err(mongodb_error) if attempt < attempts => {
+                tracing::warn!(
+                    attempt,
+                    attempts,
+                    backoff_ms = backoff.as_millis() as u64,
+                    error = %mongodb_error,
+                    "database unreachable, retrying"
+                );
+                delay_for(backoff).await;
+                backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+                attempt += 1;
+            }
+            Err(mongodb_error) => {
+                tracing::error!(attempts, error = %mongodb_error, "database unreachable, giving up");
+                std::process::exit(1);
+            }
+        }
+    };
     CollectionsContainer {
+        api_key: ApiKeysCollection::new(db.collection(env!("API_KEYS_COLLECTION"))),
+        asset: AssetsCollection::new(db.collection(env!("ASSETS_COLLECTION"))),
         category: CategoriesCollection::new(db.collection(env!("CATEGORIES_COLLECTION"))),
         feature: FeaturesCollection::new(db.collection(env!("FEATURES_COLLECTION"))),
+        migration: MigrationsCollection::new(db.collection(env!("MIGRATIONS_COLLECTION"))),
         project: ProjectsCollection::new(db.collection(env!("PROJECTS_COLLECTION"))),
         prototype: PrototypesCollection::new(db.collection(env!("PROTOTYPES_COLLECTION"))),
+        prototype_revision: PrototypeRevisionsCollection::new(
+            db.collection(env!("PROTOTYPE_REVISIONS_COLLECTION")),
+        ),
         template: TemplatesCollection::new(db.collection(env!("TEMPLATES_COLLECTION"))),
+        database: db,
     }
 }
 
@@ -58,6 +148,7 @@ pub fn init_services(cfg: &mut ServiceConfig) {
         .service(controllers::get_all_categories)
         .service(controllers::get_category_by_id)
         .service(controllers::delete_category)
+        .service(controllers::upload_category_image)
         //feature crud
         .service(controllers::create_feature)
         .service(controllers::update_feature)
@@ -66,6 +157,8 @@ pub fn init_services(cfg: &mut ServiceConfig) {
         .service(controllers::get_feature_by_id)
         .service(controllers::add_feature_wireframe)
         .service(controllers::delete_feature_wireframe)
+        .service(controllers::upload_feature_image)
+        .service(controllers::upload_feature_wireframes)
         //template crud
         .service(controllers::create_template)
         .service(controllers::update_template)
@@ -77,21 +170,83 @@ pub fn init_services(cfg: &mut ServiceConfig) {
         .service(controllers::add_template_specification)
         //prototype crud
         .service(controllers::add_prototype)
-        // .service(controllers::delete_prototype)
+        .service(controllers::delete_prototype)
         .service(controllers::get_prototype_by_template_id)
         .service(controllers::update_prototype)
+        .service(controllers::update_prototype_layout)
+        .service(controllers::auto_layout_prototype)
+        .service(controllers::update_prototype_hotspots)
+        .service(controllers::export_prototype)
+        .service(controllers::import_prototype)
+        .service(controllers::get_prototype_revisions)
+        .service(controllers::diff_prototype_revisions)
+        .service(controllers::restore_prototype_revision)
         //project crud
         .service(controllers::add_project)
         .service(controllers::get_project_by_id)
         .service(controllers::get_all_project_by_client_id)
         .service(controllers::change_project_state)
+        .service(controllers::delete_project)
         .service(controllers::get_all_projects)
         .service(controllers::update_project)
         .service(controllers::generate_project_specification)
         .service(controllers::add_full_build_project)
         .service(controllers::add_proposal_project)
         .service(controllers::add_mvp_project)
-        .service(controllers::add_design_project);
+        .service(controllers::add_design_project)
+        .service(controllers::upload_project_deliverable)
+        //asset upload
+        .service(controllers::upload_asset)
+        .service(controllers::get_asset_by_id)
+        .service(controllers::collect_orphaned_assets)
+        //media
+        .service(controllers::get_media)
+        //trash
+        .service(controllers::get_trash)
+        .service(controllers::restore_from_trash)
+        .service(controllers::purge_trash)
+        //api keys
+        .service(controllers::get_all_api_keys)
+        .service(controllers::create_api_key)
+        .service(controllers::rotate_api_key)
+        .service(controllers::revoke_api_key);
+}
+
+/// `check [--fix] [--json]` scans the database instead of serving, and exits non zero
+/// while issues remain.
+async fn run_check(collections: &CollectionsContainer, args: &[String]) {
+    let fix = args.iter().any(|arg| arg == "--fix");
+    let report = match services::consistency::check(collections, fix).await {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("🔎 Consistency check failed: {}", error);
+            std::process::exit(2);
+        }
+    };
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        println!("{}", report);
+    }
+    std::process::exit(if report.unresolved() == 0 { 0 } else { 1 });
+}
+
+/// `migrate [--dry-run] [--json]` applies pending migrations instead of serving.
+async fn run_migrate(collections: &CollectionsContainer, args: &[String]) {
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let report = match services::migrations::migrate(collections, dry_run).await {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("🗃️ Migrations failed: {}", error);
+            std::process::exit(2);
+        }
+    };
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        println!("{}", report);
+    }
+    std::process::exit(if report.failed() { 1 } else { 0 });
 }
 
 #[actix_rt::main]
@@ -103,21 +258,135 @@ async fn main() -> std::io::Result<()> {
     let subscriber = get_subscriber("app".into(), "info".into());
     init_subscriber(subscriber);
     let collections = establish_connection().await;
-    println!("🚀 Server ready at http://127.0.0.1:8080");
-    HttpServer::new(move || {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = args.first().map(String::as_str);
+    if let Some("check") | Some("migrate") = command {
+        // one off commands only compare, a unique index is built once the data is clean
+        let report =
+            services::indexes::verify_indexes(&collections.database, &declared_indexes()).await;
+        tracing::info!(indexes = %report, "indexes verified");
+    }
+    match command {
+        Some("check") => run_check(&collections, &args[1..]).await,
+        Some("migrate") => run_migrate(&collections, &args[1..]).await,
+        _ => {}
+    }
+    if env::var("MIGRATE_ON_STARTUP").is_ok_and(|migrate| migrate == "true") {
+        match services::migrations::migrate(&collections, false).await {
+            Ok(report) if !report.failed() => tracing::info!(migrations = %report, "migrated"),
+            Ok(report) => {
+                tracing::error!(migrations = %report, "migrations failed");
+                panic!("migrations failed, refusing to start");
+            }
+            Err(error) => panic!("migrations failed, refusing to start: {}", error),
+        }
+    }
+    // after migrations, which may remove the duplicates a unique index cannot be built over
+    let report = services::indexes::ensure_indexes(&collections.database, &declared_indexes()).await;
+    // a missing index slows queries down or lets a duplicate through, it is not fatal
+    tracing::info!(indexes = %report, "indexes ensured");
+    let storage_config = StorageConfig::from_env();
+    // deliverable links are signed, refuse to start without a key
+    services::assets::signing_key();
+    tracing::info!(storage = %storage_config.build().name(), "storing uploads");
+    let directory_config = DirectoryConfig::from_env();
+    tracing::info!(directory = %directory_config.build().name(), "checking clients");
+    let auth_config = Arc::new(AuthConfig::from_env());
+    let public_cors = CorsPolicy::public_from_env();
+    let admin_cors = CorsPolicy::admin_from_env();
+    let rate_limit_config = Arc::new(RateLimitConfig::from_env());
+    let rate_limit_store: Arc<dyn RateLimitStore> = Arc::new(InMemoryRateLimitStore::default());
+    tracing::info!(store = %rate_limit_store.name(), "keeping rate limits");
+    actix_rt::spawn(prune_periodically(rate_limit_store.clone()));
+    if let Some(interval_hours) = env::var("ASSET_GC_INTERVAL_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse::<u64>().ok())
+        .filter(|hours| *hours > 0)
+    {
+        actix_rt::spawn(services::gc::run_periodically(
+            collections.clone(),
+            storage_config.build(),
+            interval_hours,
+        ));
+    }
+    if let Some(interval_hours) = env::var("TRASH_PURGE_INTERVAL_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse::<u64>().ok())
+        .filter(|hours| *hours > 0)
+    {
+        actix_rt::spawn(services::trash::run_periodically(
+            collections.clone(),
+            interval_hours,
+        ));
+    }
+    tracing::info!(port, "server ready");
+    let served = HttpServer::new(move || {
         let collection_container = collections.clone();
+        let storage = storage_config.build();
+        let directory = directory_config.build();
         App::new()
-            .wrap(init_cors())
-            .wrap(TracingLogger)
+            .wrap(RequestMetrics)
+            .wrap(RequestTracing)
             .data(AppState {
                 container: collection_container,
+                storage,
+                directory,
+                declared_indexes: declared_indexes(),
             })
             .app_data(JsonConfig::default().limit(4096 * 512))
+            // uploads are cut off while streaming, each endpoint then applies its own policy
+            .data(PartsConfig::default().with_file_limit(MAX_UPLOAD_SIZE as usize))
             //2MO
             // limit(1024 * 1024 * 50))//50MO
-            .service(scope("/api/v1/builder/").configure(init_services))
+            // probes stay outside the api scopes, no credentials and no rate limits
+            .service(controllers::health)
+            .service(controllers::ready)
+            .service(controllers::version)
+            .service(controllers::metrics)
+            // both scopes serve every route, the path only picks the CORS policy
+            .service(
+                scope("/api/v1/builder/")
+                    .guard(guard::fn_guard(is_public_route))
+                    .wrap(RateLimiter::new(
+                        Stage::Identity,
+                        rate_limit_config.clone(),
+                        rate_limit_store.clone(),
+                    ))
+                    .wrap(Authentication::new(
+                        auth_config.clone(),
+                        collections.api_key.clone(),
+                    ))
+                    .wrap(RateLimiter::new(
+                        Stage::ClientAddress,
+                        rate_limit_config.clone(),
+                        rate_limit_store.clone(),
+                    ))
+                    .wrap(public_cors.build())
+                    .configure(init_services),
+            )
+            .service(
+                scope("/api/v1/builder/")
+                    .wrap(RateLimiter::new(
+                        Stage::Identity,
+                        rate_limit_config.clone(),
+                        rate_limit_store.clone(),
+                    ))
+                    .wrap(Authentication::new(
+                        auth_config.clone(),
+                        collections.api_key.clone(),
+                    ))
+                    .wrap(RateLimiter::new(
+                        Stage::ClientAddress,
+                        rate_limit_config.clone(),
+                        rate_limit_store.clone(),
+                    ))
+                    .wrap(admin_cors.build())
+                    .configure(init_services),
+            )
     })
     .bind(("0.0.0.0".to_string(), port))?
     .run()
-    .await
+    .await;
+    shutdown_telemetry();
+    served
 }
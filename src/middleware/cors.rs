@@ -1,10 +1,220 @@
 pub mod cors_middelware {
     use actix_cors::Cors;
-    pub fn init_cors() -> Cors {
-        Cors::default()
-            .allow_any_origin()
-            .allow_any_header()
-            .allow_any_method()
-            .supports_credentials()
+    use actix_web::dev::RequestHead;
+    use std::env;
+
+    /// Catalog reads a storefront may call from anywhere, every other route is for the
+    /// admin and client apps. Paths are relative to the api scope.
+    const PUBLIC_ROUTES: &[&str] = &[
+        "category/all",
+        "category/get",
+        "feature/all",
+        "feature/get",
+        "template/all",
+        "template/get",
+        "template/categories/all",
+        "prototype/get",
+        "media/",
+    ];
+    const API_SCOPE: &str = "/api/v1/builder/";
+
+    pub fn is_public_route(head: &RequestHead) -> bool {
+        head.uri
+            .path()
+            .strip_prefix(API_SCOPE)
+            .is_some_and(|path| PUBLIC_ROUTES.iter().any(|route| path.starts_with(route)))
+    }
+
+    /// `https://*.example.com` allows every subdomain of example.com over https, at any
+    /// depth, but not example.com itself nor another port.
+    #[derive(Debug, Clone)]
+    struct WildcardOrigin {
+        scheme: String,
+        domain: String,
+    }
+
+    impl WildcardOrigin {
+        fn parse(pattern: &str) -> Option<WildcardOrigin> {
+            let (scheme, domain) = pattern.split_once("://*.")?;
+            Some(WildcardOrigin {
+                scheme: format!("{}://", scheme),
+                domain: format!(".{}", domain),
+            })
+        }
+
+        fn matches(&self, origin: &str) -> bool {
+            origin
+                .strip_prefix(&self.scheme)
+                .and_then(|host| host.strip_suffix(&self.domain))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && subdomain
+                            .split('.')
+                            .all(|label| !label.is_empty() && label.chars().all(is_label_char))
+                })
+        }
+    }
+
+    fn is_label_char(character: char) -> bool {
+        character.is_ascii_alphanumeric() || character == '-'
+    }
+
+    /// One CORS policy read from `CORS_<NAME>_ORIGINS`, `_METHODS`, `_HEADERS`,
+    /// `_MAX_AGE` and `_CREDENTIALS`. Origins are comma separated, exact such as
+    /// `https://admin.example.com`, wildcard subdomains such as `https://*.example.com`,
+    /// or `*` for any origin, which cannot be combined with credentials.
+    #[derive(Debug, Clone)]
+    pub struct CorsPolicy {
+        any_origin: bool,
+        origins: Vec<String>,
+        wildcard_origins: Vec<WildcardOrigin>,
+        methods: Vec<String>,
+        headers: Vec<String>,
+        max_age: usize,
+        credentials: bool,
+    }
+
+    impl CorsPolicy {
+        /// The public catalog is readable from any origin unless configured otherwise.
+        pub fn public_from_env() -> CorsPolicy {
+            CorsPolicy::from_env("PUBLIC", "*", "GET,POST")
+        }
+
+        /// No cross origin access to admin and project routes unless configured.
+        pub fn admin_from_env() -> CorsPolicy {
+            CorsPolicy::from_env("ADMIN", "", "GET,POST,PUT,PATCH,DELETE")
+        }
+
+        fn from_env(name: &str, default_origins: &str, default_methods: &str) -> CorsPolicy {
+            let var = |setting: &str, default: &str| {
+                env::var(format!("CORS_{}_{}", name, setting))
+                    .unwrap_or_else(|_| default.to_string())
+            };
+            let list = |value: String| -> Vec<String> {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(String::from)
+                    .collect()
+            };
+            let origins = list(var("ORIGINS", default_origins));
+            let credentials = var("CREDENTIALS", "false") == "true";
+            let any_origin = origins.iter().any(|origin| origin == "*");
+            if any_origin && credentials {
+                panic!(
+                    "CORS_{}_ORIGINS cannot be * when CORS_{}_CREDENTIALS is true",
+                    name, name
+                );
+            }
+            CorsPolicy {
+                any_origin,
+                wildcard_origins: origins
+                    .iter()
+                    .filter_map(|origin| WildcardOrigin::parse(origin))
+                    .collect(),
+                origins: origins
+                    .into_iter()
+                    .filter(|origin| origin != "*" && !origin.contains("://*."))
+                    .collect(),
+                methods: list(var("METHODS", default_methods)),
+                headers: list(var("HEADERS", "authorization,content-type,x-api-key")),
+                max_age: var("MAX_AGE", "3600")
+                    .parse()
+                    .unwrap_or_else(|_| panic!("CORS_{}_MAX_AGE must be a number", name)),
+                credentials,
+            }
+        }
+
+        pub fn build(&self) -> Cors {
+            let mut cors = Cors::default()
+                .allowed_methods(self.methods.iter().map(String::as_str))
+                .allowed_headers(self.headers.iter().map(String::as_str))
+                // request ids for support, and when rate limited callers may come back
+                .expose_headers(vec![
+                    "x-request-id",
+                    "retry-after",
+                    "ratelimit-limit",
+                    "ratelimit-remaining",
+                    "ratelimit-reset",
+                ])
+                .max_age(self.max_age);
+            if self.any_origin {
+                cors = cors.allow_any_origin();
+            }
+            for origin in self.origins.iter() {
+                cors = cors.allowed_origin(origin);
+            }
+            if !self.wildcard_origins.is_empty() {
+                let wildcard_origins = self.wildcard_origins.clone();
+                cors = cors.allowed_origin_fn(move |origin, _head| {
+                    origin.to_str().is_ok_and(|origin| {
+                        wildcard_origins
+                            .iter()
+                            .any(|wildcard| wildcard.matches(origin))
+                    })
+                });
+            }
+            if self.credentials {
+                cors = cors.supports_credentials();
+            }
+            cors
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use actix_web::test::TestRequest;
+
+        fn wildcard() -> WildcardOrigin {
+            WildcardOrigin::parse("https://*.example.com").unwrap()
+        }
+
+        #[test]
+        fn only_wildcard_patterns_parse() {
+            assert!(WildcardOrigin::parse("https://admin.example.com").is_none());
+            assert!(WildcardOrigin::parse("*").is_none());
+        }
+
+        #[test]
+        fn subdomains_match_at_any_depth() {
+            assert!(wildcard().matches("https://admin.example.com"));
+            assert!(wildcard().matches("https://eu.admin.example.com"));
+            assert!(wildcard().matches("https://client-1.example.com"));
+        }
+
+        #[test]
+        fn the_domain_itself_does_not_match() {
+            assert!(!wildcard().matches("https://example.com"));
+            assert!(!wildcard().matches("https://.example.com"));
+        }
+
+        #[test]
+        fn other_schemes_ports_and_domains_do_not_match() {
+            assert!(!wildcard().matches("http://admin.example.com"));
+            assert!(!wildcard().matches("https://admin.example.com:8443"));
+            assert!(!wildcard().matches("https://admin.example.com.evil.io"));
+            assert!(!wildcard().matches("https://adminexample.com"));
+            assert!(!wildcard().matches("https://evil-example.com"));
+        }
+
+        #[test]
+        fn malformed_subdomains_do_not_match() {
+            assert!(!wildcard().matches("https://a..example.com"));
+            assert!(!wildcard().matches("https://evil.io/.example.com"));
+            assert!(!wildcard().matches("https://user@admin.example.com"));
+            assert!(!wildcard().matches("https://a b.example.com"));
+        }
+
+        #[test]
+        fn public_routes_are_under_the_api_scope() {
+            let public =
+                |path: &str| is_public_route(TestRequest::get().uri(path).to_http_request().head());
+            assert!(public("/api/v1/builder/feature/all"));
+            assert!(public("/api/v1/builder/media/some/key.png"));
+            assert!(!public("/api/v1/builder/project/all"));
+            assert!(!public("/feature/all"));
+        }
     }
 }
@@ -1,4 +1,8 @@
-use actix_web::{error, http::StatusCode, HttpResponse};
+use actix_web::{
+    error,
+    http::{header, StatusCode},
+    HttpResponse,
+};
 use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +10,24 @@ use serde::{Deserialize, Serialize};
 pub struct ErrorResponse {
     status_code: String,
     msg: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dependents: Vec<Dependent>,
+}
+
+/// An entity that still references the one a request tried to delete, or that a
+/// trashed entity needs back before it can be restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dependent {
+    pub collection: String,
+    pub id: String,
+    pub name: Option<String>,
+}
+
+/// The bucket a rate limited request ran dry on, sent back as `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitExceeded {
+    pub limit: u32,
+    pub reset_seconds: u64,
 }
 
 #[derive(Debug, Display, Error)]
@@ -25,13 +47,57 @@ pub enum ContentBuilderCustomResponseError {
     #[display(fmt = "User not Allowed!")]
     NotAllowed,
 
+    #[display(fmt = "Not Authenticated!")]
+    Unauthorized,
+
+    #[display(fmt = "Unsupported Media Type!")]
+    UnsupportedMediaType,
+
+    #[display(fmt = "Payload Too Large!")]
+    PayloadTooLarge,
+
+    #[display(fmt = "Still referenced by other entities!")]
+    Conflict(#[error(not(source))] Vec<Dependent>),
+
+    #[display(fmt = "Depends on entities that are in the trash or gone!")]
+    MissingDependencies(#[error(not(source))] Vec<Dependent>),
+
+    #[display(fmt = "Already exists!")]
+    AlreadyExists,
+
+    #[display(fmt = "Service Unavailable!")]
+    Unavailable,
+
+    #[display(fmt = "Too Many Requests!")]
+    RateLimited(#[error(not(source))] RateLimitExceeded),
 }
 
 impl error::ResponseError for ContentBuilderCustomResponseError {
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(ErrorResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            ContentBuilderCustomResponseError::Unauthorized => {
+                response.set_header(header::WWW_AUTHENTICATE, "Bearer");
+            }
+            ContentBuilderCustomResponseError::RateLimited(exceeded) => {
+                response
+                    .set_header("RateLimit-Limit", exceeded.limit as usize)
+                    .set_header("RateLimit-Remaining", 0usize)
+                    .set_header("RateLimit-Reset", exceeded.reset_seconds)
+                    .set_header(header::RETRY_AFTER, exceeded.reset_seconds);
+            }
+            _ => {}
+        }
+        response.json(ErrorResponse {
             status_code: self.status_code().to_string(),
             msg: self.to_string(),
+            dependents: match self {
+                ContentBuilderCustomResponseError::Conflict(dependents)
+                | ContentBuilderCustomResponseError::MissingDependencies(dependents) => {
+                    dependents.clone()
+                }
+                _ => Vec::new(),
+            },
         })
     }
 
@@ -43,6 +109,16 @@ impl error::ResponseError for ContentBuilderCustomResponseError {
             ContentBuilderCustomResponseError::BadHeaderData => StatusCode::FORBIDDEN,
             // UserCustomResponseError::Timeout => StatusCode::GATEWAY_TIMEOUT,
             ContentBuilderCustomResponseError::NotAllowed => StatusCode::FORBIDDEN,
+            ContentBuilderCustomResponseError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ContentBuilderCustomResponseError::UnsupportedMediaType => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            ContentBuilderCustomResponseError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ContentBuilderCustomResponseError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ContentBuilderCustomResponseError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ContentBuilderCustomResponseError::Conflict(_)
+            | ContentBuilderCustomResponseError::MissingDependencies(_)
+            | ContentBuilderCustomResponseError::AlreadyExists => StatusCode::CONFLICT,
         }
     }
 }
@@ -1,14 +1,217 @@
 pub mod logging_middelware {
+    use futures::executor;
+    use opentelemetry::{
+        global,
+        sdk::{
+            export::trace::{stdout, SpanData, SpanExporter},
+            propagation::TraceContextPropagator,
+            trace::{self, Sampler, Span, SpanProcessor, TracerProvider},
+            Resource,
+        },
+        trace::{TraceError, TraceResult, TracerProvider as _},
+        Context, KeyValue,
+    };
+    use opentelemetry_otlp::{ExportConfig, Protocol, OTEL_EXPORTER_OTLP_TIMEOUT_DEFAULT};
+    use std::{
+        env,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
     use tracing::{subscriber::set_global_default, Subscriber};
     use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
     use tracing_log::LogTracer;
     use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 
+    const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318";
+    const DEFAULT_MAX_QUEUED_SPANS: usize = 2048;
+    const MAX_EXPORT_BATCH: usize = 512;
+
+    /// Where spans are exported besides the logs, from `OTEL_TRACES_EXPORTER`. `otlp`
+    /// posts them over HTTP to `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, or to
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` followed by `/v1/traces`, a local collector by
+    /// default. `stdout` prints them. Unset or `none` exports nothing.
+    #[derive(Debug, Clone)]
+    pub enum TraceExport {
+        Otlp { endpoint: String },
+        Stdout,
+    }
+
+    impl TraceExport {
+        pub fn from_env() -> Option<TraceExport> {
+            match env::var("OTEL_TRACES_EXPORTER")
+                .unwrap_or_default()
+                .as_str()
+            {
+                "" | "none" => None,
+                "otlp" => Some(TraceExport::Otlp {
+                    endpoint: env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").unwrap_or_else(|_| {
+                        format!(
+                            "{}/v1/traces",
+                            env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                                .unwrap_or_else(|_| DEFAULT_OTLP_ENDPOINT.to_string())
+                                .trim_end_matches('/')
+                        )
+                    }),
+                }),
+                "stdout" => Some(TraceExport::Stdout),
+                other => panic!(
+                    "OTEL_TRACES_EXPORTER must be otlp, stdout or none, not {}",
+                    other
+                ),
+            }
+        }
+
+        /// Named after `OTEL_SERVICE_NAME`, or the crate. Keeps the sampling decision of
+        /// an incoming `traceparent`, and samples `OTEL_TRACES_SAMPLER_ARG` (1.0 by
+        /// default) of the traces started here.
+        fn install(&self) -> trace::Tracer {
+            let ratio = env::var("OTEL_TRACES_SAMPLER_ARG")
+                .ok()
+                .and_then(|ratio| ratio.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let config = trace::config()
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    ratio,
+                ))))
+                .with_resource(Resource::new(vec![
+                    KeyValue::new(
+                        "service.name",
+                        env::var("OTEL_SERVICE_NAME")
+                            .unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string()),
+                    ),
+                    KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+                ]));
+            let exporter: Box<dyn SpanExporter> = match self {
+                TraceExport::Otlp { endpoint } => Box::new(
+                    opentelemetry_otlp::SpanExporter::new_http(
+                        ExportConfig {
+                            endpoint: endpoint.clone(),
+                            protocol: Protocol::HttpBinary,
+                            timeout: Duration::from_secs(OTEL_EXPORTER_OTLP_TIMEOUT_DEFAULT),
+                        },
+                        Default::default(),
+                    )
+                    .unwrap_or_else(|error| {
+                        panic!("cannot export traces to {}: {}", endpoint, error)
+                    }),
+                ),
+                TraceExport::Stdout => Box::new(stdout::Exporter::new(std::io::stdout(), false)),
+            };
+            let capacity = env::var("OTEL_BSP_MAX_QUEUE_SIZE")
+                .ok()
+                .and_then(|size| size.parse::<usize>().ok())
+                .filter(|size| *size > 0)
+                .unwrap_or(DEFAULT_MAX_QUEUED_SPANS);
+            let provider = TracerProvider::builder()
+                .with_span_processor(BoundedSpanProcessor::new(exporter, capacity))
+                .with_config(config)
+                .build();
+            let tracer = provider.tracer(env!("CARGO_PKG_NAME"), Some(env!("CARGO_PKG_VERSION")));
+            global::set_tracer_provider(provider);
+            tracer
+        }
+    }
+
+    /// Exports finished spans from a thread of its own, in batches of whatever queued up
+    /// during the previous export. The queue holds `OTEL_BSP_MAX_QUEUE_SIZE` spans, 2048
+    /// by default. Spans ended while it is full are dropped rather than kept in memory
+    /// behind a slow or unreachable collector.
+    #[derive(Debug)]
+    struct BoundedSpanProcessor {
+        queue: SyncSender<Option<SpanData>>,
+        overflowing: AtomicBool,
+        exporting: Option<JoinHandle<()>>,
+    }
+
+    impl BoundedSpanProcessor {
+        fn new(exporter: Box<dyn SpanExporter>, capacity: usize) -> BoundedSpanProcessor {
+            let (queue, queued) = sync_channel(capacity);
+            let exporting = thread::Builder::new()
+                .name("span-exporter".to_string())
+                .spawn(move || export_queued(exporter, queued))
+                .expect("Failed to start the span exporter");
+            BoundedSpanProcessor {
+                queue,
+                overflowing: AtomicBool::new(false),
+                exporting: Some(exporting),
+            }
+        }
+    }
+
+    /// Runs until the processor shuts down, `None` in the queue, or goes away.
+    fn export_queued(mut exporter: Box<dyn SpanExporter>, queued: Receiver<Option<SpanData>>) {
+        let mut open = true;
+        while open {
+            let mut batch = match queued.recv() {
+                Ok(Some(span)) => vec![span],
+                _ => break,
+            };
+            while batch.len() < MAX_EXPORT_BATCH {
+                match queued.try_recv() {
+                    Ok(Some(span)) => batch.push(span),
+                    Ok(None) => {
+                        open = false;
+                        break;
+                    }
+                    Err(_empty) => break,
+                }
+            }
+            if let Err(error) = executor::block_on(exporter.export(batch)) {
+                global::handle_error(error);
+            }
+        }
+        exporter.shutdown();
+    }
+
+    impl SpanProcessor for BoundedSpanProcessor {
+        fn on_start(&self, _span: &mut Span, _context: &Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            match self.queue.try_send(Some(span)) {
+                Ok(()) => self.overflowing.store(false, Ordering::Relaxed),
+                // reported once each time the queue fills up, not for every span
+                Err(TrySendError::Full(_span)) => {
+                    if !self.overflowing.swap(true, Ordering::Relaxed) {
+                        global::handle_error(TraceError::from(
+                            "span export queue full, dropping spans",
+                        ));
+                    }
+                }
+                Err(TrySendError::Disconnected(_span)) => {}
+            }
+        }
+
+        /// Spans are exported as soon as the exporter is free, there is nothing held back.
+        fn force_flush(&self) -> TraceResult<()> {
+            Ok(())
+        }
+
+        /// Waits for the spans already queued to be exported.
+        fn shutdown(&mut self) -> TraceResult<()> {
+            if let Some(exporting) = self.exporting.take() {
+                let _ = self.queue.send(None);
+                exporting
+                    .join()
+                    .map_err(|_panic| TraceError::from("span exporter panicked"))?;
+            }
+            Ok(())
+        }
+    }
+
     pub fn get_subscriber(name: String, env_filter: String) -> impl Subscriber + Send + Sync {
         let env_filter = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new(env_filter));
-        let formatting_layer = BunyanFormattingLayer::new(name.into(), std::io::stdout);
+        let telemetry = TraceExport::from_env().map(|export| {
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            tracing_opentelemetry::layer().with_tracer(export.install())
+        });
+        let formatting_layer = BunyanFormattingLayer::new(name, std::io::stdout);
         Registry::default()
             .with(env_filter)
+            .with(telemetry)
             .with(JsonStorageLayer)
             .with(formatting_layer)
     }
@@ -17,4 +220,66 @@ pub mod logging_middelware {
         LogTracer::init().expect("Failed to set logger");
         set_global_default(subscriber).expect("Failed to set subscriber");
     }
+
+    /// Sends the spans still queued for export, once the server has stopped.
+    pub fn shutdown_telemetry() {
+        global::shutdown_tracer_provider();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use async_trait::async_trait;
+        use opentelemetry::{sdk::export::trace::ExportResult, trace::Tracer as _};
+        use std::sync::{
+            mpsc::{channel, Sender},
+            Arc, Mutex,
+        };
+
+        /// Records the size of every batch, holding the first export until released.
+        #[derive(Debug)]
+        struct HeldExporter {
+            started: Sender<()>,
+            release: Receiver<()>,
+            batches: Arc<Mutex<Vec<usize>>>,
+        }
+
+        #[async_trait]
+        impl SpanExporter for HeldExporter {
+            async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+                if self.batches.lock().unwrap().is_empty() {
+                    let _ = self.started.send(());
+                    let _ = self.release.recv();
+                }
+                self.batches.lock().unwrap().push(batch.len());
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn spans_past_the_queue_are_dropped_and_the_rest_exported_in_a_batch() {
+            let (started, exporting) = channel();
+            let (release, held) = channel();
+            let batches = Arc::new(Mutex::new(Vec::new()));
+            let exporter = HeldExporter {
+                started,
+                release: held,
+                batches: batches.clone(),
+            };
+            let provider = TracerProvider::builder()
+                .with_span_processor(BoundedSpanProcessor::new(Box::new(exporter), 2))
+                .build();
+            let tracer = provider.tracer("test", None);
+
+            tracer.start("first");
+            exporting.recv().unwrap();
+            for _ in 0..5 {
+                tracer.start("queued");
+            }
+            release.send(()).unwrap();
+            drop(provider);
+
+            assert_eq!(*batches.lock().unwrap(), vec![1, 2]);
+        }
+    }
 }
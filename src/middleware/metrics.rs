@@ -0,0 +1,136 @@
+pub mod metrics_middelware {
+    use crate::services::metrics::{HTTP_REQUESTS, HTTP_REQUEST_DURATION};
+    use actix_web::{
+        dev::{Service, ServiceRequest, ServiceResponse, Transform},
+        Error,
+    };
+    use futures::future::{ok, FutureExt, LocalBoxFuture, Ready};
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        task::{Context, Poll},
+        time::Instant,
+    };
+
+    /// Counts and times every request by route pattern, such as
+    /// `/api/v1/builder/media/{key:.*}`, so ids in paths do not each get a series.
+    pub struct RequestMetrics;
+
+    impl<S, B> Transform<S> for RequestMetrics
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type InitError = ();
+        type Transform = RequestMetricsMiddleware<S>;
+        type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            ok(RequestMetricsMiddleware {
+                service: Rc::new(RefCell::new(service)),
+            })
+        }
+    }
+
+    pub struct RequestMetricsMiddleware<S> {
+        service: Rc<RefCell<S>>,
+    }
+
+    impl<S, B> Service for RequestMetricsMiddleware<S>
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.service.borrow_mut().poll_ready(context)
+        }
+
+        fn call(&mut self, request: ServiceRequest) -> Self::Future {
+            let started = Instant::now();
+            let method = request.method().to_string();
+            let route = request
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".to_string());
+            let response = self.service.borrow_mut().call(request);
+            async move {
+                let response = response.await;
+                let status = match &response {
+                    Ok(response) => response.status(),
+                    Err(error) => error.as_response_error().status_code(),
+                };
+                HTTP_REQUEST_DURATION
+                    .with_label_values(&[&method, &route])
+                    .observe(started.elapsed().as_secs_f64());
+                HTTP_REQUESTS
+                    .with_label_values(&[&method, &route, status.as_str()])
+                    .inc();
+                response
+            }
+            .boxed_local()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use actix_web::{test, web, App, HttpResponse};
+
+        fn requests(route: &str, status: &str) -> u64 {
+            HTTP_REQUESTS
+                .with_label_values(&["GET", route, status])
+                .get()
+        }
+
+        #[actix_rt::test]
+        async fn requests_are_counted_and_timed_by_route_pattern() {
+            let route = "/metrics-test/{id}";
+            let mut app = test::init_service(
+                App::new()
+                    .wrap(RequestMetrics)
+                    .route(route, web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let timed = HTTP_REQUEST_DURATION
+                .with_label_values(&["GET", route])
+                .get_sample_count();
+            let counted = requests(route, "200");
+            for id in ["1", "2"] {
+                let request = test::TestRequest::get()
+                    .uri(&format!("/metrics-test/{}", id))
+                    .to_request();
+                test::call_service(&mut app, request).await;
+            }
+            assert_eq!(requests(route, "200"), counted + 2);
+            assert_eq!(
+                HTTP_REQUEST_DURATION
+                    .with_label_values(&["GET", route])
+                    .get_sample_count(),
+                timed + 2
+            );
+        }
+
+        #[actix_rt::test]
+        async fn unmatched_paths_share_one_series_with_their_status() {
+            let mut app = test::init_service(App::new().wrap(RequestMetrics)).await;
+            let counted = requests("unmatched", "404");
+            let request = test::TestRequest::get()
+                .uri("/metrics-test-unmatched/3")
+                .to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), 404);
+            assert!(requests("unmatched", "404") > counted);
+        }
+    }
+}
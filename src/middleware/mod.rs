@@ -1,6 +1,14 @@
+pub mod auth;
 pub mod cors;
 pub mod error;
 pub mod logging;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub use auth::auth_middelware;
 pub use cors::cors_middelware;
 pub use error::ContentBuilderCustomResponseError;
 pub use logging::logging_middelware;
+pub use metrics::metrics_middelware;
+pub use rate_limit::rate_limit_middelware;
+pub use request_id::request_id_middelware;
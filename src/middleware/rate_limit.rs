@@ -0,0 +1,414 @@
+pub mod rate_limit_middelware {
+    use crate::middleware::auth_middelware::Identity;
+    use crate::middleware::error::{ContentBuilderCustomResponseError, RateLimitExceeded};
+    use actix_rt::time::interval_at;
+    use actix_web::{
+        dev::{Service, ServiceRequest, ServiceResponse, Transform},
+        Error, HttpMessage,
+    };
+    use async_trait::async_trait;
+    use futures::future::{ok, FutureExt, LocalBoxFuture, Ready};
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        env,
+        rc::Rc,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    const API_SCOPE: &str = "/api/v1/builder/";
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// `requests` per `period_seconds`, as a bucket of `requests` tokens refilled
+    /// continuously over the period.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Limit {
+        pub requests: u32,
+        pub period_seconds: u64,
+    }
+
+    impl Limit {
+        /// `<requests>/<seconds>`, e.g. `120/60`.
+        fn parse(limit: &str) -> Option<Limit> {
+            let (requests, period_seconds) = limit.trim().split_once('/')?;
+            let limit = Limit {
+                requests: requests.trim().parse().ok()?,
+                period_seconds: period_seconds.trim().parse().ok()?,
+            };
+            if limit.requests == 0 || limit.period_seconds == 0 {
+                None
+            } else {
+                Some(limit)
+            }
+        }
+
+        fn refill_per_second(&self) -> f64 {
+            self.requests as f64 / self.period_seconds as f64
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Decision {
+        pub allowed: bool,
+        /// Until the next token when refused.
+        pub reset_seconds: u64,
+    }
+
+    /// Where buckets live. The in-memory store limits each instance on its own, a
+    /// shared store (Redis, Mongo, ...) limits the whole deployment.
+    #[async_trait]
+    pub trait RateLimitStore: Send + Sync {
+        fn name(&self) -> &'static str;
+
+        /// Takes a token from the bucket under `key`, creating it full if needed.
+        async fn take(&self, key: &str, limit: Limit) -> Result<Decision, String>;
+
+        /// Forgets the buckets that refilled, a full bucket is the same as none. Shared
+        /// stores usually expire their keys on their own.
+        async fn prune(&self) {}
+    }
+
+    /// Keeps the limit it was created with, so it can be pruned without knowing which
+    /// limit its key belongs to.
+    struct Bucket {
+        tokens: f64,
+        capacity: f64,
+        refill_per_second: f64,
+        updated_at: Instant,
+    }
+
+    impl Bucket {
+        fn new(limit: Limit, now: Instant) -> Bucket {
+            Bucket {
+                tokens: limit.requests as f64,
+                capacity: limit.requests as f64,
+                refill_per_second: limit.refill_per_second(),
+                updated_at: now,
+            }
+        }
+
+        fn tokens_at(&self, now: Instant) -> f64 {
+            (self.tokens
+                + now.duration_since(self.updated_at).as_secs_f64() * self.refill_per_second)
+                .min(self.capacity)
+        }
+
+        fn is_full(&self, now: Instant) -> bool {
+            self.tokens_at(now) >= self.capacity
+        }
+
+        fn take(&mut self, now: Instant) -> Decision {
+            self.tokens = self.tokens_at(now);
+            self.updated_at = now;
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                Decision {
+                    allowed: true,
+                    reset_seconds: 0,
+                }
+            } else {
+                Decision {
+                    allowed: false,
+                    reset_seconds: ((1.0 - self.tokens) / self.refill_per_second).ceil() as u64,
+                }
+            }
+        }
+    }
+
+    #[derive(Default)]
+    pub struct InMemoryRateLimitStore {
+        buckets: Mutex<HashMap<String, Bucket>>,
+    }
+
+    #[async_trait]
+    impl RateLimitStore for InMemoryRateLimitStore {
+        fn name(&self) -> &'static str {
+            "in-memory rate limits"
+        }
+
+        async fn take(&self, key: &str, limit: Limit) -> Result<Decision, String> {
+            let now = Instant::now();
+            let mut buckets = self
+                .buckets
+                .lock()
+                .map_err(|_poison_error| "rate limit buckets poisoned".to_string())?;
+            Ok(buckets
+                .entry(key.to_string())
+                .or_insert_with(|| Bucket::new(limit, now))
+                .take(now))
+        }
+
+        async fn prune(&self) {
+            let now = Instant::now();
+            if let Ok(mut buckets) = self.buckets.lock() {
+                buckets.retain(|_key, bucket| !bucket.is_full(now));
+            }
+        }
+    }
+
+    /// Prunes the store every minute, off the request path.
+    pub async fn prune_periodically(store: Arc<dyn RateLimitStore>) {
+        let mut ticks = interval_at(
+            actix_rt::time::Instant::now() + PRUNE_INTERVAL,
+            PRUNE_INTERVAL,
+        );
+        loop {
+            ticks.tick().await;
+            store.prune().await;
+        }
+    }
+
+    /// Read from `RATE_LIMIT_PER_IP`, `RATE_LIMIT_PER_IDENTITY` and `RATE_LIMIT_ROUTES`
+    /// (`project/all=20/60,template/all=60/60`). Client addresses come from
+    /// `X-Forwarded-For` only with `RATE_LIMIT_TRUST_PROXY=true`, it is spoofable
+    /// without a proxy that overwrites it.
+    #[derive(Debug, Clone)]
+    pub struct RateLimitConfig {
+        per_ip: Limit,
+        per_identity: Limit,
+        routes: Vec<(String, Limit)>,
+        trust_proxy: bool,
+    }
+
+    impl RateLimitConfig {
+        pub fn from_env() -> RateLimitConfig {
+            let limit = |name: &str, default: &str| {
+                Limit::parse(&env::var(name).unwrap_or_else(|_| default.to_string()))
+                    .unwrap_or_else(|| panic!("{} must look like <requests>/<seconds>", name))
+            };
+            RateLimitConfig {
+                per_ip: limit("RATE_LIMIT_PER_IP", "300/60"),
+                per_identity: limit("RATE_LIMIT_PER_IDENTITY", "600/60"),
+                routes: env::var("RATE_LIMIT_ROUTES")
+                    .unwrap_or_else(|_| "project/all=20/60,template/all=60/60".to_string())
+                    .split(',')
+                    .filter(|route| !route.trim().is_empty())
+                    .map(|route| {
+                        route
+                            .split_once('=')
+                            .and_then(|(path, limit)| {
+                                Some((path.trim().to_string(), Limit::parse(limit)?))
+                            })
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "RATE_LIMIT_ROUTES must look like <route>=<requests>/<seconds>"
+                                )
+                            })
+                    })
+                    .collect(),
+                trust_proxy: env::var("RATE_LIMIT_TRUST_PROXY")
+                    .is_ok_and(|trust_proxy| trust_proxy == "true"),
+            }
+        }
+    }
+
+    /// Which buckets a `RateLimiter` draws from. The client address is limited ahead of
+    /// authentication so failed attempts count too, the caller and the route after it.
+    #[derive(Debug, Clone, Copy)]
+    pub enum Stage {
+        ClientAddress,
+        Identity,
+    }
+
+    pub struct RateLimiter {
+        stage: Stage,
+        config: Arc<RateLimitConfig>,
+        store: Arc<dyn RateLimitStore>,
+    }
+
+    impl RateLimiter {
+        pub fn new(
+            stage: Stage,
+            config: Arc<RateLimitConfig>,
+            store: Arc<dyn RateLimitStore>,
+        ) -> RateLimiter {
+            RateLimiter {
+                stage,
+                config,
+                store,
+            }
+        }
+    }
+
+    impl<S, B> Transform<S> for RateLimiter
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type InitError = ();
+        type Transform = RateLimiterMiddleware<S>;
+        type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            ok(RateLimiterMiddleware {
+                service: Rc::new(RefCell::new(service)),
+                stage: self.stage,
+                config: self.config.clone(),
+                store: self.store.clone(),
+            })
+        }
+    }
+
+    pub struct RateLimiterMiddleware<S> {
+        service: Rc<RefCell<S>>,
+        stage: Stage,
+        config: Arc<RateLimitConfig>,
+        store: Arc<dyn RateLimitStore>,
+    }
+
+    impl<S> RateLimiterMiddleware<S> {
+        fn buckets(&self, request: &ServiceRequest) -> Vec<(String, Limit)> {
+            let connection = request.connection_info();
+            let address = if self.config.trust_proxy {
+                connection.realip_remote_addr()
+            } else {
+                connection.remote_addr()
+            }
+            .unwrap_or("unknown")
+            .to_string();
+            match self.stage {
+                Stage::ClientAddress => vec![(format!("ip:{}", address), self.config.per_ip)],
+                Stage::Identity => {
+                    // public media has no identity, its route limits go by address
+                    let caller = match request.extensions().get::<Identity>() {
+                        Some(identity) => format!("id:{}", identity.subject),
+                        None => format!("ip:{}", address),
+                    };
+                    let path = request
+                        .path()
+                        .strip_prefix(API_SCOPE)
+                        .unwrap_or_default()
+                        .to_string();
+                    let mut buckets = vec![(caller.clone(), self.config.per_identity)];
+                    buckets.extend(
+                        self.config
+                            .routes
+                            .iter()
+                            .filter(|(route, _limit)| *route == path)
+                            .map(|(route, limit)| (format!("route:{}:{}", route, caller), *limit)),
+                    );
+                    buckets
+                }
+            }
+        }
+    }
+
+    impl<S, B> Service for RateLimiterMiddleware<S>
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.service.borrow_mut().poll_ready(context)
+        }
+
+        fn call(&mut self, request: ServiceRequest) -> Self::Future {
+            let service = self.service.clone();
+            let store = self.store.clone();
+            let buckets = self.buckets(&request);
+            async move {
+                for (key, limit) in buckets {
+                    match store.take(&key, limit).await {
+                        Ok(decision) if !decision.allowed => {
+                            let error =
+                                ContentBuilderCustomResponseError::RateLimited(RateLimitExceeded {
+                                    limit: limit.requests,
+                                    reset_seconds: decision.reset_seconds,
+                                });
+                            return Ok(request
+                                .error_response(error)
+                                .map_body(|_head, body| body.into_body()));
+                        }
+                        Ok(_decision) => {}
+                        // an unreachable shared store must not take the api down with it
+                        Err(error) => tracing::warn!("{}: {}", store.name(), error),
+                    }
+                }
+                let response = service.borrow_mut().call(request);
+                response.await
+            }
+            .boxed_local()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn limit(requests: u32, period_seconds: u64) -> Limit {
+            Limit {
+                requests,
+                period_seconds,
+            }
+        }
+
+        #[test]
+        fn limits_parse() {
+            let parsed = Limit::parse(" 120 / 60 ").unwrap();
+            assert_eq!((parsed.requests, parsed.period_seconds), (120, 60));
+            assert!(Limit::parse("0/60").is_none());
+            assert!(Limit::parse("120/0").is_none());
+            assert!(Limit::parse("120").is_none());
+        }
+
+        #[test]
+        fn a_full_bucket_allows_its_capacity_then_refuses() {
+            let now = Instant::now();
+            let mut bucket = Bucket::new(limit(3, 60), now);
+            for _ in 0..3 {
+                assert!(bucket.take(now).allowed);
+            }
+            let refused = bucket.take(now);
+            assert!(!refused.allowed);
+            assert_eq!(refused.reset_seconds, 20);
+        }
+
+        #[test]
+        fn tokens_refill_with_time_up_to_capacity() {
+            let now = Instant::now();
+            let mut bucket = Bucket::new(limit(2, 10), now);
+            bucket.take(now);
+            bucket.take(now);
+            assert!(!bucket.take(now + Duration::from_secs(4)).allowed);
+            assert!(bucket.take(now + Duration::from_secs(5)).allowed);
+            assert!(!bucket.take(now + Duration::from_secs(5)).allowed);
+            let later = now + Duration::from_secs(3600);
+            assert_eq!(bucket.tokens_at(later), 2.0);
+            assert!(bucket.is_full(later));
+        }
+
+        #[test]
+        fn reset_counts_down_to_the_next_token() {
+            let now = Instant::now();
+            let mut bucket = Bucket::new(limit(1, 60), now);
+            bucket.take(now);
+            assert_eq!(bucket.take(now + Duration::from_secs(45)).reset_seconds, 15);
+        }
+
+        #[actix_rt::test]
+        async fn pruning_forgets_only_refilled_buckets() {
+            let store = InMemoryRateLimitStore::default();
+            store.take("slow", limit(1, 3600)).await.unwrap();
+            store.take("fast", limit(1_000_000, 1)).await.unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+            store.prune().await;
+            let buckets = store.buckets.lock().unwrap();
+            assert!(buckets.contains_key("slow"));
+            assert!(!buckets.contains_key("fast"));
+        }
+    }
+}
@@ -0,0 +1,277 @@
+pub mod request_id_middelware {
+    use actix_web::{
+        dev::{Service, ServiceRequest, ServiceResponse, Transform},
+        http::{HeaderMap, HeaderName, HeaderValue},
+        Error,
+    };
+    use futures::future::{ok, FutureExt, LocalBoxFuture, Ready};
+    use opentelemetry::{global, propagation::Extractor};
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        task::{Context, Poll},
+    };
+    use tracing::{Instrument, Span};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use uuid::Uuid;
+
+    const REQUEST_ID_HEADER: &str = "x-request-id";
+    const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+    /// Ids land in every log line and are echoed back, only plain tokens are accepted.
+    fn is_valid(request_id: &str) -> bool {
+        !request_id.is_empty()
+            && request_id.len() <= MAX_REQUEST_ID_LENGTH
+            && request_id
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || "-_.:".contains(character))
+    }
+
+    /// Reads the W3C `traceparent` and `tracestate` headers.
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl<'a> Extractor for HeaderExtractor<'a> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(HeaderName::as_str).collect()
+        }
+    }
+
+    /// Opens the request span every span and event of the request nests under, so they
+    /// all carry its `request_id`, and returns the id as `X-Request-Id`. An id sent by a
+    /// proxy or another service is kept so logs can be followed across them, and so is
+    /// the trace it belongs to when traces are exported.
+    pub struct RequestTracing;
+
+    impl<S, B> Transform<S> for RequestTracing
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type InitError = ();
+        type Transform = RequestTracingMiddleware<S>;
+        type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+        fn new_transform(&self, service: S) -> Self::Future {
+            ok(RequestTracingMiddleware {
+                service: Rc::new(RefCell::new(service)),
+            })
+        }
+    }
+
+    pub struct RequestTracingMiddleware<S> {
+        service: Rc<RefCell<S>>,
+    }
+
+    impl<S, B> Service for RequestTracingMiddleware<S>
+    where
+        S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>
+            + 'static,
+        S::Future: 'static,
+        B: 'static,
+    {
+        type Request = ServiceRequest;
+        type Response = ServiceResponse<B>;
+        type Error = Error;
+        type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.service.borrow_mut().poll_ready(context)
+        }
+
+        fn call(&mut self, request: ServiceRequest) -> Self::Future {
+            let request_id = request
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .filter(|request_id| is_valid(request_id))
+                .map(String::from)
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let span = tracing::info_span!(
+                "Request",
+                otel.name = %format!(
+                    "{} {}",
+                    request.method(),
+                    request
+                        .match_pattern()
+                        .unwrap_or_else(|| request.path().to_string())
+                ),
+                otel.kind = "server",
+                request_id = %request_id,
+                method = %request.method(),
+                request_path = %request.path(),
+                user_agent = %request
+                    .headers()
+                    .get("User-Agent")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default(),
+                client_ip_address = %request
+                    .connection_info()
+                    .realip_remote_addr()
+                    .unwrap_or_default(),
+                status_code = tracing::field::Empty,
+            );
+            span.set_parent(global::get_text_map_propagator(|propagator| {
+                propagator.extract(&HeaderExtractor(request.headers()))
+            }));
+            let response = span.in_scope(|| self.service.borrow_mut().call(request));
+            async move {
+                let response = response.await;
+                let status = match &response {
+                    Ok(response) => response.status(),
+                    Err(error) => error.as_response_error().status_code(),
+                };
+                Span::current().record("status_code", &status.as_u16());
+                response.map(|mut response| {
+                    // validated or generated above, always a valid header value
+                    if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+                        response
+                            .headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), request_id);
+                    }
+                    response
+                })
+            }
+            .instrument(span)
+            .boxed_local()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use actix_web::{test, web, App, HttpResponse};
+        use std::{
+            fmt::Debug,
+            sync::{Arc, Mutex},
+        };
+        use tracing::{
+            field::{Field, Visit},
+            span::{Attributes, Id},
+            Event, Subscriber,
+        };
+        use tracing_subscriber::{
+            layer::{Context, SubscriberExt},
+            registry::{Extensions, LookupSpan},
+            Layer, Registry,
+        };
+
+        /// The `request_id` of the span an event was logged in, or of its closest parent.
+        #[derive(Clone, Default)]
+        struct SeenRequestIds(Arc<Mutex<Vec<Option<String>>>>);
+
+        struct RequestId(String);
+
+        struct RequestIdVisitor(Option<String>);
+
+        impl Visit for RequestIdVisitor {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                if field.name() == "request_id" {
+                    self.0 = Some(format!("{:?}", value));
+                }
+            }
+        }
+
+        impl<S> Layer<S> for SeenRequestIds
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            fn new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+                let mut visitor = RequestIdVisitor(None);
+                attributes.record(&mut visitor);
+                if let (Some(request_id), Some(span)) = (visitor.0, context.span(id)) {
+                    span.extensions_mut().insert(RequestId(request_id));
+                }
+            }
+
+            fn on_event(&self, _event: &Event<'_>, context: Context<'_, S>) {
+                let request_id_of = |extensions: &Extensions| {
+                    extensions
+                        .get::<RequestId>()
+                        .map(|request_id| request_id.0.clone())
+                };
+                let request_id = context.lookup_current().and_then(|span| {
+                    request_id_of(&span.extensions()).or_else(|| {
+                        span.parents()
+                            .find_map(|parent| request_id_of(&parent.extensions()))
+                    })
+                });
+                self.0.lock().unwrap().push(request_id);
+            }
+        }
+
+        async fn handled() -> HttpResponse {
+            tracing::info!("handled");
+            HttpResponse::Ok().finish()
+        }
+
+        /// The id sent back and the one the handler logged under.
+        async fn call(request_id: Option<&str>) -> (String, Option<String>) {
+            let seen = SeenRequestIds::default();
+            let _subscriber =
+                tracing::subscriber::set_default(Registry::default().with(seen.clone()));
+            let mut app = test::init_service(
+                App::new()
+                    .wrap(RequestTracing)
+                    .route("/", web::get().to(handled)),
+            )
+            .await;
+            let mut request = test::TestRequest::get().uri("/");
+            if let Some(request_id) = request_id {
+                request = request.header(REQUEST_ID_HEADER, request_id);
+            }
+            let response = test::call_service(&mut app, request.to_request()).await;
+            let sent_back = response
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let logged = seen.0.lock().unwrap().clone();
+            assert_eq!(logged.len(), 1);
+            (sent_back, logged[0].clone())
+        }
+
+        #[test]
+        fn only_plain_tokens_are_valid_ids() {
+            assert!(is_valid("3f2c9a1e-7b1d-4c3e-9a8f-0d2b5e6c7a81"));
+            assert!(is_valid("gateway:req_42.1"));
+            assert!(!is_valid(""));
+            assert!(!is_valid("two words"));
+            assert!(!is_valid("id\"injected"));
+            assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+            assert!(is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH)));
+        }
+
+        #[actix_rt::test]
+        async fn an_incoming_id_is_propagated_and_sent_back() {
+            let (sent_back, logged) = call(Some("gateway-42")).await;
+            assert_eq!(sent_back, "gateway-42");
+            assert_eq!(logged.as_deref(), Some("gateway-42"));
+        }
+
+        #[actix_rt::test]
+        async fn an_id_is_generated_when_none_is_sent() {
+            let (sent_back, logged) = call(None).await;
+            assert!(Uuid::parse_str(&sent_back).is_ok());
+            assert_eq!(logged, Some(sent_back.clone()));
+            assert_ne!(call(None).await.0, sent_back);
+        }
+
+        #[actix_rt::test]
+        async fn an_invalid_id_is_replaced() {
+            let (sent_back, logged) = call(Some("not a token")).await;
+            assert!(Uuid::parse_str(&sent_back).is_ok());
+            assert_eq!(logged, Some(sent_back));
+        }
+    }
+}
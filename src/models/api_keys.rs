@@ -0,0 +1,148 @@
+use super::indexes::IndexSpec;
+use bson::{doc, oid::ObjectId, Document};
+use chrono::{DateTime, Utc};
+use mongodb::{
+    error::Error,
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    results::{InsertOneResult, UpdateResult},
+    Collection, Cursor,
+};
+
+#[derive(Debug, Clone)]
+pub struct ApiKeysCollection {
+    collection: Collection,
+}
+
+impl ApiKeysCollection {
+    pub fn new(collection: Collection) -> ApiKeysCollection {
+        ApiKeysCollection { collection }
+    }
+
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![IndexSpec::unique(doc! {"prefix": 1})]
+    }
+
+    pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("ApiKeysCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_all(&self) -> Result<Cursor, Error> {
+        operation!("ApiKeysCollection", "find_all", {
+            Ok(self.collection.find(None, None).await?)
+        })
+    }
+
+    pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("ApiKeysCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap()
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_one_by_prefix(&self, prefix: &str) -> Result<Option<Document>, Error> {
+        operation!("ApiKeysCollection", "find_one_by_prefix", {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "prefix":prefix
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn revoke_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("ApiKeysCollection", "revoke_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "revoked_at":{"$exists":false}
+                    },
+                    doc! {
+                        "$set":{
+                            "revoked_at":Utc::now()
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    /// Brings the expiry forward to `expires_at`, never pushes it back.
+    pub async fn expire_one(
+        &self,
+        id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<Document>, Error> {
+        operation!("ApiKeysCollection", "expire_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap()
+                    },
+                    doc! {
+                        "$min":{
+                            "expires_at":expires_at
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn touch_one(&self, id: &ObjectId) -> Result<UpdateResult, Error> {
+        operation!("ApiKeysCollection", "touch_one", entity_id = id, {
+            Ok(self
+                .collection
+                .update_one(
+                    doc! {
+                        "_id":id
+                    },
+                    doc! {
+                        "$set":{
+                            "last_used_at":Utc::now()
+                        }
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use super::indexes::IndexSpec;
+use bson::{doc, oid::ObjectId, Document};
+use mongodb::{error::Error, results::InsertOneResult, Collection, Cursor};
+
+#[derive(Debug, Clone)]
+pub struct AssetsCollection {
+    collection: Collection,
+}
+
+impl AssetsCollection {
+    pub fn new(collection: Collection) -> AssetsCollection {
+        AssetsCollection { collection }
+    }
+
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::new(doc! {"key": 1}),
+            IndexSpec::new(doc! {"thumbnail": 1}),
+            IndexSpec::new(doc! {"medium": 1}),
+        ]
+    }
+
+    pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("AssetsCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_all(&self) -> Result<Cursor, Error> {
+        operation!("AssetsCollection", "find_all", {
+            Ok(self.collection.find(None, None).await?)
+        })
+    }
+
+    pub async fn delete_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("AssetsCollection", "delete_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_delete(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap()
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("AssetsCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap()
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_one_by_key(&self, key: &str) -> Result<Option<Document>, Error> {
+        operation!("AssetsCollection", "find_one_by_key", {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "$or":[
+                            {"key":key},
+                            {"thumbnail":key},
+                            {"medium":key}
+                        ]
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+}
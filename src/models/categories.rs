@@ -1,8 +1,10 @@
+use super::indexes::IndexSpec;
 use bson::{doc, oid::ObjectId, Document};
+use chrono::{DateTime, Utc};
 use mongodb::{
     error::Error,
     options::{FindOneAndUpdateOptions, ReturnDocument},
-    results::InsertOneResult,
+    results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection, Cursor,
 };
 
@@ -16,54 +18,76 @@ impl CategoriesCollection {
         CategoriesCollection { collection }
     }
 
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![IndexSpec::unique(doc! {"name": 1, "deleted_at": 1})]
+    }
+
     pub async fn find_one<T>(&self, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("CategoriesCollection", "find_one", {
+            let mut filter = bson::to_bson(&document)
+                .unwrap()
+                .as_document()
+                .unwrap()
+                .clone();
+            filter.insert("deleted_at", doc! {"$exists": false});
+            Ok(self.collection.find_one(filter, None).await?)
+        })
     }
 
     pub async fn find_all(&self) -> Result<Cursor, Error> {
-        Ok(self.collection.find(None, None).await?)
+        operation!("CategoriesCollection", "find_all", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .insert_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("CategoriesCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_one(&self, user_id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_delete(
-                doc! {
-                "_id":ObjectId::with_string(user_id).unwrap()
+        operation!("CategoriesCollection", "delete_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                    "_id":ObjectId::with_string(user_id).unwrap(),
+                    "deleted_at":{"$exists":false}
+                        },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
                     },
-                None,
-            )
-            .await?)
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn update_one<T>(
@@ -74,38 +98,169 @@ impl CategoriesCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(catagory_id).unwrap()
-                },
-                doc! {
-                      "$set":bson::to_bson(&document)
-                        .unwrap()
-                        .as_document()
-                        .unwrap()
-                        .clone()
-
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("CategoriesCollection", "update_one", entity_id = catagory_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(catagory_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":bson::to_bson(&document)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
+
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_image<T>(&self, id: &str, image: T) -> Result<Option<Document>, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("CategoriesCollection", "update_image", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "image":bson::to_bson(&image)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
+
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                None,
-            )
-            .await?)
+        operation!("CategoriesCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed(&self) -> Result<Cursor, Error> {
+        operation!("CategoriesCollection", "find_trashed", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("CategoriesCollection", "find_trashed_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn restore_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("CategoriesCollection", "restore_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    doc! {
+                        "$unset":{
+                            "deleted_at":""
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<DeleteResult, Error> {
+        operation!("CategoriesCollection", "purge_deleted_before", {
+            Ok(self
+                .collection
+                .delete_many(
+                    doc! {
+                        "deleted_at":{"$lt":cutoff}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Documents as stored, trashed ones included. Only migrations bypass the models.
+    pub async fn find_documents(&self, filter: Document) -> Result<Cursor, Error> {
+        operation!("CategoriesCollection", "find_documents", {
+            Ok(self.collection.find(filter, None).await?)
+        })
+    }
+
+    pub async fn replace_document(
+        &self,
+        id: &ObjectId,
+        document: Document,
+    ) -> Result<UpdateResult, Error> {
+        operation!("CategoriesCollection", "replace_document", entity_id = id, {
+            Ok(self
+                .collection
+                .replace_one(
+                    doc! {
+                        "_id":id
+                    },
+                    document,
+                    None,
+                )
+                .await?)
+        })
     }
 }
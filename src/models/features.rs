@@ -1,8 +1,10 @@
+use super::indexes::IndexSpec;
 use bson::{doc, oid::ObjectId, Document};
+use chrono::{DateTime, Utc};
 use mongodb::{
     error::Error,
     options::{FindOneAndUpdateOptions, ReturnDocument},
-    results::InsertOneResult,
+    results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection, Cursor,
 };
 
@@ -17,54 +19,76 @@ impl FeaturesCollection {
         FeaturesCollection { collection }
     }
 
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![IndexSpec::new(doc! {"wireframes._id": 1})]
+    }
+
     pub async fn find_one<T>(&self, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("FeaturesCollection", "find_one", {
+            let mut filter = bson::to_bson(&document)
+                .unwrap()
+                .as_document()
+                .unwrap()
+                .clone();
+            filter.insert("deleted_at", doc! {"$exists": false});
+            Ok(self.collection.find_one(filter, None).await?)
+        })
     }
 
     pub async fn find_all(&self) -> Result<Cursor, Error> {
-        Ok(self.collection.find(None, None).await?)
+        operation!("FeaturesCollection", "find_all", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .insert_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("FeaturesCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_one(&self, user_id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_delete(
-                doc! {
-                "_id":ObjectId::with_string(user_id).unwrap()
+        operation!("FeaturesCollection", "delete_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                    "_id":ObjectId::with_string(user_id).unwrap(),
+                    "deleted_at":{"$exists":false}
+                        },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
                     },
-                None,
-            )
-            .await?)
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn update_one<T>(
@@ -75,57 +99,115 @@ impl FeaturesCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(feature_id).unwrap()
-                },
-                doc! {
-                      "$set":bson::to_bson(&document)
-                        .unwrap()
-                        .as_document()
-                        .unwrap()
-                        .clone()
+        operation!("FeaturesCollection", "update_one", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(feature_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":bson::to_bson(&document)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
+
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_image<T>(&self, id: &str, image: T) -> Result<Option<Document>, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("FeaturesCollection", "update_image", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "image":bson::to_bson(&image)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                None,
-            )
-            .await?)
+        operation!("FeaturesCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_ids(&self, ids: Vec<ObjectId>) -> Result<Cursor, Error> {
+        operation!("FeaturesCollection", "find_by_ids", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "_id":{
+                            "$in":ids
+                        },
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn find_wireframe_by_id(&self, id: &str) -> Result<Cursor<Document>, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![doc! {
-                    "$match":{
-                         "wireframes":{
-                              "$elemMatch": {
-                                   "_id": ObjectId::with_string(id).unwrap()
-                                }
-                            }
-                    }
-                }],
-                None,
-            )
-            .await?)
+        operation!("FeaturesCollection", "find_wireframe_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![doc! {
+                        "$match":{
+                             "wireframes":{
+                                  "$elemMatch": {
+                                       "_id": ObjectId::with_string(id).unwrap()
+                                    }
+                                },
+                             "deleted_at":{"$exists":false}
+                        }
+                    }],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn add_wireframe(
@@ -133,26 +215,29 @@ impl FeaturesCollection {
         feautre_id: &str,
         wireframes: Vec<Document>,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(feautre_id).unwrap()
-                },
-                doc! {
-                  "$push":{
-                    "wireframes":{
-                        "$each":wireframes
-                        }
-                  }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("FeaturesCollection", "add_wireframe", entity_id = feautre_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(feautre_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$push":{
+                        "wireframes":{
+                            "$each":wireframes
+                            }
+                      }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_wireframe<T>(
@@ -163,27 +248,151 @@ impl FeaturesCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(feautre_id).unwrap()
-                },
-                doc! {
-                  "$pull":{
-                      "wireframes":bson::to_bson(&document)
-                      .unwrap()
-                      .as_document()
-                      .unwrap()
-                      .clone(),
-                  }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("FeaturesCollection", "delete_wireframe", entity_id = feautre_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(feautre_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                          "wireframes":bson::to_bson(&document)
+                          .unwrap()
+                          .as_document()
+                          .unwrap()
+                          .clone(),
+                      }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_wireframes(
+        &self,
+        feature_id: &str,
+        wireframes: Vec<Document>,
+    ) -> Result<Option<Document>, Error> {
+        operation!("FeaturesCollection", "update_wireframes", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(feature_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$set":{
+                          "wireframes":wireframes
+                         }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed(&self) -> Result<Cursor, Error> {
+        operation!("FeaturesCollection", "find_trashed", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("FeaturesCollection", "find_trashed_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn restore_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("FeaturesCollection", "restore_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    doc! {
+                        "$unset":{
+                            "deleted_at":""
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<DeleteResult, Error> {
+        operation!("FeaturesCollection", "purge_deleted_before", {
+            Ok(self
+                .collection
+                .delete_many(
+                    doc! {
+                        "deleted_at":{"$lt":cutoff}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Documents as stored, trashed ones included. Only migrations bypass the models.
+    pub async fn find_documents(&self, filter: Document) -> Result<Cursor, Error> {
+        operation!("FeaturesCollection", "find_documents", {
+            Ok(self.collection.find(filter, None).await?)
+        })
+    }
+
+    pub async fn replace_document(
+        &self,
+        id: &ObjectId,
+        document: Document,
+    ) -> Result<UpdateResult, Error> {
+        operation!("FeaturesCollection", "replace_document", entity_id = id, {
+            Ok(self
+                .collection
+                .replace_one(
+                    doc! {
+                        "_id":id
+                    },
+                    document,
+                    None,
+                )
+                .await?)
+        })
     }
 }
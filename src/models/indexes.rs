@@ -0,0 +1,56 @@
+use bson::{Bson, Document};
+use mongodb::error::{CommandError, Error, ErrorKind, WriteError, WriteFailure};
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// An index a collection's queries rely on. Unique indexes of soft deletable
+/// collections end with `deleted_at`: live documents all share its missing value while
+/// trashed ones each carry their own timestamp, so only live documents can collide.
+#[derive(Debug, Clone)]
+pub struct IndexSpec {
+    pub keys: Document,
+    pub unique: bool,
+}
+
+impl IndexSpec {
+    pub fn new(keys: Document) -> IndexSpec {
+        IndexSpec {
+            keys,
+            unique: false,
+        }
+    }
+
+    pub fn unique(keys: Document) -> IndexSpec {
+        IndexSpec { keys, unique: true }
+    }
+
+    /// The name the server would give the index, e.g. `template_id_1_revision_-1`.
+    pub fn name(&self) -> String {
+        self.keys
+            .iter()
+            .map(|(field, direction)| match direction {
+                Bson::Int32(direction) => format!("{}_{}", field, direction),
+                Bson::Int64(direction) => format!("{}_{}", field, direction),
+                Bson::String(kind) => format!("{}_{}", field, kind),
+                other => format!("{}_{}", field, other),
+            })
+            .collect::<Vec<String>>()
+            .join("_")
+    }
+}
+
+/// Whether a write was refused by a unique index.
+pub fn is_duplicate_key(error: &Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::CommandError(CommandError { code, .. }) => *code == DUPLICATE_KEY,
+        ErrorKind::WriteError(WriteFailure::WriteError(WriteError { code, .. })) => {
+            *code == DUPLICATE_KEY
+        }
+        ErrorKind::BulkWriteError(failure) => failure
+            .write_errors
+            .iter()
+            .flatten()
+            .any(|write_error| write_error.code == DUPLICATE_KEY),
+        _ => false,
+    }
+}
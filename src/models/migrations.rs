@@ -0,0 +1,38 @@
+use mongodb::{error::Error, results::InsertOneResult, Collection, Cursor};
+
+#[derive(Debug, Clone)]
+pub struct MigrationsCollection {
+    collection: Collection,
+}
+
+impl MigrationsCollection {
+    pub fn new(collection: Collection) -> MigrationsCollection {
+        MigrationsCollection { collection }
+    }
+
+    pub async fn find_all(&self) -> Result<Cursor, Error> {
+        operation!("MigrationsCollection", "find_all", {
+            Ok(self.collection.find(None, None).await?)
+        })
+    }
+
+    /// Keyed by version, recording a version twice fails on the `_id` index.
+    pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("MigrationsCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
+    }
+}
@@ -1,11 +1,80 @@
+/// Runs the body of a collection method in a `Collection::operation` span carrying the
+/// collection, operation and entity id, logs the error it fails with on that span, and
+/// times it into `mongodb_operation_duration_seconds`.
+macro_rules! operation {
+    ($collection:literal, $operation:literal $(, entity_id = $entity_id:expr)?, $body:block) => {{
+        let _timer = crate::services::metrics::operation_timer($collection, $operation);
+        let span = tracing::info_span!(
+            concat!($collection, "::", $operation),
+            collection = $collection,
+            operation = $operation,
+            $(entity_id = %$entity_id,)?
+        );
+        tracing::Instrument::instrument(
+            async move {
+                let result: Result<_, mongodb::error::Error> = async move $body.await;
+                if let Err(error) = &result {
+                    tracing::error!(error = %error);
+                }
+                result
+            },
+            span,
+        )
+        .await
+    }};
+}
+
+pub mod api_keys;
+pub mod assets;
 pub mod categories;
 pub mod features;
+pub mod indexes;
+pub mod migrations;
 pub mod projects;
+pub mod prototype_revisions;
 pub mod prototypes;
+pub mod soft_delete;
 pub mod templates;
 
+pub use api_keys::ApiKeysCollection;
+pub use assets::AssetsCollection;
 pub use categories::CategoriesCollection;
 pub use features::FeaturesCollection;
+pub use migrations::MigrationsCollection;
 pub use projects::ProjectsCollection;
+pub use prototype_revisions::PrototypeRevisionsCollection;
 pub use prototypes::PrototypesCollection;
 pub use templates::TemplatesCollection;
+
+#[cfg(test)]
+mod tests {
+    use crate::services::metrics::render;
+    use mongodb::error::Error;
+    use std::io;
+
+    async fn succeeding(id: &str) -> Result<String, Error> {
+        operation!("ModelsTestCollection", "succeeding", entity_id = id, {
+            Ok(id.to_uppercase())
+        })
+    }
+
+    async fn failing() -> Result<(), Error> {
+        operation!("ModelsTestCollection", "failing", {
+            Err(io::Error::other("unreachable"))?;
+            Ok(())
+        })
+    }
+
+    #[actix_rt::test]
+    async fn operations_return_what_their_body_returns_and_are_timed() {
+        assert_eq!(succeeding("abc").await.unwrap(), "ABC");
+        assert!(failing().await.is_err());
+        let metrics = render().unwrap();
+        for operation in ["succeeding", "failing"] {
+            assert!(metrics.contains(&format!(
+                "collection=\"ModelsTestCollection\",operation=\"{}\"",
+                operation
+            )));
+        }
+    }
+}
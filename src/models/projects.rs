@@ -1,8 +1,11 @@
+use super::indexes::IndexSpec;
+use super::soft_delete::live_lookup;
 use bson::{doc, oid::ObjectId, Document};
+use chrono::{DateTime, Utc};
 use mongodb::{
     error::Error,
     options::{FindOneAndUpdateOptions, ReturnDocument},
-    results::InsertOneResult,
+    results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection, Cursor,
 };
 
@@ -16,93 +19,119 @@ impl ProjectsCollection {
         ProjectsCollection { collection }
     }
 
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::new(doc! {"client_id": 1}),
+            IndexSpec::new(doc! {"template": 1}),
+            IndexSpec::new(doc! {"features": 1}),
+        ]
+    }
+
     pub async fn find_one<T>(&self, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("ProjectsCollection", "find_one", {
+            let mut filter = bson::to_bson(&document)
+                .unwrap()
+                .as_document()
+                .unwrap()
+                .clone();
+            filter.insert("deleted_at", doc! {"$exists": false});
+            Ok(self.collection.find_one(filter, None).await?)
+        })
     }
 
     pub async fn find_all(&self) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
-                    doc! {
-                        "$lookup": {
-                               "from": "Templates",
-                               "localField": "template",
-                               "foreignField": "_id",
-                               "as": "template"
+        operation!("ProjectsCollection", "find_all", {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                             "$match": {
+                                    "deleted_at":{"$exists":false}
+                                }
+                        },
+                        live_lookup("Templates", "template", "template"),
+                        doc! {
+                           "$unwind":
+                            {
+                                "path": "$template",
+                                "preserveNullAndEmptyArrays": true
                             }
-                    },
-                    doc! {
-                       "$unwind":
-                        {
-                            "path": "$template",
-                            "preserveNullAndEmptyArrays": true
-                        }
-                    },
-                    doc! {
-                         "$lookup": {
-                                "from": "Features",
-                                "localField": "template.features",
-                                "foreignField": "_id",
-                                "as": "template.features"
-                             }
+                        },
+                        live_lookup("Features", "template.features", "template.features"),
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
+    }
 
-                    },
-                    doc! {
-                        "$lookup": {
-                               "from": "Features",
-                               "localField": "features",
-                               "foreignField": "_id",
-                               "as": "features"
+    /// Live projects grouped as `{_id: <state>, count}`.
+    pub async fn count_by_state(&self) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "count_by_state", {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                            "$match": {
+                                "deleted_at":{"$exists":false}
                             }
-                    },
-                ],
-                None,
-            )
-            .await?)
+                        },
+                        doc! {
+                            "$group": {
+                                "_id":"$state",
+                                "count":{"$sum":1}
+                            }
+                        },
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .insert_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("ProjectsCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_one(&self, user_id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_delete(
-                doc! {
-                "_id":ObjectId::with_string(user_id).unwrap()
-                    },
-                None,
-            )
-            .await?)
+        operation!("ProjectsCollection", "delete_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                    "_id":ObjectId::with_string(user_id).unwrap(),
+                    "deleted_at":{"$exists":false}
+                        },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn update_one<T>(
@@ -114,139 +143,107 @@ impl ProjectsCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(user_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-                        "name":name,
-                        "image":bson::to_bson(&image)
-                        .unwrap()
-                        .as_document()
-                        .unwrap()
-                        .clone()
-                      }
+        operation!("ProjectsCollection", "update_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(user_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                            "name":name,
+                            "image":bson::to_bson(&image)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
+                          }
 
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                None,
-            )
-            .await?)
-    }
-
-    pub async fn refactor_one_by_id(&self, id: &str) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
+        operation!("ProjectsCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
                     doc! {
-                         "$match": {
-                                "_id":ObjectId::with_string(id).unwrap()
-                            }
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
                     },
-                    doc! {
-                        "$lookup": {
-                               "from": "Templates",
-                               "localField": "template",
-                               "foreignField": "_id",
-                               "as": "template"
-                            }
-                    },
-                    doc! {
-                       "$unwind":
-                        {
-                            "path": "$template",
-                            "preserveNullAndEmptyArrays": true
-                        }
-                    },
-                    doc! {
-                         "$lookup": {
-                                "from": "Features",
-                                "localField": "template.features",
-                                "foreignField": "_id",
-                                "as": "template.features"
-                             }
+                    None,
+                )
+                .await?)
+        })
+    }
 
-                    },
-                    doc! {
-                        "$lookup": {
-                               "from": "Features",
-                               "localField": "features",
-                               "foreignField": "_id",
-                               "as": "features"
+    pub async fn refactor_one_by_id(&self, id: &str) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "refactor_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                             "$match": {
+                                    "_id":ObjectId::with_string(id).unwrap(),
+                                    "deleted_at":{"$exists":false}
+                                }
+                        },
+                        live_lookup("Templates", "template", "template"),
+                        doc! {
+                           "$unwind":
+                            {
+                                "path": "$template",
+                                "preserveNullAndEmptyArrays": true
                             }
-                    },
-                ],
-                None,
-            )
-            .await?)
+                        },
+                        live_lookup("Features", "template.features", "template.features"),
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn refactor_one_by_client_id(&self, client_id: &str) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
-                    doc! {
-                         "$match": {
-                                "client_id":ObjectId::with_string(client_id).unwrap()
-                            }
-                    },
-                    doc! {
-                        "$lookup": {
-                               "from": "Templates",
-                               "localField": "template",
-                               "foreignField": "_id",
-                               "as": "template"
+        operation!("ProjectsCollection", "refactor_one_by_client_id", entity_id = client_id, {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                             "$match": {
+                                    "client_id":ObjectId::with_string(client_id).unwrap(),
+                                    "deleted_at":{"$exists":false}
+                                }
+                        },
+                        live_lookup("Templates", "template", "template"),
+                        doc! {
+                           "$unwind":
+                            {
+                                "path": "$template",
+                                "preserveNullAndEmptyArrays": true
                             }
-                    },
-                    doc! {
-                       "$unwind":
-                        {
-                            "path": "$template",
-                            "preserveNullAndEmptyArrays": true
-                        }
-                    },
-                    doc! {
-                         "$lookup": {
-                                "from": "Features",
-                                "localField": "template.features",
-                                "foreignField": "_id",
-                                "as": "template.features"
-                             }
-
-                    },
-                    doc! {
-                        "$lookup": {
-                               "from": "Features",
-                               "localField": "features",
-                               "foreignField": "_id",
-                               "as": "features"
-                            }
-                    },
-                ],
-                None,
-            )
-            .await?)
+                        },
+                        live_lookup("Features", "template.features", "template.features"),
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn update_state(
@@ -254,25 +251,28 @@ impl ProjectsCollection {
         user_id: &str,
         state: &str,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(user_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-                          "state":state
-                      }
+        operation!("ProjectsCollection", "update_state", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(user_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "state":state
+                          }
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_full_build(
@@ -280,25 +280,28 @@ impl ProjectsCollection {
         project_id: &str,
         full_build: &str,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-                          "delivrable.full_build":full_build
-                      }
+        operation!("ProjectsCollection", "update_full_build", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "delivrable.full_build":full_build
+                          }
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_propsal<T>(
@@ -309,58 +312,100 @@ impl ProjectsCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-                          "proposal":bson::to_bson(&propsal)
-                          .unwrap()
-                          .as_document()
-                          .unwrap()
-                          .clone()
-                      }
+        operation!("ProjectsCollection", "update_propsal", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "proposal":bson::to_bson(&propsal)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_mvp<T>(&self, project_id: &str, mvp: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-                          "delivrable.mvp":bson::to_bson(&mvp)
-                          .unwrap()
-                          .as_document()
-                          .unwrap()
-                          .clone()
-                      }
+        operation!("ProjectsCollection", "update_mvp", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "delivrable.mvp":bson::to_bson(&mvp)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
+
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_specification_file<T>(
+        &self,
+        project_id: &str,
+        specification: T,
+    ) -> Result<Option<Document>, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("ProjectsCollection", "update_specification_file", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
+                              "delivrable.specification":bson::to_bson(&specification)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_design<T>(
@@ -371,30 +416,33 @@ impl ProjectsCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                      "$set":{
-
-                          "delivrable.design":bson::to_bson(&design)
-                          .unwrap()
-                          .as_document()
-                          .unwrap()
-                          .clone()
-                      }
+        operation!("ProjectsCollection", "update_design", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                              "delivrable.design":bson::to_bson(&design)
+                              .unwrap()
+                              .as_document()
+                              .unwrap()
+                              .clone()
+                          }
+
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn add_feature(
@@ -402,26 +450,29 @@ impl ProjectsCollection {
         project_id: &str,
         features_id: Vec<ObjectId>,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                  "$push":{
-                      "features":{
-                        "$each":features_id
-                        }
-                     }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("ProjectsCollection", "add_feature", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$push":{
+                          "features":{
+                            "$each":features_id
+                            }
+                         }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_feature(
@@ -429,24 +480,236 @@ impl ProjectsCollection {
         project_id: &str,
         feature_id: &str,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(project_id).unwrap()
-                },
-                doc! {
-                  "$pull":{
-                    "features":ObjectId::with_string(feature_id).unwrap()
-                  }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("ProjectsCollection", "delete_feature", entity_id = project_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                        "features":ObjectId::with_string(feature_id).unwrap()
+                      }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_feature_id(&self, feature_id: &ObjectId) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "find_by_feature_id", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "features":feature_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_template_ids(&self, template_ids: Vec<ObjectId>) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "find_by_template_ids", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "template":{
+                            "$in":template_ids
+                        },
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// `price` comes off the total of every project the feature is pulled from, in the
+    /// same update so a total never lists a feature it no longer has.
+    pub async fn pull_feature_from_all<T>(
+        &self,
+        feature_id: &ObjectId,
+        price: f64,
+        notice: T,
+    ) -> Result<UpdateResult, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("ProjectsCollection", "pull_feature_from_all", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .update_many(
+                    doc! {
+                        "features":feature_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                        "features":feature_id
+                      },
+                      "$inc":{
+                        "total_price":-price
+                      },
+                      "$push":{
+                        "notices":bson::to_bson(&notice).unwrap()
+                      }
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Live documents as stored, ids not joined.
+    pub async fn find_all_raw(&self) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "find_all_raw", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Replaces the feature list, and the total when one is given, in a single update.
+    pub async fn update_features(
+        &self,
+        project_id: &str,
+        features_id: Vec<ObjectId>,
+        total_price: Option<f64>,
+    ) -> Result<Option<Document>, Error> {
+        operation!("ProjectsCollection", "update_features", entity_id = project_id, {
+            let mut update = doc! {
+                "features":features_id
+            };
+            if let Some(total_price) = total_price {
+                update.insert("total_price", total_price);
+            }
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(project_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$set":update
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed(&self) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "find_trashed", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("ProjectsCollection", "find_trashed_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn restore_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("ProjectsCollection", "restore_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    doc! {
+                        "$unset":{
+                            "deleted_at":""
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<DeleteResult, Error> {
+        operation!("ProjectsCollection", "purge_deleted_before", {
+            Ok(self
+                .collection
+                .delete_many(
+                    doc! {
+                        "deleted_at":{"$lt":cutoff}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Documents as stored, trashed ones included. Only migrations bypass the models.
+    pub async fn find_documents(&self, filter: Document) -> Result<Cursor, Error> {
+        operation!("ProjectsCollection", "find_documents", {
+            Ok(self.collection.find(filter, None).await?)
+        })
+    }
+
+    pub async fn replace_document(
+        &self,
+        id: &ObjectId,
+        document: Document,
+    ) -> Result<UpdateResult, Error> {
+        operation!("ProjectsCollection", "replace_document", entity_id = id, {
+            Ok(self
+                .collection
+                .replace_one(
+                    doc! {
+                        "_id":id
+                    },
+                    document,
+                    None,
+                )
+                .await?)
+        })
     }
-    
 }
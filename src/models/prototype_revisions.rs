@@ -0,0 +1,94 @@
+use super::indexes::IndexSpec;
+use bson::{doc, oid::ObjectId, Document};
+use mongodb::{
+    error::Error,
+    options::{FindOneOptions, FindOptions},
+    results::InsertOneResult,
+    Collection, Cursor,
+};
+
+#[derive(Debug, Clone)]
+pub struct PrototypeRevisionsCollection {
+    collection: Collection,
+}
+
+impl PrototypeRevisionsCollection {
+    pub fn new(collection: Collection) -> PrototypeRevisionsCollection {
+        PrototypeRevisionsCollection { collection }
+    }
+
+    /// Unique, two saves racing for the same revision number cannot both get it.
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![IndexSpec::unique(doc! {"template_id": 1, "revision": -1})]
+    }
+
+    pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("PrototypeRevisionsCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_latest(&self, template_id: &str) -> Result<Option<Document>, Error> {
+        operation!("PrototypeRevisionsCollection", "find_latest", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap()
+                    },
+                    Some(
+                        FindOneOptions::builder()
+                            .sort(doc! {"revision": -1})
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_template_id(&self, template_id: &str) -> Result<Cursor, Error> {
+        operation!("PrototypeRevisionsCollection", "find_by_template_id", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap()
+                    },
+                    Some(FindOptions::builder().sort(doc! {"revision": -1}).build()),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_one_by_revision(
+        &self,
+        template_id: &str,
+        revision: i64,
+    ) -> Result<Option<Document>, Error> {
+        operation!("PrototypeRevisionsCollection", "find_one_by_revision", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "revision":revision
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+}
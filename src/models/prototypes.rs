@@ -1,8 +1,11 @@
-use bson::{doc, oid::ObjectId, Document};
+use super::indexes::IndexSpec;
+use super::soft_delete::live_lookup;
+use bson::{doc, oid::ObjectId, Bson, Document};
+use chrono::{DateTime, Utc};
 use mongodb::{
     error::Error,
-    options::{FindOneAndUpdateOptions, ReturnDocument},
-    results::InsertOneResult,
+    options::{FindOneAndReplaceOptions, FindOneAndUpdateOptions, ReturnDocument},
+    results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection, Cursor,
 };
 
@@ -16,145 +19,540 @@ impl PrototypesCollection {
         PrototypesCollection { collection }
     }
 
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::unique(doc! {"template_id": 1, "deleted_at": 1}),
+            IndexSpec::new(doc! {"prototype.feature_id": 1}),
+        ]
+    }
+
     pub async fn find_one<T>(&self, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("PrototypesCollection", "find_one", {
+            let mut filter = bson::to_bson(&document)
+                .unwrap()
+                .as_document()
+                .unwrap()
+                .clone();
+            filter.insert("deleted_at", doc! {"$exists": false});
+            Ok(self.collection.find_one(filter, None).await?)
+        })
     }
 
     pub async fn find_all(&self) -> Result<Cursor, Error> {
-        Ok(self.collection.find(None, None).await?)
+        operation!("PrototypesCollection", "find_all", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .insert_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("PrototypesCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_one(&self, user_id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_delete(
-                doc! {
-                "_id":ObjectId::with_string(user_id).unwrap()
+        operation!("PrototypesCollection", "delete_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                    "_id":ObjectId::with_string(user_id).unwrap(),
+                    "deleted_at":{"$exists":false}
+                        },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
                     },
-                None,
-            )
-            .await?)
+                    None,
+                )
+                .await?)
+        })
     }
 
-    pub async fn update_one<T>(&self, user_id: &str, document: T) -> Result<Option<Document>, Error>
+    pub async fn delete_by_template_id(
+        &self,
+        template_id: &str,
+    ) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "delete_by_template_id", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn replace_by_template_id<T>(
+        &self,
+        template_id: &str,
+        document: T,
+    ) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "template_id":ObjectId::with_string(user_id).unwrap()
-                },
-                doc! {
-                      "$set":bson::to_bson(&document)
+        operation!("PrototypesCollection", "replace_by_template_id", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_replace(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    bson::to_bson(&document)
                         .unwrap()
                         .as_document()
                         .unwrap()
-                        .clone()
+                        .clone(),
+                    Some(
+                        FindOneAndReplaceOptions::builder()
+                            .upsert(true)
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_one<T>(&self, user_id: &str, document: T) -> Result<Option<Document>, Error>
+    where
+        T: serde::Serialize,
+    {
+        operation!("PrototypesCollection", "update_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "template_id":ObjectId::with_string(user_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":bson::to_bson(&document)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_one_by_template_id(
+        &self,
+        template_id: &str,
+    ) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "find_one_by_template_id", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_layout(
+        &self,
+        template_id: &str,
+        nodes: Vec<(ObjectId, Bson)>,
+        viewport: Option<Bson>,
+        lanes: Option<Bson>,
+    ) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "update_layout", entity_id = template_id, {
+            let mut set = Document::new();
+            let mut array_filters = Vec::new();
+            for (index, (feature_id, layout)) in nodes.into_iter().enumerate() {
+                set.insert(format!("prototype.$[node{}].layout", index), layout);
+                array_filters.push(doc! {
+                    format!("node{}.feature_id", index): feature_id
+                });
+            }
+            if let Some(viewport) = viewport {
+                set.insert("viewport", viewport);
+            }
+            if let Some(lanes) = lanes {
+                set.insert("lanes", lanes);
+            }
+            // an empty `$set` is rejected by the server
+            if set.is_empty() {
+                return self.find_one_by_template_id(template_id).await;
+            }
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                        "$set": set
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .array_filters(if array_filters.is_empty() {
+                                None
+                            } else {
+                                Some(array_filters)
+                            })
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn update_hotspots(
+        &self,
+        template_id: &str,
+        feature_id: &str,
+        hotspots: Bson,
+    ) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "update_hotspots", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "template_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false},
+                        "prototype.feature_id":ObjectId::with_string(feature_id).unwrap()
+                    },
+                    doc! {
+                        "$set":{
+                            "prototype.$.hotspots":hotspots
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                None,
-            )
-            .await?)
+        operation!("PrototypesCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
     pub async fn refactor_one_by_id(&self, id: &str) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
-                    doc! {
+        operation!("PrototypesCollection", "refactor_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
 
-                            "$match": {
-                                "template_id":ObjectId::with_string(id).unwrap()
+                                "$match": {
+                                    "template_id":ObjectId::with_string(id).unwrap(),
+                                    "deleted_at":{"$exists":false}
+                                }
+
+
+                        },
+                        doc! {
+
+                            "$unwind":
+                            {
+                                "path": "$prototype",
+                                "preserveNullAndEmptyArrays": true
                             }
+                        },
+                        live_lookup("Features", "prototype.feature_id", "prototype.feature"),
+                        doc! {
+                            "$unset": "prototype.feature_id"
+                        },
+                        doc! {
+
+                            "$unwind":{
 
+                                "path": "$prototype.feature",
+                                "preserveNullAndEmptyArrays": true
+                            }
+                        },
+                        doc! {
+                          "$group":
+                            {
+                              "_id": "$_id",
+                              "template": {"$first": "$template_id"},
+                              "prototype": { "$push":  "$prototype" },
+                              "viewport": {"$first": "$viewport"},
+                              "lanes": {"$first": "$lanes"}
+                            }
+                        },
+                    ],
+                    None,
+                )
+                .await?)
+        })
+    }
 
+    pub async fn find_by_feature_id(&self, feature_id: &ObjectId) -> Result<Cursor, Error> {
+        operation!("PrototypesCollection", "find_by_feature_id", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "$or":[
+                            {"prototype.feature_id":feature_id},
+                            {"prototype.connections.to":feature_id}
+                        ],
+                        "deleted_at":{"$exists":false}
                     },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_template_ids(&self, template_ids: Vec<ObjectId>) -> Result<Cursor, Error> {
+        operation!("PrototypesCollection", "find_by_template_ids", {
+            Ok(self
+                .collection
+                .find(
                     doc! {
+                        "template_id":{
+                            "$in":template_ids
+                        },
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
 
-                        "$unwind":
-                        {
-                            "path": "$prototype",
-                            "preserveNullAndEmptyArrays": true
-                        }
+    /// Drops every hotspot drawn on the wireframe, trashed prototypes included.
+    pub async fn remove_wireframe_hotspots(
+        &self,
+        wireframe_id: &ObjectId,
+    ) -> Result<UpdateResult, Error> {
+        operation!("PrototypesCollection", "remove_wireframe_hotspots", entity_id = wireframe_id, {
+            Ok(self
+                .collection
+                .update_many(
+                    doc! {
+                        "prototype.hotspots.wireframe_id":wireframe_id
                     },
                     doc! {
-                        "$lookup": {
-                               "from": "Features",
-                               "localField": "prototype.feature_id",
-                               "foreignField": "_id",
-                               "as": "prototype.feature"
-                            }
+                      "$pull":{
+                        "prototype.$[].hotspots":{"wireframe_id":wireframe_id}
+                      }
                     },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Drops the feature's nodes along with every connection and hotspot leading to it.
+    pub async fn remove_feature_from_all(
+        &self,
+        feature_id: &ObjectId,
+    ) -> Result<UpdateResult, Error> {
+        operation!("PrototypesCollection", "remove_feature_from_all", entity_id = feature_id, {
+            self.collection
+                .update_many(
                     doc! {
-                        "$unset": "prototype.feature_id"
+                        "prototype.connections.to":feature_id,
+                        "deleted_at":{"$exists":false}
                     },
                     doc! {
+                      "$pull":{
+                        "prototype.$[].connections":{"to":feature_id},
+                        "prototype.$[].hotspots":{"to":feature_id}
+                      }
+                    },
+                    None,
+                )
+                .await?;
+            Ok(self
+                .collection
+                .update_many(
+                    doc! {
+                        "prototype.feature_id":feature_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                        "prototype":{"feature_id":feature_id}
+                      }
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
 
-                        "$unwind":{
-
-                            "path": "$prototype.feature",
-                            "preserveNullAndEmptyArrays": true
+    pub async fn delete_by_template_ids(&self, template_ids: Vec<ObjectId>) -> Result<UpdateResult, Error> {
+        operation!("PrototypesCollection", "delete_by_template_ids", {
+            Ok(self
+                .collection
+                .update_many(
+                    doc! {
+                        "template_id":{
+                            "$in":template_ids
+                        },
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
                         }
                     },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed(&self) -> Result<Cursor, Error> {
+        operation!("PrototypesCollection", "find_trashed", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "find_trashed_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn restore_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("PrototypesCollection", "restore_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
                     doc! {
-                      "$group":
-                        {
-                          "_id": "$_id",
-                          "template": {"$first": "$template_id"},
-                          "prototype": { "$push":  "$prototype" }
+                        "$unset":{
+                            "deleted_at":""
                         }
                     },
-                ],
-                None,
-            )
-            .await?)
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<DeleteResult, Error> {
+        operation!("PrototypesCollection", "purge_deleted_before", {
+            Ok(self
+                .collection
+                .delete_many(
+                    doc! {
+                        "deleted_at":{"$lt":cutoff}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Documents as stored, trashed ones included. Only migrations bypass the models.
+    pub async fn find_documents(&self, filter: Document) -> Result<Cursor, Error> {
+        operation!("PrototypesCollection", "find_documents", {
+            Ok(self.collection.find(filter, None).await?)
+        })
+    }
+
+    pub async fn replace_document(
+        &self,
+        id: &ObjectId,
+        document: Document,
+    ) -> Result<UpdateResult, Error> {
+        operation!("PrototypesCollection", "replace_document", entity_id = id, {
+            Ok(self
+                .collection
+                .replace_one(
+                    doc! {
+                        "_id":id
+                    },
+                    document,
+                    None,
+                )
+                .await?)
+        })
     }
 }
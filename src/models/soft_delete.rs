@@ -0,0 +1,26 @@
+use bson::{doc, Document};
+
+/// Equivalent of a `localField`/`foreignField: "_id"` lookup that leaves soft deleted
+/// documents out of the joined array. The local field may hold a single id or an array.
+pub fn live_lookup(from: &str, local_field: &str, as_field: &str) -> Document {
+    doc! {
+        "$lookup": {
+            "from": from,
+            "let": {"ids": format!("${}", local_field)},
+            "pipeline": [
+                {
+                    "$match": {
+                        "$expr": {
+                            "$in": [
+                                "$_id",
+                                {"$cond": [{"$isArray": "$$ids"}, "$$ids", ["$$ids"]]}
+                            ]
+                        },
+                        "deleted_at": {"$exists": false}
+                    }
+                }
+            ],
+            "as": as_field
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use super::indexes::IndexSpec;
+use super::soft_delete::live_lookup;
 use bson::{doc, oid::ObjectId, Document};
+use chrono::{DateTime, Utc};
 use mongodb::{
     error::Error,
     options::{FindOneAndUpdateOptions, ReturnDocument},
-    results::InsertOneResult,
+    results::{DeleteResult, InsertOneResult, UpdateResult},
     Collection, Cursor,
 };
 
@@ -16,158 +19,173 @@ impl TemplatesCollection {
         TemplatesCollection { collection }
     }
 
+    pub fn indexes() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::new(doc! {"category": 1}),
+            IndexSpec::new(doc! {"features": 1}),
+        ]
+    }
+
     pub async fn find_one<T>(&self, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("TemplatesCollection", "find_one", {
+            let mut filter = bson::to_bson(&document)
+                .unwrap()
+                .as_document()
+                .unwrap()
+                .clone();
+            filter.insert("deleted_at", doc! {"$exists": false});
+            Ok(self.collection.find_one(filter, None).await?)
+        })
     }
 
     pub async fn find_all(&self) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![doc! {
-                    "$lookup":{
-                        "from": "Features",
-                        "localField": "features",
-                        "foreignField": "_id",
-                        "as": "features"
-                    }
-                }],
-                None,
-            )
-            .await?)
+        operation!("TemplatesCollection", "find_all", {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                            "$match":{
+                                "deleted_at":{"$exists":false}
+                            }
+                        },
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
     pub async fn find_one_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                None,
-            )
-            .await?)
+        operation!("TemplatesCollection", "find_one_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn insert_one<T>(&self, document: T) -> Result<InsertOneResult, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .insert_one(
-                bson::to_bson(&document)
-                    .unwrap()
-                    .as_document()
-                    .unwrap()
-                    .clone(),
-                None,
-            )
-            .await?)
+        operation!("TemplatesCollection", "insert_one", {
+            Ok(self
+                .collection
+                .insert_one(
+                    bson::to_bson(&document)
+                        .unwrap()
+                        .as_document()
+                        .unwrap()
+                        .clone(),
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_one(&self, user_id: &str) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_delete(
-                doc! {
-                "_id":ObjectId::with_string(user_id).unwrap()
+        operation!("TemplatesCollection", "delete_one", entity_id = user_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                    "_id":ObjectId::with_string(user_id).unwrap(),
+                    "deleted_at":{"$exists":false}
+                        },
+                    doc! {
+                        "$set":{
+                            "deleted_at":Utc::now()
+                        }
                     },
-                None,
-            )
-            .await?)
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn update_one<T>(&self, id: &str, document: T) -> Result<Option<Document>, Error>
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(id).unwrap()
-                },
-                doc! {
-                      "$set":bson::to_bson(&document)
-                        .unwrap()
-                        .as_document()
-                        .unwrap()
-                        .clone()
+        operation!("TemplatesCollection", "update_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":bson::to_bson(&document)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
 
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn find_templates_by_categories_id(
         &self,
         categories_id: Vec<ObjectId>,
     ) -> Result<Cursor, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
-                    doc! {"$match": {
+        operation!("TemplatesCollection", "find_templates_by_categories_id", {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {"$match": {
 
-                    "category": {
-                            "$in":categories_id
-                            },
-                        }
-                    },
-                    doc! {
-                       "$lookup":{
-                                    "from": "Features",
-                                    "localField": "features",
-                                    "foreignField": "_id",
-                                    "as": "features"
-                                }
-                    },
-                ],
-                None,
-            )
-            .await?)
+                        "category": {
+                                "$in":categories_id
+                                },
+                        "deleted_at":{"$exists":false},
+                            }
+                        },
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn refactor_template(&self, id: &str) -> Result<Cursor<Document>, Error> {
-        Ok(self
-            .collection
-            .aggregate(
-                vec![
-                    doc! {
-                        "$match":{
-                            "_id":ObjectId::with_string(id).unwrap()
-                        }
-                    },
-                    doc! {
-                        "$lookup":{
-                            "from": "Features",
-                            "localField": "features",
-                            "foreignField": "_id",
-                            "as": "features"
-                        }
-                    },
-                ],
-                None,
-            )
-            .await?)
+        operation!("TemplatesCollection", "refactor_template", entity_id = id, {
+            Ok(self
+                .collection
+                .aggregate(
+                    vec![
+                        doc! {
+                            "$match":{
+                                "_id":ObjectId::with_string(id).unwrap(),
+                                "deleted_at":{"$exists":false}
+                            }
+                        },
+                        live_lookup("Features", "features", "features"),
+                    ],
+                    None,
+                )
+                .await?)
+        })
     }
 
     pub async fn add_feature(
@@ -175,26 +193,29 @@ impl TemplatesCollection {
         template_id: &str,
         features_id: Vec<ObjectId>,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(template_id).unwrap()
-                },
-                doc! {
-                  "$push":{
-                      "features":{
-                        "$each":features_id
-                        }
-                     }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("TemplatesCollection", "add_feature", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$push":{
+                          "features":{
+                            "$each":features_id
+                            }
+                         }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_features(
@@ -202,24 +223,27 @@ impl TemplatesCollection {
         template_id: &str,
         features_id: Vec<ObjectId>,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(template_id).unwrap()
-                },
-                doc! {
-                  "$set":{
-                      "features":features_id
-                     }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("TemplatesCollection", "update_features", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$set":{
+                          "features":features_id
+                         }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn delete_feature(
@@ -227,24 +251,27 @@ impl TemplatesCollection {
         template_id: &str,
         feature_id: &str,
     ) -> Result<Option<Document>, Error> {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(template_id).unwrap()
-                },
-                doc! {
-                  "$pull":{
-                    "features":ObjectId::with_string(feature_id).unwrap()
-                  }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+        operation!("TemplatesCollection", "delete_feature", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                        "features":ObjectId::with_string(feature_id).unwrap()
+                      }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
     }
 
     pub async fn update_specification<T>(
@@ -255,29 +282,190 @@ impl TemplatesCollection {
     where
         T: serde::Serialize,
     {
-        Ok(self
-            .collection
-            .find_one_and_update(
-                doc! {
-                    "_id":ObjectId::with_string(template_id).unwrap()
-                },
-                doc! {
-                      "$set":{
+        operation!("TemplatesCollection", "update_specification", entity_id = template_id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(template_id).unwrap(),
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                          "$set":{
 
-                         "specification" :bson::to_bson(&document)
-                        .unwrap()
-                        .as_document()
-                        .unwrap()
-                        .clone()
+                             "specification" :bson::to_bson(&document)
+                            .unwrap()
+                            .as_document()
+                            .unwrap()
+                            .clone()
+
+                          }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_feature_id(&self, feature_id: &ObjectId) -> Result<Cursor, Error> {
+        operation!("TemplatesCollection", "find_by_feature_id", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "features":feature_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_by_category_id(&self, category_id: &ObjectId) -> Result<Cursor, Error> {
+        operation!("TemplatesCollection", "find_by_category_id", entity_id = category_id, {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "category":category_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
 
+    pub async fn pull_feature_from_all(&self, feature_id: &ObjectId) -> Result<UpdateResult, Error> {
+        operation!("TemplatesCollection", "pull_feature_from_all", entity_id = feature_id, {
+            Ok(self
+                .collection
+                .update_many(
+                    doc! {
+                        "features":feature_id,
+                        "deleted_at":{"$exists":false}
+                    },
+                    doc! {
+                      "$pull":{
+                        "features":feature_id
                       }
-                },
-                Some(
-                    FindOneAndUpdateOptions::builder()
-                        .return_document(ReturnDocument::After)
-                        .build(),
-                ),
-            )
-            .await?)
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Live documents as stored, ids not joined.
+    pub async fn find_all_raw(&self) -> Result<Cursor, Error> {
+        operation!("TemplatesCollection", "find_all_raw", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":false}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed(&self) -> Result<Cursor, Error> {
+        operation!("TemplatesCollection", "find_trashed", {
+            Ok(self
+                .collection
+                .find(
+                    doc! {
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn find_trashed_by_id(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("TemplatesCollection", "find_trashed_by_id", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    pub async fn restore_one(&self, id: &str) -> Result<Option<Document>, Error> {
+        operation!("TemplatesCollection", "restore_one", entity_id = id, {
+            Ok(self
+                .collection
+                .find_one_and_update(
+                    doc! {
+                        "_id":ObjectId::with_string(id).unwrap(),
+                        "deleted_at":{"$exists":true}
+                    },
+                    doc! {
+                        "$unset":{
+                            "deleted_at":""
+                        }
+                    },
+                    Some(
+                        FindOneAndUpdateOptions::builder()
+                            .return_document(ReturnDocument::After)
+                            .build(),
+                    ),
+                )
+                .await?)
+        })
+    }
+
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<DeleteResult, Error> {
+        operation!("TemplatesCollection", "purge_deleted_before", {
+            Ok(self
+                .collection
+                .delete_many(
+                    doc! {
+                        "deleted_at":{"$lt":cutoff}
+                    },
+                    None,
+                )
+                .await?)
+        })
+    }
+
+    /// Documents as stored, trashed ones included. Only migrations bypass the models.
+    pub async fn find_documents(&self, filter: Document) -> Result<Cursor, Error> {
+        operation!("TemplatesCollection", "find_documents", {
+            Ok(self.collection.find(filter, None).await?)
+        })
+    }
+
+    pub async fn replace_document(
+        &self,
+        id: &ObjectId,
+        document: Document,
+    ) -> Result<UpdateResult, Error> {
+        operation!("TemplatesCollection", "replace_document", entity_id = id, {
+            Ok(self
+                .collection
+                .replace_one(
+                    doc! {
+                        "_id":id
+                    },
+                    document,
+                    None,
+                )
+                .await?)
+        })
     }
 }
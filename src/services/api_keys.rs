@@ -0,0 +1,219 @@
+use crate::controllers::schema::{
+    ApiKey, ApiKeyCreatedModel, ApiKeyDeserializeModel, ApiKeyResponseModel,
+};
+use crate::middleware::auth_middelware::{Identity, Scope};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::models::ApiKeysCollection;
+use crate::services::integrity::documents;
+use bson::{oid::ObjectId, Document};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const KEY_PREFIX: &str = "cbk";
+/// Last use is written at most this often per key, not on every request.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+pub fn default_grace_period_hours() -> i64 {
+    24
+}
+
+/// `cbk_<prefix>_<secret>`: the prefix finds the key without scanning, the secret is
+/// checked against its hash.
+fn generate() -> (String, String, String) {
+    let mut rng = rand::thread_rng();
+    let prefix = hex::encode(rng.gen::<[u8; 6]>());
+    let secret = hex::encode(rng.gen::<[u8; 32]>());
+    let hash = hash_secret(&secret);
+    (format!("{}_{}_{}", KEY_PREFIX, prefix, secret), prefix, hash)
+}
+
+/// Secrets are 32 random bytes, far beyond guessing, so an unsalted SHA-256 is as strong
+/// as the secret itself and keeps verification cheap on every request.
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Compares every byte of the hashes whatever the first difference, so the time taken
+/// says nothing about the stored hash.
+fn verify_secret(secret: &str, hash: &str) -> bool {
+    let stored = match hex::decode(hash) {
+        Ok(stored) => stored,
+        Err(_hex_error) => return false,
+    };
+    let computed = Sha256::digest(secret.as_bytes());
+    stored.len() == computed.len()
+        && stored
+            .iter()
+            .zip(computed.iter())
+            .fold(0, |difference, (stored, computed)| difference | (stored ^ computed))
+            == 0
+}
+
+fn deserialize(
+    document: Document,
+) -> Result<ApiKeyDeserializeModel, ContentBuilderCustomResponseError> {
+    bson::from_document::<ApiKeyDeserializeModel>(document)
+        .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)
+}
+
+pub async fn list_api_keys(
+    collection: &ApiKeysCollection,
+) -> Result<Vec<ApiKeyResponseModel>, ContentBuilderCustomResponseError> {
+    documents(collection.find_all().await)
+        .await?
+        .into_iter()
+        .map(|document| deserialize(document).map(ApiKeyResponseModel::build_api_key))
+        .collect()
+}
+
+pub async fn create_api_key(
+    collection: &ApiKeysCollection,
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>,
+    rotated_from: Option<ObjectId>,
+) -> Result<ApiKeyCreatedModel, ContentBuilderCustomResponseError> {
+    if name.trim().is_empty() || scopes.is_empty() {
+        return Err(ContentBuilderCustomResponseError::BadClientData);
+    }
+    let (key, prefix, hash) = generate();
+    let inserted = collection
+        .insert_one(ApiKey {
+            name,
+            prefix,
+            hash,
+            scopes,
+            created_at: Utc::now().into(),
+            expires_at: expires_at.map(|expires_at| expires_at.into()),
+            rotated_from,
+        })
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    let id = inserted
+        .inserted_id
+        .as_object_id()
+        .ok_or(ContentBuilderCustomResponseError::InternalError)?;
+    match collection.find_one_by_id(&id.to_hex()).await {
+        Ok(Some(document)) => Ok(ApiKeyCreatedModel {
+            key,
+            api_key: ApiKeyResponseModel::build_api_key(deserialize(document)?),
+        }),
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Issues a new key with the same name, scopes and expiry, and lets the replaced one
+/// work for `grace_period_hours` more so callers can switch over without downtime.
+/// Revoked and expired keys are not rotated, the new key would be as unusable.
+pub async fn rotate_api_key(
+    collection: &ApiKeysCollection,
+    id: &str,
+    grace_period_hours: i64,
+) -> Result<ApiKeyCreatedModel, ContentBuilderCustomResponseError> {
+    let replaced = match collection.find_one_by_id(id).await {
+        Ok(Some(document)) => deserialize(document)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    if replaced.revoked_at.is_some()
+        || replaced
+            .expires_at
+            .is_some_and(|expires_at| *expires_at <= Utc::now())
+    {
+        return Err(ContentBuilderCustomResponseError::BadClientData);
+    }
+    let rotated = create_api_key(
+        collection,
+        replaced.name,
+        replaced.scopes,
+        replaced.expires_at.map(|expires_at| *expires_at),
+        Some(replaced._id),
+    )
+    .await?;
+    collection
+        .expire_one(id, Utc::now() + Duration::hours(grace_period_hours))
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    Ok(rotated)
+}
+
+pub async fn revoke_api_key(
+    collection: &ApiKeysCollection,
+    id: &str,
+) -> Result<ApiKeyResponseModel, ContentBuilderCustomResponseError> {
+    match collection.revoke_one(id).await {
+        Ok(Some(document)) => Ok(ApiKeyResponseModel::build_api_key(deserialize(document)?)),
+        Ok(None) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Unknown, revoked and expired keys are all `Unauthorized`, callers are not told which.
+pub async fn authenticate_api_key(
+    collection: &ApiKeysCollection,
+    key: &str,
+) -> Result<Identity, ContentBuilderCustomResponseError> {
+    let mut parts = key.splitn(3, '_');
+    let (prefix, secret) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(KEY_PREFIX), Some(prefix), Some(secret)) => (prefix, secret),
+        _ => return Err(ContentBuilderCustomResponseError::Unauthorized),
+    };
+    let api_key = match collection.find_one_by_prefix(prefix).await {
+        Ok(Some(document)) => deserialize(document)?,
+        Ok(None) => return Err(ContentBuilderCustomResponseError::Unauthorized),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    let now = Utc::now();
+    if api_key.revoked_at.is_some()
+        || api_key
+            .expires_at
+            .is_some_and(|expires_at| *expires_at <= now)
+    {
+        return Err(ContentBuilderCustomResponseError::Unauthorized);
+    }
+    if !verify_secret(secret, &api_key.hash) {
+        return Err(ContentBuilderCustomResponseError::Unauthorized);
+    }
+    if api_key.last_used_at.is_none_or(|last_used_at| {
+        now - *last_used_at >= Duration::seconds(LAST_USED_RESOLUTION_SECONDS)
+    }) {
+        // losing a last use timestamp is not worth failing the request over
+        let _ = collection.touch_one(&api_key._id).await;
+    }
+    Ok(Identity {
+        subject: format!("apikey:{}", api_key._id.to_hex()),
+        roles: Vec::new(),
+        scopes: api_key.scopes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_keys_carry_their_prefix_and_verify() {
+        let (key, prefix, hash) = generate();
+        let secret = key
+            .strip_prefix(&format!("{}_{}_", KEY_PREFIX, prefix))
+            .unwrap();
+        assert_eq!(secret.len(), 64);
+        assert!(verify_secret(secret, &hash));
+    }
+
+    #[test]
+    fn other_secrets_do_not_verify() {
+        let hash = hash_secret("0123");
+        assert!(!verify_secret("0124", &hash));
+        assert!(!verify_secret("", &hash));
+    }
+
+    #[test]
+    fn malformed_hashes_never_verify() {
+        assert!(!verify_secret("0123", "not hex"));
+        assert!(!verify_secret("0123", &hash_secret("0123")[..62]));
+        assert!(!verify_secret("0123", ""));
+    }
+}
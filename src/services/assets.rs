@@ -0,0 +1,563 @@
+use crate::controllers::schema::{AssetDeserializeModel, File};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::models::AssetsCollection;
+use crate::services::images::{self, MEDIUM_SIZE, THUMBNAIL_SIZE};
+use crate::services::storage::{StorageBackend, StorageError};
+use crate::services::validation::{
+    sniff, UploadPolicy, DELIVERABLE_POLICY, IMAGE_POLICY, WIREFRAME_POLICY,
+};
+use actix_web::web;
+use awmp::Parts;
+use bson::oid::ObjectId;
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssetKind {
+    CategoryImage,
+    FeatureImage,
+    Wireframe,
+    Deliverable,
+}
+
+impl AssetKind {
+    pub fn parse(kind: &str) -> Option<AssetKind> {
+        match kind {
+            "category_image" => Some(AssetKind::CategoryImage),
+            "feature_image" => Some(AssetKind::FeatureImage),
+            "wireframe" => Some(AssetKind::Wireframe),
+            "deliverable" => Some(AssetKind::Deliverable),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AssetKind::CategoryImage => "category_image",
+            AssetKind::FeatureImage => "feature_image",
+            AssetKind::Wireframe => "wireframe",
+            AssetKind::Deliverable => "deliverable",
+        }
+    }
+
+    /// Policy applied when the caller has nothing more specific, deliverable endpoints
+    /// narrow it down per deliverable.
+    pub fn policy(self) -> &'static UploadPolicy {
+        match self {
+            AssetKind::CategoryImage | AssetKind::FeatureImage => &IMAGE_POLICY,
+            AssetKind::Wireframe => &WIREFRAME_POLICY,
+            AssetKind::Deliverable => &DELIVERABLE_POLICY,
+        }
+    }
+
+    fn has_renditions(self) -> bool {
+        self != AssetKind::Deliverable
+    }
+}
+
+const PRIVATE_PREFIX: &str = "private";
+
+/// Blobs are addressed by the sha256 of their content so identical uploads share one
+/// object. Deliverables get their own prefix so a public key never needs a token.
+fn blob_key(kind: AssetKind, hash: &str) -> String {
+    let prefix = match kind {
+        AssetKind::Deliverable => PRIVATE_PREFIX,
+        _ => "blobs",
+    };
+    format!("{}/{}/{}", prefix, &hash[..2], hash)
+}
+
+/// Whether a key holds a deliverable, without looking its asset up.
+pub fn is_private_key(key: &str) -> bool {
+    key.split('/').next() == Some(PRIVATE_PREFIX)
+}
+
+fn media_base() -> String {
+    env::var("MEDIA_BASE_URL")
+        .unwrap_or_else(|_| "/api/v1/builder/media".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Public url of a stored key, served by the media route.
+pub fn media_url(key: &str) -> String {
+    format!("{}/{}", media_base(), key)
+}
+
+/// Storage key behind a url built by `media_url`, whichever host it was built for.
+pub fn media_key(url: &str) -> Option<String> {
+    key_under(&media_base(), url)
+}
+
+fn key_under(base: &str, url: &str) -> Option<String> {
+    let path = match base.find("://") {
+        Some(scheme) => {
+            let authority = &base[scheme + 3..];
+            authority.find('/').map_or("", |path| &authority[path..])
+        }
+        None => base,
+    };
+    let marker = format!("{}/", path);
+    let start = url.find(&marker)? + marker.len();
+    let key = url[start..].split(['?', '#']).next().unwrap_or_default();
+    if key.is_empty() {
+        None
+    } else {
+        Some(key.to_string())
+    }
+}
+
+/// Deliverables belong to a single project and are only served through signed links.
+pub fn is_private(asset: &AssetDeserializeModel) -> bool {
+    asset.kind == AssetKind::Deliverable.as_str()
+}
+
+/// How long a signed link to a private asset stays valid.
+pub const SIGNED_LINK_TTL_SECONDS: i64 = 60 * 60;
+
+pub fn signing_key() -> String {
+    env::var("MEDIA_SIGNING_KEY").expect("MEDIA_SIGNING_KEY must be set")
+}
+
+fn signer(key: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(signing_key().as_bytes())
+        .expect("hmac accepts keys of any size");
+    mac.update(format!("{}\n{}", key, expires).as_bytes());
+    mac
+}
+
+/// Token granting access to `key` until `expires`, a unix timestamp in seconds.
+pub fn access_token(key: &str, expires: i64) -> String {
+    hex::encode(signer(key, expires).finalize().into_bytes())
+}
+
+pub fn verify_access_token(key: &str, expires: i64, token: &str) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+    match hex::decode(token) {
+        Ok(token) => signer(key, expires).verify(&token).is_ok(),
+        Err(_hex_error) => false,
+    }
+}
+
+/// Media url of a private key, valid for `SIGNED_LINK_TTL_SECONDS`.
+pub fn signed_url(key: &str) -> String {
+    let expires = Utc::now().timestamp() + SIGNED_LINK_TTL_SECONDS;
+    format!(
+        "{}?expires={}&token={}",
+        media_url(key),
+        expires,
+        access_token(key, expires)
+    )
+}
+
+/// Url handed out for a stored src. Links to private keys expire, so they are stored
+/// unsigned and signed each time they are read.
+pub fn signed_src(src: &str) -> String {
+    match media_key(src) {
+        Some(key) if is_private_key(&key) => signed_url(&key),
+        _ => src.to_string(),
+    }
+}
+
+/// File stored for an asset, its src is the plain media url even when the asset is
+/// private.
+pub fn asset_file(asset: &AssetDeserializeModel) -> File {
+    File {
+        name: asset.name.clone(),
+        src: media_url(&asset.key),
+        thumbnail: asset.thumbnail.as_deref().map(media_url),
+        medium: asset.medium.as_deref().map(media_url),
+    }
+}
+
+/// Files sent under `field`. A file cut short by the multipart file limit is refused as
+/// too large rather than treated as missing.
+pub fn take_files(
+    parts: Parts,
+    field: &str,
+) -> Result<Vec<awmp::File>, ContentBuilderCustomResponseError> {
+    let mut files = Vec::new();
+    for (name, file) in parts.files.into_inner() {
+        if name != field {
+            continue;
+        }
+        match file {
+            Ok(file) => files.push(file),
+            Err(awmp::Error::FileTooLarge { .. }) => {
+                return Err(ContentBuilderCustomResponseError::PayloadTooLarge)
+            }
+            Err(_multipart_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+        }
+    }
+    if files.is_empty() {
+        Err(ContentBuilderCustomResponseError::BadClientData)
+    } else {
+        Ok(files)
+    }
+}
+
+pub fn take_file(parts: Parts, field: &str) -> Result<awmp::File, ContentBuilderCustomResponseError> {
+    take_files(parts, field)?
+        .pop()
+        .ok_or(ContentBuilderCustomResponseError::BadClientData)
+}
+
+pub fn text_field(parts: &Parts, field: &str) -> Result<String, ContentBuilderCustomResponseError> {
+    parts
+        .texts
+        .as_hash_map()
+        .get(field)
+        .map(|value| value.to_string())
+        .ok_or(ContentBuilderCustomResponseError::BadClientData)
+}
+
+/// Validates an uploaded file against the policy, persists it in the storage backend
+/// and records it in the assets collection under a generated asset id.
+pub async fn store_upload(
+    storage: &dyn StorageBackend,
+    assets: &AssetsCollection,
+    kind: AssetKind,
+    policy: &UploadPolicy,
+    file: awmp::File,
+    owner: Option<ObjectId>,
+) -> Result<AssetDeserializeModel, ContentBuilderCustomResponseError> {
+    let name = file.sanitized_file_name().to_string();
+    let temp_file = file.into_inner();
+    let size = temp_file
+        .as_file()
+        .metadata()
+        .map_err(|_io_error| ContentBuilderCustomResponseError::InternalError)?
+        .len();
+    policy.check_size(size)?;
+    let path = temp_file.path().to_path_buf();
+    let (hash, head) = web::block(move || digest_file(&path))
+        .await
+        .map_err(|_io_error| ContentBuilderCustomResponseError::InternalError)?;
+    // images are decoded for their dimensions and renditions, only they are read whole
+    let (content_type, content) =
+        if sniff(&name, &head).is_some_and(|content_type| content_type.starts_with("image/")) {
+            let path = temp_file.path().to_path_buf();
+            let bytes = web::block(move || fs::read(path))
+                .await
+                .map_err(|_io_error| ContentBuilderCustomResponseError::InternalError)?;
+            (policy.check_content(&name, &bytes)?, Content::Bytes(bytes))
+        } else {
+            (
+                policy.check_content(&name, &head)?,
+                Content::File(temp_file.path().to_path_buf()),
+            )
+        };
+    let blob = Blob {
+        hash,
+        size: size as i64,
+        content,
+    };
+    // the temporary file is removed once dropped, it has to outlive the copy
+    let stored = store_blob(storage, assets, kind, &name, content_type, blob, owner).await;
+    drop(temp_file);
+    stored
+}
+
+/// Enough of a file to sniff its content type from.
+const HEAD_LENGTH: usize = 512;
+
+/// The sha256 of a file, read in chunks, along with its first `HEAD_LENGTH` bytes.
+fn digest_file(path: &Path) -> io::Result<(String, Vec<u8>)> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(HEAD_LENGTH);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        let missing = HEAD_LENGTH.saturating_sub(head.len()).min(read);
+        head.extend_from_slice(&buffer[..missing]);
+        hasher.update(&buffer[..read]);
+    }
+    Ok((hex::encode(hasher.finalize()), head))
+}
+
+/// Where the bytes of a blob are read from when it is stored.
+enum Content {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+struct Blob {
+    hash: String,
+    size: i64,
+    content: Content,
+}
+
+pub async fn store_bytes(
+    storage: &dyn StorageBackend,
+    assets: &AssetsCollection,
+    kind: AssetKind,
+    name: &str,
+    content_type: &str,
+    bytes: Vec<u8>,
+    owner: Option<ObjectId>,
+) -> Result<AssetDeserializeModel, ContentBuilderCustomResponseError> {
+    let blob = Blob {
+        hash: hex::encode(Sha256::digest(&bytes)),
+        size: bytes.len() as i64,
+        content: Content::Bytes(bytes),
+    };
+    store_blob(storage, assets, kind, name, content_type, blob, owner).await
+}
+
+/// Stores the blob unless an earlier upload of the same content already did, and
+/// records the asset. Renditions are made from blobs held in memory.
+async fn store_blob(
+    storage: &dyn StorageBackend,
+    assets: &AssetsCollection,
+    kind: AssetKind,
+    name: &str,
+    content_type: &str,
+    blob: Blob,
+    owner: Option<ObjectId>,
+) -> Result<AssetDeserializeModel, ContentBuilderCustomResponseError> {
+    let Blob {
+        hash,
+        size,
+        content,
+    } = blob;
+    let key = blob_key(kind, &hash);
+    let (thumbnail, medium) = match &content {
+        Content::Bytes(bytes) if kind.has_renditions() => {
+            store_renditions(storage, &hash, bytes)
+                .await
+                .map_err(|_storage_error| ContentBuilderCustomResponseError::InternalError)?
+        }
+        _ => (None, None),
+    };
+    if !storage
+        .exists(&key)
+        .await
+        .map_err(|_storage_error| ContentBuilderCustomResponseError::InternalError)?
+    {
+        match content {
+            Content::Bytes(bytes) => storage.put(&key, bytes, content_type).await,
+            Content::File(path) => storage.put_file(&key, path, content_type).await,
+        }
+        .map_err(|_storage_error| ContentBuilderCustomResponseError::InternalError)?;
+    }
+    let asset = AssetDeserializeModel {
+        _id: ObjectId::new(),
+        kind: kind.as_str().to_string(),
+        key,
+        name: name.to_string(),
+        content_type: content_type.to_string(),
+        size,
+        owner,
+        created_at: Utc::now().into(),
+        hash: Some(hash),
+        thumbnail,
+        medium,
+    };
+    match assets.insert_one(&asset).await {
+        Ok(_) => Ok(asset),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Stores the thumbnail and medium renditions of an image, keyed by the hash of the
+/// original so a re-upload reuses them. Bytes the pipeline cannot decode are kept
+/// without renditions.
+async fn store_renditions(
+    storage: &dyn StorageBackend,
+    hash: &str,
+    bytes: &[u8],
+) -> Result<(Option<String>, Option<String>), StorageError> {
+    let (extension, content_type) = match images::rendition_format(bytes) {
+        Some(format) => format,
+        None => return Ok((None, None)),
+    };
+    let thumbnail = format!("renditions/{}/{}/thumbnail.{}", &hash[..2], hash, extension);
+    let medium = format!("renditions/{}/{}/medium.{}", &hash[..2], hash, extension);
+    if storage.exists(&thumbnail).await? && storage.exists(&medium).await? {
+        return Ok((Some(thumbnail), Some(medium)));
+    }
+    let source = bytes.to_vec();
+    let rendered = web::block(move || images::render(&source, &[THUMBNAIL_SIZE, MEDIUM_SIZE])).await;
+    match rendered {
+        Ok(mut renditions) => {
+            let medium_bytes = renditions.pop().unwrap_or_default();
+            let thumbnail_bytes = renditions.pop().unwrap_or_default();
+            storage.put(&thumbnail, thumbnail_bytes, content_type).await?;
+            storage.put(&medium, medium_bytes, content_type).await?;
+            Ok((Some(thumbnail), Some(medium)))
+        }
+        Err(_image_error) => Ok((None, None)),
+    }
+}
+
+pub fn content_type_for(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "apk" => "application/vnd.android.package-archive",
+        "ipa" => "application/octet-stream",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "/api/v1/builder/media";
+
+    #[test]
+    fn key_of_a_relative_media_url() {
+        assert_eq!(
+            key_under(BASE, "/api/v1/builder/media/blobs/ab/abcdef").as_deref(),
+            Some("blobs/ab/abcdef")
+        );
+    }
+
+    #[test]
+    fn key_whatever_host_the_url_was_built_for() {
+        assert_eq!(
+            key_under(
+                "https://cdn.example.com/api/v1/builder/media",
+                "https://old.example.com/api/v1/builder/media/blobs/ab/abcdef"
+            )
+            .as_deref(),
+            Some("blobs/ab/abcdef")
+        );
+        assert_eq!(
+            key_under(
+                BASE,
+                "https://cdn.example.com/api/v1/builder/media/blobs/ab/abcdef"
+            )
+            .as_deref(),
+            Some("blobs/ab/abcdef")
+        );
+    }
+
+    #[test]
+    fn query_and_fragment_are_not_part_of_the_key() {
+        assert_eq!(
+            key_under(
+                BASE,
+                "/api/v1/builder/media/private/ab/abcdef?token=00&expires=1"
+            )
+            .as_deref(),
+            Some("private/ab/abcdef")
+        );
+        assert_eq!(
+            key_under(BASE, "/api/v1/builder/media/blobs/ab/abcdef#frame").as_deref(),
+            Some("blobs/ab/abcdef")
+        );
+    }
+
+    #[test]
+    fn other_urls_have_no_key() {
+        assert_eq!(key_under(BASE, "https://cdn.example.com/login.png"), None);
+        assert_eq!(key_under(BASE, "/api/v1/builder/media/"), None);
+        assert_eq!(key_under(BASE, "/api/v1/builder/media/?token=00"), None);
+    }
+
+    fn with_signing_key() {
+        env::set_var("MEDIA_SIGNING_KEY", "test-signing-key");
+    }
+
+    #[test]
+    fn tokens_are_bound_to_key_and_expiry() {
+        with_signing_key();
+        let expires = Utc::now().timestamp() + 60;
+        let token = access_token("private/ab/abcdef", expires);
+        assert!(verify_access_token("private/ab/abcdef", expires, &token));
+        assert!(!verify_access_token("private/ab/abcdeg", expires, &token));
+        assert!(!verify_access_token(
+            "private/ab/abcdef",
+            expires + 1,
+            &token
+        ));
+        assert!(!verify_access_token(
+            "private/ab/abcdef",
+            expires,
+            "not hex"
+        ));
+    }
+
+    #[test]
+    fn expired_tokens_are_refused() {
+        with_signing_key();
+        let expires = Utc::now().timestamp() - 1;
+        let token = access_token("private/ab/abcdef", expires);
+        assert!(!verify_access_token("private/ab/abcdef", expires, &token));
+    }
+
+    #[test]
+    fn only_private_srcs_are_signed() {
+        with_signing_key();
+        let public = media_url("blobs/ab/abcdef");
+        assert_eq!(signed_src(&public), public);
+        assert_eq!(
+            signed_src("https://cdn.example.com/a.png"),
+            "https://cdn.example.com/a.png"
+        );
+        // stored links signed before are signed again
+        let signed = signed_src(&format!("{}?token=00", media_url("private/ab/abcdef")));
+        assert!(signed.starts_with(&format!("{}?expires=", media_url("private/ab/abcdef"))));
+        assert!(signed.contains("&token="));
+    }
+
+    #[test]
+    fn private_keys_are_told_apart() {
+        assert!(is_private_key(&blob_key(AssetKind::Deliverable, "abcdef")));
+        assert!(!is_private_key(&blob_key(AssetKind::Wireframe, "abcdef")));
+        assert!(!is_private_key("privateer/ab/abcdef"));
+    }
+
+    fn written(bytes: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("digest-{}", ObjectId::new()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn files_are_hashed_in_chunks_and_keep_their_head() {
+        let bytes: Vec<u8> = (0..200_000).map(|index| (index % 251) as u8).collect();
+        let path = written(&bytes);
+        let (hash, head) = digest_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(hash, hex::encode(Sha256::digest(&bytes)));
+        assert_eq!(head, bytes[..HEAD_LENGTH].to_vec());
+    }
+
+    #[test]
+    fn small_files_are_their_own_head() {
+        let path = written(b"%PDF-1.7");
+        let (hash, head) = digest_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(hash, hex::encode(Sha256::digest(b"%PDF-1.7")));
+        assert_eq!(head, b"%PDF-1.7".to_vec());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::middleware::auth_middelware::{Identity, Policy};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::CollectionsContainer;
+use bson::{oid::ObjectId, Document};
+
+/// Whoever `policy` lets reach every project, or the client whose `client_id` the
+/// project carries. Checked before anything is written, the project is looked up for
+/// clients only.
+pub async fn authorize_project(
+    container: &CollectionsContainer,
+    identity: &Identity,
+    project_id: &str,
+    policy: Policy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    ObjectId::with_string(project_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    if identity.require(policy).is_ok() {
+        return Ok(());
+    }
+    match container.project.find_one_by_id(project_id).await {
+        Ok(project) => authorize_owner(identity, project.as_ref(), policy),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+fn authorize_owner(
+    identity: &Identity,
+    project: Option<&Document>,
+    policy: Policy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    match project.map(|project| project.get_object_id("client_id")) {
+        Some(Ok(client_id)) => identity.require_client(&client_id.to_hex(), policy),
+        Some(Err(_bson_error)) => Err(ContentBuilderCustomResponseError::InternalError),
+        None => Err(ContentBuilderCustomResponseError::NotFound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::auth_middelware::{Role, Scope, PROJECTS_READ, PROJECTS_WRITE};
+    use crate::models::{
+        ApiKeysCollection, AssetsCollection, CategoriesCollection, FeaturesCollection,
+        MigrationsCollection, ProjectsCollection, PrototypeRevisionsCollection,
+        PrototypesCollection, TemplatesCollection,
+    };
+    use bson::doc;
+    use mongodb::{options::ClientOptions, Client};
+
+    /// Any lookup fails, a policy that needs none still answers.
+    async fn unreachable() -> CollectionsContainer {
+        let options = ClientOptions::parse("mongodb://127.0.0.1:9/?serverSelectionTimeoutMS=100")
+            .await
+            .unwrap();
+        let database = Client::with_options(options).unwrap().database("test");
+        CollectionsContainer {
+            api_key: ApiKeysCollection::new(database.collection("api_keys")),
+            asset: AssetsCollection::new(database.collection("assets")),
+            category: CategoriesCollection::new(database.collection("categories")),
+            feature: FeaturesCollection::new(database.collection("features")),
+            migration: MigrationsCollection::new(database.collection("migrations")),
+            project: ProjectsCollection::new(database.collection("projects")),
+            prototype: PrototypesCollection::new(database.collection("prototypes")),
+            prototype_revision: PrototypeRevisionsCollection::new(
+                database.collection("prototype_revisions"),
+            ),
+            template: TemplatesCollection::new(database.collection("templates")),
+            database,
+        }
+    }
+
+    fn identity(subject: &str, roles: Vec<Role>, scopes: Vec<Scope>) -> Identity {
+        Identity {
+            subject: subject.to_string(),
+            roles,
+            scopes,
+        }
+    }
+
+    fn client(client_id: &ObjectId) -> Identity {
+        identity(&client_id.to_hex(), vec![Role::Client], vec![])
+    }
+
+    #[actix_rt::test]
+    async fn malformed_ids_are_refused_before_anything_else() {
+        let container = unreachable().await;
+        let staff = identity("staff", vec![Role::Staff], vec![]);
+        for project_id in ["", "not-an-id", "123"] {
+            assert!(matches!(
+                authorize_project(&container, &staff, project_id, PROJECTS_WRITE).await,
+                Err(ContentBuilderCustomResponseError::BadClientData)
+            ));
+            assert!(matches!(
+                authorize_project(
+                    &container,
+                    &client(&ObjectId::new()),
+                    project_id,
+                    PROJECTS_READ
+                )
+                .await,
+                Err(ContentBuilderCustomResponseError::BadClientData)
+            ));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn staff_and_scoped_keys_reach_every_project_without_a_lookup() {
+        let container = unreachable().await;
+        let project_id = ObjectId::new().to_hex();
+        for identity in [
+            identity("admin", vec![Role::Admin], vec![]),
+            identity("staff", vec![Role::Staff], vec![]),
+            identity("key", vec![], vec![Scope::ProjectsWrite]),
+        ] {
+            assert!(
+                authorize_project(&container, &identity, &project_id, PROJECTS_WRITE)
+                    .await
+                    .is_ok()
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn clients_are_looked_up() {
+        let container = unreachable().await;
+        let reader = identity("key", vec![], vec![Scope::ProjectsRead]);
+        for identity in [client(&ObjectId::new()), reader] {
+            assert!(matches!(
+                authorize_project(
+                    &container,
+                    &identity,
+                    &ObjectId::new().to_hex(),
+                    PROJECTS_WRITE
+                )
+                .await,
+                Err(ContentBuilderCustomResponseError::InternalError)
+            ));
+        }
+    }
+
+    #[test]
+    fn clients_reach_only_their_own_projects() {
+        let (owner, other) = (ObjectId::new(), ObjectId::new());
+        let project = doc! {"_id": ObjectId::new(), "client_id": owner.clone()};
+        assert!(authorize_owner(&client(&owner), Some(&project), PROJECTS_WRITE).is_ok());
+        assert!(matches!(
+            authorize_owner(&client(&other), Some(&project), PROJECTS_WRITE),
+            Err(ContentBuilderCustomResponseError::NotAllowed)
+        ));
+        let impostor = identity(&owner.to_hex(), vec![], vec![Scope::CatalogRead]);
+        assert!(matches!(
+            authorize_owner(&impostor, Some(&project), PROJECTS_READ),
+            Err(ContentBuilderCustomResponseError::NotAllowed)
+        ));
+    }
+
+    #[test]
+    fn missing_projects_and_owners_are_told_apart() {
+        let owner = ObjectId::new();
+        assert!(matches!(
+            authorize_owner(&client(&owner), None, PROJECTS_READ),
+            Err(ContentBuilderCustomResponseError::NotFound)
+        ));
+        assert!(matches!(
+            authorize_owner(
+                &client(&owner),
+                Some(&doc! {"_id": owner.clone()}),
+                PROJECTS_READ
+            ),
+            Err(ContentBuilderCustomResponseError::InternalError)
+        ));
+    }
+}
@@ -0,0 +1,577 @@
+use crate::controllers::schema::{
+    CategoryDeserializeModel, FeatureDeserializeModel, Project, ProtoTypeDeserializeModel,
+    TemplateDeserializeModel,
+};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::integrity::documents;
+use crate::CollectionsContainer;
+use bson::{oid::ObjectId, Bson, Document};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Totals closer than this to the sum of the feature prices are taken as equal.
+const PRICE_TOLERANCE: f64 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    DanglingReference,
+    DuplicateId,
+    Undeserializable,
+    PriceMismatch,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsistencyIssue {
+    pub collection: &'static str,
+    pub id: String,
+    pub kind: IssueKind,
+    pub detail: String,
+    pub fixed: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConsistencyReport {
+    pub fix: bool,
+    pub scanned_documents: usize,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+impl ConsistencyReport {
+    pub fn unresolved(&self) -> usize {
+        self.issues.iter().filter(|issue| !issue.fixed).count()
+    }
+
+    fn issue(&mut self, collection: &'static str, id: &str, kind: IssueKind, detail: String) {
+        self.issues.push(ConsistencyIssue {
+            collection,
+            id: id.to_string(),
+            kind,
+            detail,
+            fixed: false,
+        });
+    }
+
+    /// Marks the issues raised for a document since `from` as fixed.
+    fn fixed_since(&mut self, from: usize) {
+        self.issues[from..]
+            .iter_mut()
+            .for_each(|issue| issue.fixed = true);
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for issue in self.issues.iter() {
+            writeln!(
+                formatter,
+                "{:<18} {}/{}: {}{}",
+                format!("{:?}", issue.kind),
+                issue.collection,
+                issue.id,
+                issue.detail,
+                if issue.fixed { " (fixed)" } else { "" }
+            )?;
+        }
+        write!(
+            formatter,
+            "{} documents scanned, {} issues, {} unresolved",
+            self.scanned_documents,
+            self.issues.len(),
+            self.unresolved()
+        )
+    }
+}
+
+/// Scans the live documents of the five collections. With `fix` set, repairs that lose
+/// nothing a client chose are applied: duplicate and dangling ids are dropped from
+/// feature lists, wireframes sharing an id are deduplicated or given a fresh id,
+/// prototypes of missing templates go to the trash and prototype nodes, connections and
+/// hotspots leading to missing features are removed. A project whose feature list is
+/// repaired gets its total recomputed from the features it keeps. Dangling templates and
+/// categories, undeserializable documents and other price mismatches are only reported.
+pub async fn check(
+    container: &CollectionsContainer,
+    fix: bool,
+) -> Result<ConsistencyReport, ContentBuilderCustomResponseError> {
+    let categories = documents(container.category.find_all().await).await?;
+    let features = documents(container.feature.find_all().await).await?;
+    let templates = documents(container.template.find_all_raw().await).await?;
+    let projects = documents(container.project.find_all_raw().await).await?;
+    let prototypes = documents(container.prototype.find_all().await).await?;
+
+    let mut report = ConsistencyReport {
+        fix,
+        scanned_documents: categories.len()
+            + features.len()
+            + templates.len()
+            + projects.len()
+            + prototypes.len(),
+        issues: Vec::new(),
+    };
+    let category_ids = ids(&categories);
+    let template_ids = ids(&templates);
+    let feature_prices: HashMap<ObjectId, Option<f64>> = features
+        .iter()
+        .filter_map(|feature| {
+            Some((
+                feature.get_object_id("_id").ok()?.clone(),
+                price_of(feature, "price"),
+            ))
+        })
+        .collect();
+
+    for category in categories.iter() {
+        check_model::<CategoryDeserializeModel>(&mut report, "categories", category);
+    }
+
+    for feature in features.iter() {
+        let id = hex_id(feature);
+        check_model::<FeatureDeserializeModel>(&mut report, "features", feature);
+        let from = report.issues.len();
+        if let Some(wireframes) = deduplicated_wireframes(&mut report, &id, feature) {
+            if fix
+                && matches!(
+                    container.feature.update_wireframes(&id, wireframes).await,
+                    Ok(Some(_))
+                )
+            {
+                report.fixed_since(from);
+            }
+        }
+    }
+
+    for template in templates.iter() {
+        let id = hex_id(template);
+        check_model::<TemplateDeserializeModel>(&mut report, "templates", template);
+        if let Ok(category_id) = template.get_object_id("category") {
+            if !category_ids.contains(category_id) {
+                report.issue(
+                    "templates",
+                    &id,
+                    IssueKind::DanglingReference,
+                    format!("category {} does not exist", category_id),
+                );
+            }
+        }
+        let from = report.issues.len();
+        if let Some(features) =
+            cleaned_feature_ids(&mut report, "templates", &id, template, &feature_prices)
+        {
+            if fix
+                && matches!(
+                    container.template.update_features(&id, features).await,
+                    Ok(Some(_))
+                )
+            {
+                report.fixed_since(from);
+            }
+        }
+    }
+
+    for project in projects.iter() {
+        let id = hex_id(project);
+        check_model::<Project>(&mut report, "projects", project);
+        if let Ok(template_id) = project.get_object_id("template") {
+            if !template_ids.contains(template_id) {
+                report.issue(
+                    "projects",
+                    &id,
+                    IssueKind::DanglingReference,
+                    format!("template {} does not exist", template_id),
+                );
+            }
+        }
+        let from = report.issues.len();
+        let cleaned = cleaned_feature_ids(&mut report, "projects", &id, project, &feature_prices);
+        // the price is checked against the features the project keeps
+        let total_price = match cleaned.as_ref() {
+            Some(features) => check_price(&mut report, &id, project, features, &feature_prices),
+            None => listed_feature_ids(project).and_then(|features| {
+                check_price(&mut report, &id, project, &features, &feature_prices)
+            }),
+        };
+        if let Some(features) = cleaned {
+            if fix
+                && matches!(
+                    container
+                        .project
+                        .update_features(&id, features, total_price)
+                        .await,
+                    Ok(Some(_))
+                )
+            {
+                report.fixed_since(from);
+            }
+        }
+    }
+
+    let mut prototypes_per_template: HashMap<ObjectId, usize> = HashMap::new();
+    let mut missing_features: HashMap<ObjectId, Vec<usize>> = HashMap::new();
+    for prototype in prototypes.iter() {
+        let id = hex_id(prototype);
+        check_model::<ProtoTypeDeserializeModel>(&mut report, "prototypes", prototype);
+        if let Ok(template_id) = prototype.get_object_id("template_id") {
+            if !template_ids.contains(template_id) {
+                report.issue(
+                    "prototypes",
+                    &id,
+                    IssueKind::DanglingReference,
+                    format!("template {} does not exist", template_id),
+                );
+                if fix && matches!(container.prototype.delete_one(&id).await, Ok(Some(_))) {
+                    report.fixed_since(report.issues.len() - 1);
+                }
+                continue;
+            }
+            *prototypes_per_template
+                .entry(template_id.clone())
+                .or_default() += 1;
+        }
+
+        let mut nodes = HashSet::new();
+        for node in array_documents(prototype, "prototype") {
+            let feature_id = match node.get_object_id("feature_id") {
+                Ok(feature_id) => feature_id,
+                Err(_) => continue,
+            };
+            if !nodes.insert(feature_id.clone()) {
+                report.issue(
+                    "prototypes",
+                    &id,
+                    IssueKind::DuplicateId,
+                    format!("feature {} has more than one node", feature_id),
+                );
+            }
+            let targets = array_documents(node, "connections")
+                .chain(array_documents(node, "hotspots"))
+                .filter_map(|target| target.get_object_id("to").ok());
+            for (what, target) in
+                std::iter::once(("node of", feature_id)).chain(targets.map(|to| ("link to", to)))
+            {
+                if !feature_prices.contains_key(target) {
+                    missing_features
+                        .entry(target.clone())
+                        .or_default()
+                        .push(report.issues.len());
+                    report.issue(
+                        "prototypes",
+                        &id,
+                        IssueKind::DanglingReference,
+                        format!("{} to feature {} which does not exist", what, target),
+                    );
+                }
+            }
+        }
+    }
+    for (template_id, count) in prototypes_per_template {
+        if count > 1 {
+            report.issue(
+                "templates",
+                &template_id.to_hex(),
+                IssueKind::DuplicateId,
+                format!("{} live prototypes share this template", count),
+            );
+        }
+    }
+    if fix {
+        for (feature_id, issues) in missing_features {
+            if container
+                .prototype
+                .remove_feature_from_all(&feature_id)
+                .await
+                .is_ok()
+            {
+                issues
+                    .into_iter()
+                    .for_each(|index| report.issues[index].fixed = true);
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn check_model<T: DeserializeOwned>(
+    report: &mut ConsistencyReport,
+    collection: &'static str,
+    document: &Document,
+) {
+    if let Err(bson_de_error) = bson::from_document::<T>(document.clone()) {
+        report.issue(
+            collection,
+            &hex_id(document),
+            IssueKind::Undeserializable,
+            bson_de_error.to_string(),
+        );
+    }
+}
+
+/// Feature ids of a template or project with duplicates and unknown features dropped,
+/// `None` when there is nothing to drop.
+fn cleaned_feature_ids(
+    report: &mut ConsistencyReport,
+    collection: &'static str,
+    id: &str,
+    document: &Document,
+    feature_prices: &HashMap<ObjectId, Option<f64>>,
+) -> Option<Vec<ObjectId>> {
+    let features = document.get_array("features").ok()?;
+    let mut seen = HashSet::new();
+    let mut cleaned = Vec::new();
+    for feature_id in features.iter().filter_map(Bson::as_object_id) {
+        if !seen.insert(feature_id.clone()) {
+            report.issue(
+                collection,
+                id,
+                IssueKind::DuplicateId,
+                format!("feature {} is listed more than once", feature_id),
+            );
+        } else if !feature_prices.contains_key(feature_id) {
+            report.issue(
+                collection,
+                id,
+                IssueKind::DanglingReference,
+                format!("feature {} does not exist", feature_id),
+            );
+        } else {
+            cleaned.push(feature_id.clone());
+        }
+    }
+    if cleaned.len() == features.len() {
+        None
+    } else {
+        Some(cleaned)
+    }
+}
+
+fn listed_feature_ids(document: &Document) -> Option<Vec<ObjectId>> {
+    Some(
+        document
+            .get_array("features")
+            .ok()?
+            .iter()
+            .filter_map(Bson::as_object_id)
+            .cloned()
+            .collect(),
+    )
+}
+
+/// A project's price is the sum of the prices of the features it lists, each counted once.
+/// Returns that sum, `None` when a feature has no known price.
+fn check_price(
+    report: &mut ConsistencyReport,
+    id: &str,
+    project: &Document,
+    feature_ids: &[ObjectId],
+    feature_prices: &HashMap<ObjectId, Option<f64>>,
+) -> Option<f64> {
+    let mut expected = 0.0;
+    for feature_id in feature_ids.iter().collect::<HashSet<&ObjectId>>() {
+        // unknown features are reported on their own, a price without them means nothing
+        expected += (*feature_prices.get(feature_id)?)?;
+    }
+    if let Some(total_price) = price_of(project, "total_price") {
+        if (expected - total_price).abs() > PRICE_TOLERANCE {
+            report.issue(
+                "projects",
+                id,
+                IssueKind::PriceMismatch,
+                format!(
+                    "total_price is {} but its features add up to {}",
+                    total_price, expected
+                ),
+            );
+        }
+    }
+    Some(expected)
+}
+
+/// Wireframes with exact duplicates dropped and a fresh id on entries that only share an
+/// id, hotspots then keep pointing at the first of them as they already did. `None` when
+/// every id is unique.
+fn deduplicated_wireframes(
+    report: &mut ConsistencyReport,
+    id: &str,
+    feature: &Document,
+) -> Option<Vec<Document>> {
+    let mut seen: HashMap<ObjectId, Vec<Document>> = HashMap::new();
+    let mut wireframes = Vec::new();
+    let mut changed = false;
+    for wireframe in array_documents(feature, "wireframes") {
+        let wireframe_id = match wireframe.get_object_id("_id") {
+            Ok(wireframe_id) => wireframe_id.clone(),
+            Err(_) => {
+                wireframes.push(wireframe.clone());
+                continue;
+            }
+        };
+        let kept = seen.entry(wireframe_id.clone()).or_default();
+        if kept.is_empty() {
+            kept.push(wireframe.clone());
+            wireframes.push(wireframe.clone());
+            continue;
+        }
+        changed = true;
+        report.issue(
+            "features",
+            id,
+            IssueKind::DuplicateId,
+            format!("wireframe id {} is used more than once", wireframe_id),
+        );
+        if !kept.contains(wireframe) {
+            kept.push(wireframe.clone());
+            let mut renamed = wireframe.clone();
+            renamed.insert("_id", ObjectId::new());
+            wireframes.push(renamed);
+        }
+    }
+    if changed {
+        Some(wireframes)
+    } else {
+        None
+    }
+}
+
+fn ids(documents: &[Document]) -> HashSet<ObjectId> {
+    documents
+        .iter()
+        .filter_map(|document| document.get_object_id("_id").ok().cloned())
+        .collect()
+}
+
+fn hex_id(document: &Document) -> String {
+    document
+        .get_object_id("_id")
+        .map(ObjectId::to_hex)
+        .unwrap_or_default()
+}
+
+fn array_documents<'a>(document: &'a Document, key: &str) -> impl Iterator<Item = &'a Document> {
+    document
+        .get_array(key)
+        .map(|values| values.iter())
+        .into_iter()
+        .flatten()
+        .filter_map(Bson::as_document)
+}
+
+pub fn price_of(document: &Document, key: &str) -> Option<f64> {
+    match document.get(key)? {
+        Bson::Double(price) => Some(*price),
+        Bson::Int32(price) => Some(*price as f64),
+        Bson::Int64(price) => Some(*price as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    fn prices(features: &[(&ObjectId, Option<f64>)]) -> HashMap<ObjectId, Option<f64>> {
+        features
+            .iter()
+            .map(|(id, price)| ((*id).clone(), *price))
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_and_dangling_features_are_dropped() {
+        let (kept, gone) = (ObjectId::new(), ObjectId::new());
+        let project = doc! {"features": [kept.clone(), kept.clone(), gone.clone()]};
+        let mut report = ConsistencyReport::default();
+        let cleaned = cleaned_feature_ids(
+            &mut report,
+            "projects",
+            "p",
+            &project,
+            &prices(&[(&kept, Some(10.0))]),
+        );
+        assert_eq!(cleaned, Some(vec![kept]));
+        let kinds: Vec<IssueKind> = report.issues.iter().map(|issue| issue.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![IssueKind::DuplicateId, IssueKind::DanglingReference]
+        );
+    }
+
+    #[test]
+    fn clean_lists_are_left_alone() {
+        let feature = ObjectId::new();
+        let mut report = ConsistencyReport::default();
+        let cleaned = cleaned_feature_ids(
+            &mut report,
+            "templates",
+            "t",
+            &doc! {"features": [feature.clone()]},
+            &prices(&[(&feature, None)]),
+        );
+        assert_eq!(cleaned, None);
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn price_is_checked_against_the_kept_features() {
+        let (kept, gone) = (ObjectId::new(), ObjectId::new());
+        let project = doc! {"features": [kept.clone(), gone.clone()], "total_price": 30.0};
+        let feature_prices = prices(&[(&kept, Some(10.0)), (&gone, Some(20.0))]);
+        let mut report = ConsistencyReport::default();
+        assert_eq!(
+            check_price(
+                &mut report,
+                "p",
+                &project,
+                std::slice::from_ref(&kept),
+                &feature_prices
+            ),
+            Some(10.0)
+        );
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].kind, IssueKind::PriceMismatch);
+
+        let mut report = ConsistencyReport::default();
+        assert_eq!(
+            check_price(&mut report, "p", &project, &[kept, gone], &feature_prices),
+            Some(30.0)
+        );
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn features_count_once_towards_the_price() {
+        let feature = ObjectId::new();
+        let project = doc! {"total_price": 10};
+        let mut report = ConsistencyReport::default();
+        assert_eq!(
+            check_price(
+                &mut report,
+                "p",
+                &project,
+                &[feature.clone(), feature.clone()],
+                &prices(&[(&feature, Some(10.0))])
+            ),
+            Some(10.0)
+        );
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn no_price_without_every_feature_price() {
+        let (priced, unpriced) = (ObjectId::new(), ObjectId::new());
+        let project = doc! {"total_price": 99.0};
+        let mut report = ConsistencyReport::default();
+        assert_eq!(
+            check_price(
+                &mut report,
+                "p",
+                &project,
+                &[priced.clone(), unpriced.clone()],
+                &prices(&[(&priced, Some(10.0)), (&unpriced, None)])
+            ),
+            None
+        );
+        assert!(report.issues.is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use super::{ClientStatus, DirectoryError, UserDirectory};
+use actix_web::client::Client;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Deserialize)]
+struct ClientRecord {
+    #[serde(default)]
+    disabled: bool,
+}
+
+/// Asks the user service for `GET <base url>/clients/<client id>`: a 404 is an unknown
+/// client, a record with `"disabled": true` a disabled one. Active clients are cached
+/// for `cache_ttl`, other answers are not so a new or re-enabled client is seen at once.
+pub struct HttpUserDirectory {
+    client: Client,
+    base_url: String,
+    token: Option<String>,
+    cache_ttl: Duration,
+    active: RefCell<HashMap<String, Instant>>,
+}
+
+impl HttpUserDirectory {
+    pub fn new(
+        base_url: &str,
+        token: Option<String>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    ) -> HttpUserDirectory {
+        HttpUserDirectory {
+            client: Client::builder().timeout(timeout).finish(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            cache_ttl,
+            active: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn is_cached(&self, client_id: &str) -> bool {
+        let mut active = self.active.borrow_mut();
+        match active.get(client_id) {
+            Some(checked_at) if checked_at.elapsed() < self.cache_ttl => true,
+            Some(_) => {
+                active.remove(client_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Expired entries go as new ones come in, the cache never holds more than the
+    /// clients seen active within `cache_ttl`.
+    fn remember(&self, client_id: &str) {
+        let mut active = self.active.borrow_mut();
+        active.retain(|_client_id, checked_at| checked_at.elapsed() < self.cache_ttl);
+        active.insert(client_id.to_string(), Instant::now());
+    }
+}
+
+#[async_trait(?Send)]
+impl UserDirectory for HttpUserDirectory {
+    fn name(&self) -> &'static str {
+        "http user directory"
+    }
+
+    async fn client_status(&self, client_id: &str) -> Result<ClientStatus, DirectoryError> {
+        if self.is_cached(client_id) {
+            return Ok(ClientStatus::Active);
+        }
+        let mut request = self
+            .client
+            .get(format!("{}/clients/{}", self.base_url, client_id));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let mut response = request
+            .send()
+            .await
+            .map_err(|error| DirectoryError::Unavailable(error.to_string()))?;
+        match response.status().as_u16() {
+            200 => {
+                let record = response
+                    .json::<ClientRecord>()
+                    .await
+                    .map_err(|error| DirectoryError::Unavailable(error.to_string()))?;
+                if record.disabled {
+                    Ok(ClientStatus::Disabled)
+                } else {
+                    self.remember(client_id);
+                    Ok(ClientStatus::Active)
+                }
+            }
+            404 => Ok(ClientStatus::Unknown),
+            status => Err(DirectoryError::Unavailable(format!(
+                "{} answered {}",
+                self.base_url, status
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(cache_ttl: Duration) -> HttpUserDirectory {
+        HttpUserDirectory::new(
+            "http://127.0.0.1:9",
+            None,
+            Duration::from_millis(100),
+            cache_ttl,
+        )
+    }
+
+    #[actix_rt::test]
+    async fn active_clients_are_answered_from_the_cache() {
+        let directory = directory(Duration::from_secs(60));
+        directory.remember("client-1");
+        assert_eq!(
+            directory.client_status("client-1").await.unwrap(),
+            ClientStatus::Active
+        );
+        assert!(directory.client_status("client-2").await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn expired_entries_are_dropped_when_others_come_in() {
+        let directory = directory(Duration::from_secs(0));
+        directory.remember("client-1");
+        directory.remember("client-2");
+        let active = directory.active.borrow();
+        assert!(!active.contains_key("client-1"));
+        assert!(active.contains_key("client-2"));
+    }
+
+    #[actix_rt::test]
+    async fn expired_entries_are_asked_for_again() {
+        let directory = directory(Duration::from_secs(0));
+        directory.remember("client-1");
+        assert!(!directory.is_cached("client-1"));
+        assert!(directory.active.borrow().is_empty());
+    }
+}
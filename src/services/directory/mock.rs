@@ -0,0 +1,47 @@
+use super::{ClientStatus, DirectoryError, UserDirectory};
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+/// For tests and local runs: every client is active but the ones listed as disabled,
+/// unknown, or as if the directory could not be reached.
+pub struct MockUserDirectory {
+    disabled: HashSet<String>,
+    unknown: HashSet<String>,
+    unavailable: HashSet<String>,
+}
+
+impl MockUserDirectory {
+    pub fn new(
+        disabled: HashSet<String>,
+        unknown: HashSet<String>,
+        unavailable: HashSet<String>,
+    ) -> MockUserDirectory {
+        MockUserDirectory {
+            disabled,
+            unknown,
+            unavailable,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl UserDirectory for MockUserDirectory {
+    fn name(&self) -> &'static str {
+        "mock user directory"
+    }
+
+    async fn client_status(&self, client_id: &str) -> Result<ClientStatus, DirectoryError> {
+        if self.unavailable.contains(client_id) {
+            Err(DirectoryError::Unavailable(format!(
+                "{} listed as unavailable",
+                client_id
+            )))
+        } else if self.unknown.contains(client_id) {
+            Ok(ClientStatus::Unknown)
+        } else if self.disabled.contains(client_id) {
+            Ok(ClientStatus::Disabled)
+        } else {
+            Ok(ClientStatus::Active)
+        }
+    }
+}
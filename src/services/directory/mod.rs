@@ -0,0 +1,185 @@
+pub mod http;
+pub mod mock;
+
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+use derive_more::Display;
+use std::{collections::HashSet, env, time::Duration};
+
+pub use http::HttpUserDirectory;
+pub use mock::MockUserDirectory;
+
+#[derive(Debug, Display)]
+pub enum DirectoryError {
+    #[display(fmt = "user directory unavailable: {}", _0)]
+    Unavailable(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientStatus {
+    Active,
+    Disabled,
+    Unknown,
+}
+
+/// The user service that owns `client_id`s, asked whether a client exists and may
+/// still have projects.
+#[async_trait(?Send)]
+pub trait UserDirectory {
+    fn name(&self) -> &'static str;
+
+    async fn client_status(&self, client_id: &str) -> Result<ClientStatus, DirectoryError>;
+}
+
+/// Directory settings read once at startup and handed to every worker, which builds
+/// its own client from them.
+#[derive(Debug, Clone)]
+pub enum DirectoryConfig {
+    Http {
+        base_url: String,
+        token: Option<String>,
+        timeout: Duration,
+        cache_ttl: Duration,
+    },
+    Mock {
+        disabled: HashSet<String>,
+        unknown: HashSet<String>,
+        unavailable: HashSet<String>,
+    },
+}
+
+impl DirectoryConfig {
+    /// `USER_DIRECTORY` has no default: a deployment that forgets it would otherwise
+    /// check clients against the mock and let every one of them through.
+    pub fn from_env() -> DirectoryConfig {
+        match env::var("USER_DIRECTORY").unwrap_or_default().as_str() {
+            "http" => DirectoryConfig::Http {
+                base_url: env::var("USER_DIRECTORY_URL").expect("USER_DIRECTORY_URL must be set"),
+                token: env::var("USER_DIRECTORY_TOKEN").ok(),
+                timeout: Duration::from_millis(number("USER_DIRECTORY_TIMEOUT_MS", 2000)),
+                cache_ttl: Duration::from_secs(number("USER_DIRECTORY_CACHE_SECONDS", 300)),
+            },
+            "mock" => DirectoryConfig::Mock {
+                disabled: ids("USER_DIRECTORY_MOCK_DISABLED"),
+                unknown: ids("USER_DIRECTORY_MOCK_UNKNOWN"),
+                unavailable: ids("USER_DIRECTORY_MOCK_UNAVAILABLE"),
+            },
+            other => panic!("USER_DIRECTORY must be http or mock, not {:?}", other),
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn UserDirectory> {
+        match self {
+            DirectoryConfig::Http {
+                base_url,
+                token,
+                timeout,
+                cache_ttl,
+            } => Box::new(HttpUserDirectory::new(
+                base_url,
+                token.clone(),
+                *timeout,
+                *cache_ttl,
+            )),
+            DirectoryConfig::Mock {
+                disabled,
+                unknown,
+                unavailable,
+            } => Box::new(MockUserDirectory::new(
+                disabled.clone(),
+                unknown.clone(),
+                unavailable.clone(),
+            )),
+        }
+    }
+}
+
+fn number(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Comma separated client ids.
+fn ids(name: &str) -> HashSet<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Unknown clients are `NotFound`, disabled ones `NotAllowed`. A directory that cannot
+/// answer fails the request rather than letting an unchecked client through.
+pub async fn require_active_client(
+    directory: &dyn UserDirectory,
+    client_id: &str,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    ObjectId::with_string(client_id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    match directory.client_status(client_id).await {
+        Ok(ClientStatus::Active) => Ok(()),
+        Ok(ClientStatus::Disabled) => Err(ContentBuilderCustomResponseError::NotAllowed),
+        Ok(ClientStatus::Unknown) => Err(ContentBuilderCustomResponseError::NotFound),
+        Err(error) => {
+            tracing::warn!("{}", error);
+            Err(ContentBuilderCustomResponseError::Unavailable)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACTIVE: &str = "5f8d0d55b54764421b7156c1";
+    const DISABLED: &str = "5f8d0d55b54764421b7156c2";
+    const UNKNOWN: &str = "5f8d0d55b54764421b7156c3";
+    const UNAVAILABLE: &str = "5f8d0d55b54764421b7156c4";
+
+    fn directory() -> MockUserDirectory {
+        let ids = |id: &str| vec![id.to_string()].into_iter().collect();
+        MockUserDirectory::new(ids(DISABLED), ids(UNKNOWN), ids(UNAVAILABLE))
+    }
+
+    #[actix_rt::test]
+    async fn active_clients_pass() {
+        assert!(require_active_client(&directory(), ACTIVE).await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn disabled_clients_are_not_allowed() {
+        assert!(matches!(
+            require_active_client(&directory(), DISABLED).await,
+            Err(ContentBuilderCustomResponseError::NotAllowed)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn unknown_clients_are_not_found() {
+        assert!(matches!(
+            require_active_client(&directory(), UNKNOWN).await,
+            Err(ContentBuilderCustomResponseError::NotFound)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn an_unreachable_directory_fails_the_request() {
+        assert!(matches!(
+            require_active_client(&directory(), UNAVAILABLE).await,
+            Err(ContentBuilderCustomResponseError::Unavailable)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn malformed_client_ids_are_refused_before_asking() {
+        assert!(matches!(
+            require_active_client(&directory(), "client-1").await,
+            Err(ContentBuilderCustomResponseError::BadClientData)
+        ));
+    }
+}
@@ -0,0 +1,523 @@
+use crate::controllers::schema::{FeatureDeserializeModel, ProtoTypeObject};
+use crate::services::assets::{is_private_key, media_key};
+use crate::services::storage::{is_valid_key, StorageBackend};
+use actix_rt::time::timeout;
+use actix_web::{client::Client, http::Uri};
+use std::{
+    collections::HashMap,
+    env,
+    io::{Cursor, Write},
+    time::{Duration, Instant},
+};
+use zip::{result::ZipResult, write::FileOptions, ZipWriter};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Spent collecting wireframes for one export, the ones left after it keep their url.
+const EXPORT_TIME_BUDGET: Duration = Duration::from_secs(30);
+const MAX_ASSET_SIZE: usize = 20 * 1024 * 1024;
+const MAX_BUNDLE_ASSETS_SIZE: usize = 100 * 1024 * 1024;
+
+const STYLESHEET: &str = "body{font-family:sans-serif;margin:0;background:#f4f5f7;color:#222}\
+header{background:#222;color:#fff;padding:12px 24px}header a{color:#fff}\
+main{padding:24px}nav a{display:inline-block;margin:4px 8px 4px 0}\
+.wireframe{position:relative;display:inline-block;margin:0 16px 16px 0}\
+.wireframe img{display:block;max-width:100%}\
+.hotspot{position:absolute;display:block;background:rgba(0,120,255,.15);border:1px solid rgba(0,120,255,.6)}\
+.hotspot:hover{background:rgba(0,120,255,.3)}";
+
+/// A wireframe image copied into the bundle so it works offline.
+pub struct ExportAsset {
+    pub path: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Collects every wireframe of the given features, keyed by wireframe id. Uploaded
+/// wireframes are read from storage. Any other url is only downloaded from a host listed
+/// in `EXPORT_FETCH_HOSTS`, the server never requests an arbitrary url it was handed.
+/// Wireframes that cannot be read, or that no longer fit the time and size budget of the
+/// export, keep their url.
+pub async fn fetch_wireframes(
+    storage: &dyn StorageBackend,
+    features: &HashMap<String, FeatureDeserializeModel>,
+) -> HashMap<String, ExportAsset> {
+    let fetch_hosts = fetch_hosts();
+    let client = Client::builder().timeout(FETCH_TIMEOUT).finish();
+    let deadline = Instant::now() + EXPORT_TIME_BUDGET;
+    let mut total_size = 0;
+    let mut assets = HashMap::new();
+    for wireframe in features
+        .values()
+        .flat_map(|feature| feature.wireframes.iter().flatten())
+    {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) => remaining,
+            None => break,
+        };
+        let read = async {
+            match media_key(&wireframe.src) {
+                Some(key) => read_stored(storage, &key).await,
+                None if is_fetchable(&wireframe.src, &fetch_hosts) => {
+                    download(&client, &wireframe.src).await
+                }
+                None => None,
+            }
+        };
+        let bytes = match timeout(remaining, read).await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            Err(_elapsed) => break,
+        };
+        if bytes.len() > MAX_ASSET_SIZE || total_size + bytes.len() > MAX_BUNDLE_ASSETS_SIZE {
+            continue;
+        }
+        total_size += bytes.len();
+        let id = wireframe._id.to_string();
+        assets.insert(
+            id.clone(),
+            ExportAsset {
+                path: format!("assets/{}.{}", id, extension(&wireframe.src)),
+                bytes,
+            },
+        );
+    }
+    assets
+}
+
+/// Deliverables are never copied into a bundle, whatever a wireframe points at.
+async fn read_stored(storage: &dyn StorageBackend, key: &str) -> Option<Vec<u8>> {
+    if !is_valid_key(key) || is_private_key(key) {
+        return None;
+    }
+    storage.get(key).await.ok()
+}
+
+async fn download(client: &Client, src: &str) -> Option<Vec<u8>> {
+    let mut response = match client.get(src).send().await {
+        Ok(response) if response.status().is_success() => response,
+        _ => return None,
+    };
+    response
+        .body()
+        .limit(MAX_ASSET_SIZE)
+        .await
+        .ok()
+        .map(|bytes| bytes.to_vec())
+}
+
+/// `EXPORT_FETCH_HOSTS`, comma separated, e.g. `cdn.example.com,images.example.com`.
+fn fetch_hosts() -> Vec<String> {
+    env::var("EXPORT_FETCH_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Plain http(s) to an allowed host on the default port of its scheme, so an allowed
+/// name cannot be used to reach another service running on the same machine.
+fn is_fetchable(src: &str, fetch_hosts: &[String]) -> bool {
+    let uri = match src.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(_uri_error) => return false,
+    };
+    let default_port = match uri.scheme_str() {
+        Some("http") => 80,
+        Some("https") => 443,
+        _ => return false,
+    };
+    uri.port_u16().is_none_or(|port| port == default_port)
+        && uri.host().is_some_and(|host| {
+            fetch_hosts
+                .iter()
+                .any(|allowed| host.eq_ignore_ascii_case(allowed))
+        })
+}
+
+/// Builds the zipped static site: an index plus one page per prototype node, linked
+/// along the prototype connections and the wireframe hotspots.
+pub fn build_bundle(
+    title: &str,
+    nodes: &[ProtoTypeObject],
+    features: &HashMap<String, FeatureDeserializeModel>,
+    assets: &HashMap<String, ExportAsset>,
+) -> ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default();
+
+    zip.start_file("style.css", options)?;
+    zip.write_all(STYLESHEET.as_bytes())?;
+
+    let nodes: Vec<&ProtoTypeObject> = nodes
+        .iter()
+        .filter(|node| features.contains_key(&node.feature_id.to_string()))
+        .collect();
+    let feature_name = |id: &str| {
+        features
+            .get(id)
+            .map(|feature| feature.name.clone())
+            .unwrap_or_default()
+    };
+
+    let mut index = String::new();
+    for node in &nodes {
+        let id = node.feature_id.to_string();
+        index.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>",
+            page_name(&id),
+            escape(&feature_name(&id))
+        ));
+    }
+    zip.start_file("index.html", options)?;
+    zip.write_all(page(title, title, &format!("<ul>{}</ul>", index)).as_bytes())?;
+
+    for node in &nodes {
+        let id = node.feature_id.to_string();
+        let feature = &features[&id];
+        let mut body = String::new();
+        body.push_str(&format!("<p>{}</p><nav>", escape(&feature.description)));
+        for connection in &node.connections {
+            let to = connection.to.to_string();
+            if !features.contains_key(&to) {
+                continue;
+            }
+            let arrow = match (connection.releations.forword, connection.releations.back) {
+                (true, true) => "&harr;",
+                (false, true) => "&larr;",
+                _ => "&rarr;",
+            };
+            body.push_str(&format!(
+                "<a href=\"{}\">{} {}</a>",
+                page_name(&to),
+                arrow,
+                escape(&feature_name(&to))
+            ));
+        }
+        body.push_str("</nav>");
+
+        for wireframe in feature.wireframes.iter().flatten() {
+            let wireframe_id = wireframe._id.to_string();
+            let src = match assets.get(&wireframe_id) {
+                Some(asset) => asset.path.clone(),
+                None => wireframe.src.clone(),
+            };
+            body.push_str(&format!(
+                "<div class=\"wireframe\"><img src=\"{}\" alt=\"{}\">",
+                escape(&src),
+                escape(&wireframe.name)
+            ));
+            for hotspot in node
+                .hotspots
+                .iter()
+                .filter(|hotspot| hotspot.wireframe_id == wireframe._id)
+            {
+                let to = hotspot.to.to_string();
+                if !features.contains_key(&to) {
+                    continue;
+                }
+                body.push_str(&format!(
+                    "<a class=\"hotspot\" href=\"{}\" title=\"{}\" \
+                     style=\"left:{:.4}%;top:{:.4}%;width:{:.4}%;height:{:.4}%\"></a>",
+                    page_name(&to),
+                    escape(&feature_name(&to)),
+                    hotspot.region.x * 100.0,
+                    hotspot.region.y * 100.0,
+                    hotspot.region.width * 100.0,
+                    hotspot.region.height * 100.0
+                ));
+            }
+            body.push_str("</div>");
+        }
+
+        zip.start_file(page_name(&id), options)?;
+        zip.write_all(page(title, &feature.name, &body).as_bytes())?;
+    }
+
+    for asset in assets.values() {
+        zip.start_file(asset.path.as_str(), options)?;
+        zip.write_all(&asset.bytes)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn page_name(feature_id: &str) -> String {
+    format!("feature-{}.html", feature_id)
+}
+
+fn page(title: &str, heading: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title>\
+         <link rel=\"stylesheet\" href=\"style.css\"></head><body>\
+         <header><a href=\"index.html\">{title}</a></header>\
+         <main><h1>{heading}</h1>{body}</main></body></html>",
+        title = escape(title),
+        heading = escape(heading),
+        body = body
+    )
+}
+
+fn extension(src: &str) -> String {
+    let path = src.split(['?', '#']).next().unwrap_or("");
+    match path.rsplit('/').next().and_then(|name| name.rsplit_once('.')) {
+        Some((_, extension))
+            if !extension.is_empty() && extension.chars().all(char::is_alphanumeric) =>
+        {
+            extension.to_lowercase()
+        }
+        _ => "img".to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::schema::{Connections, File, FileWithId, Hotspot, Region, Relations};
+    use bson::oid::ObjectId;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    fn hosts() -> Vec<String> {
+        vec!["allowed.com".to_string()]
+    }
+
+    #[test]
+    fn only_allowed_hosts_are_fetchable() {
+        assert!(is_fetchable("https://allowed.com/wireframe.png", &hosts()));
+        assert!(is_fetchable("http://ALLOWED.com/wireframe.png", &hosts()));
+        assert!(!is_fetchable(
+            "https://images.example.com/wireframe.png",
+            &hosts()
+        ));
+        assert!(!is_fetchable("https://allowed.com/wireframe.png", &[]));
+    }
+
+    #[test]
+    fn lookalike_hosts_are_not_fetchable() {
+        for src in [
+            "https://allowed.com.evil.io/wireframe.png",
+            "https://evilallowed.com/wireframe.png",
+            "https://sub.allowed.com/wireframe.png",
+            "https://allowed.com./wireframe.png",
+        ] {
+            assert!(!is_fetchable(src, &hosts()), "{}", src);
+        }
+    }
+
+    #[test]
+    fn userinfo_does_not_count_as_the_host() {
+        assert!(!is_fetchable(
+            "http://allowed.com@evil.io/wireframe.png",
+            &hosts()
+        ));
+        assert!(!is_fetchable(
+            "http://allowed.com:80@evil.io/wireframe.png",
+            &hosts()
+        ));
+        assert!(is_fetchable(
+            "http://user@allowed.com/wireframe.png",
+            &hosts()
+        ));
+    }
+
+    #[test]
+    fn only_default_ports_are_fetchable() {
+        assert!(is_fetchable(
+            "http://allowed.com:80/wireframe.png",
+            &hosts()
+        ));
+        assert!(is_fetchable(
+            "https://allowed.com:443/wireframe.png",
+            &hosts()
+        ));
+        assert!(!is_fetchable(
+            "http://allowed.com:8080/wireframe.png",
+            &hosts()
+        ));
+        assert!(!is_fetchable(
+            "https://allowed.com:80/wireframe.png",
+            &hosts()
+        ));
+    }
+
+    #[test]
+    fn other_schemes_and_relative_urls_are_not_fetchable() {
+        for src in [
+            "ftp://allowed.com/wireframe.png",
+            "file://allowed.com/etc/passwd",
+            "gopher://allowed.com/",
+            "//allowed.com/wireframe.png",
+            "/api/v1/builder/media/wireframes/1/login.png",
+            "allowed.com/wireframe.png",
+            "",
+        ] {
+            assert!(!is_fetchable(src, &hosts()), "{}", src);
+        }
+    }
+
+    #[test]
+    fn extension_comes_from_the_file_name() {
+        assert_eq!(extension("https://allowed.com/a/login.PNG"), "png");
+        assert_eq!(
+            extension("https://allowed.com/a/login.jpeg?v=2#top"),
+            "jpeg"
+        );
+        assert_eq!(extension("https://allowed.com/a.b/login"), "img");
+        assert_eq!(extension("https://allowed.com/a/login."), "img");
+        assert_eq!(extension("https://allowed.com/a/login.p/ng"), "img");
+    }
+
+    #[test]
+    fn escape_covers_html_special_characters() {
+        assert_eq!(
+            escape("<a href=\"x\" title='y'>&</a>"),
+            "&lt;a href=&quot;x&quot; title=&#39;y&#39;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    fn feature(id: &ObjectId, name: &str, wireframes: Vec<FileWithId>) -> FeatureDeserializeModel {
+        FeatureDeserializeModel {
+            _id: id.clone(),
+            name: name.to_string(),
+            description: format!("{} description", name),
+            feature_type: "screen".to_string(),
+            image: File {
+                name: "icon".to_string(),
+                src: "icon.png".to_string(),
+                thumbnail: None,
+                medium: None,
+            },
+            wireframes: Some(wireframes),
+            price: 0.0,
+            repo: String::new(),
+        }
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn bundle_links_pages_along_connections_and_hotspots() {
+        let (login, home, removed) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let (stored, linked) = (ObjectId::new(), ObjectId::new());
+        let wireframe = |id: &ObjectId, name: &str| FileWithId {
+            _id: id.clone(),
+            name: name.to_string(),
+            src: format!("https://allowed.com/{}.png", name),
+            thumbnail: None,
+            medium: None,
+            source: None,
+        };
+        let mut features = HashMap::new();
+        features.insert(
+            login.to_string(),
+            feature(
+                &login,
+                "Log <in>",
+                vec![wireframe(&stored, "login"), wireframe(&linked, "signup")],
+            ),
+        );
+        features.insert(home.to_string(), feature(&home, "Home", vec![]));
+        let connection = |to: &ObjectId| Connections {
+            to: to.clone(),
+            releations: Relations {
+                back: false,
+                forword: true,
+            },
+        };
+        let hotspot = |to: &ObjectId| Hotspot {
+            _id: ObjectId::new(),
+            wireframe_id: stored.clone(),
+            to: to.clone(),
+            region: Region {
+                x: 0.25,
+                y: 0.5,
+                width: 0.1,
+                height: 0.2,
+            },
+        };
+        let nodes = vec![
+            ProtoTypeObject {
+                feature_id: login.clone(),
+                connections: vec![connection(&home), connection(&removed)],
+                layout: None,
+                hotspots: vec![hotspot(&home), hotspot(&removed)],
+            },
+            ProtoTypeObject {
+                feature_id: home.clone(),
+                connections: vec![],
+                layout: None,
+                hotspots: vec![],
+            },
+            ProtoTypeObject {
+                feature_id: removed.clone(),
+                connections: vec![],
+                layout: None,
+                hotspots: vec![],
+            },
+        ];
+        let mut assets = HashMap::new();
+        assets.insert(
+            stored.to_string(),
+            ExportAsset {
+                path: format!("assets/{}.png", stored),
+                bytes: vec![1, 2, 3],
+            },
+        );
+
+        let bundle = build_bundle("Shop & co", &nodes, &features, &assets).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(bundle)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        let mut expected = vec![
+            "index.html".to_string(),
+            "style.css".to_string(),
+            page_name(&login.to_string()),
+            page_name(&home.to_string()),
+            format!("assets/{}.png", stored),
+        ];
+        expected.sort_unstable();
+        assert_eq!(names, expected);
+
+        let index = String::from_utf8(read(&mut archive, "index.html")).unwrap();
+        assert!(index.contains("<title>Shop &amp; co</title>"));
+        assert!(index.contains("Log &lt;in&gt;"));
+        assert!(!index.contains(&removed.to_string()));
+
+        let page = String::from_utf8(read(&mut archive, &page_name(&login.to_string()))).unwrap();
+        let home_page = page_name(&home.to_string());
+        assert!(page.contains(&format!("<a href=\"{}\">&rarr; Home</a>", home_page)));
+        assert!(page.contains(&format!(
+            "<img src=\"assets/{}.png\" alt=\"login\">",
+            stored
+        )));
+        assert!(page.contains("<img src=\"https://allowed.com/signup.png\" alt=\"signup\">"));
+        assert!(page.contains(&format!(
+            "<a class=\"hotspot\" href=\"{}\" title=\"Home\" \
+             style=\"left:25.0000%;top:50.0000%;width:10.0000%;height:20.0000%\"></a>",
+            home_page
+        )));
+        assert!(!page.contains(&removed.to_string()));
+
+        assert_eq!(
+            read(&mut archive, &format!("assets/{}.png", stored)),
+            vec![1, 2, 3]
+        );
+    }
+}
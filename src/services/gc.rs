@@ -0,0 +1,424 @@
+use crate::controllers::schema::{AssetDeserializeModel, AssetGcReportModel, OrphanedAssetModel};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::assets::media_key;
+use crate::services::storage::{StorageBackend, StoredObject};
+use crate::CollectionsContainer;
+use actix_rt::time::{interval_at, Instant};
+use bson::Bson;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::StreamExt;
+use mongodb::Cursor;
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+/// Prefixes written by the upload pipeline. Files elsewhere under the storage root
+/// predate it and are never swept as stray objects.
+const MANAGED_PREFIXES: [&str; 3] = ["blobs/", "private/", "renditions/"];
+
+pub fn default_grace_period_hours() -> i64 {
+    env::var("ASSET_GC_GRACE_PERIOD_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(24)
+}
+
+/// Mark and sweep over stored assets. Every string of every category, feature,
+/// template, project and prototype that points at the media route marks its key as
+/// referenced, trashed ones included until they are purged. Asset records marked by
+/// nothing and older than the grace period are orphaned, and so are objects under the
+/// managed prefixes that no record points at. Nothing is deleted in dry run mode.
+pub async fn collect_garbage(
+    container: &CollectionsContainer,
+    storage: &dyn StorageBackend,
+    dry_run: bool,
+    grace_period_hours: i64,
+) -> Result<AssetGcReportModel, ContentBuilderCustomResponseError> {
+    let cutoff = Utc::now() - Duration::hours(grace_period_hours);
+
+    let mut referenced = HashSet::new();
+    for cursor in [
+        container.category.find_all().await,
+        container.feature.find_all().await,
+        container.template.find_all().await,
+        container.project.find_all().await,
+        container.prototype.find_all().await,
+        container.category.find_trashed().await,
+        container.feature.find_trashed().await,
+        container.template.find_trashed().await,
+        container.project.find_trashed().await,
+        container.prototype.find_trashed().await,
+    ] {
+        let cursor =
+            cursor.map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+        mark(cursor, &mut referenced).await?;
+    }
+
+    // objects are listed before records are loaded, an upload landing in between is
+    // then seen through its record rather than taken for a stray object
+    let mut objects = HashMap::new();
+    for prefix in MANAGED_PREFIXES.iter() {
+        for object in storage
+            .list(prefix)
+            .await
+            .map_err(|_storage_error| ContentBuilderCustomResponseError::InternalError)?
+        {
+            objects.insert(object.key.clone(), object);
+        }
+    }
+    let assets = load_assets(
+        container
+            .asset
+            .find_all()
+            .await
+            .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?,
+    )
+    .await?;
+
+    let scanned_assets = assets.len();
+    let Sweep {
+        referenced_assets,
+        pending_assets,
+        orphaned,
+        orphaned_keys,
+        stray_objects,
+    } = classify(assets, &referenced, &objects, cutoff);
+
+    let reclaimed_bytes = orphaned_keys
+        .iter()
+        .chain(stray_objects.iter())
+        .filter_map(|key| objects.get(key))
+        .map(|object| object.size)
+        .sum();
+    let mut report = AssetGcReportModel {
+        dry_run,
+        grace_period_hours,
+        scanned_assets,
+        referenced_assets,
+        pending_assets,
+        orphaned_assets: orphaned.iter().map(orphaned_asset).collect(),
+        stray_objects: stray_objects.clone(),
+        deleted_assets: 0,
+        deleted_objects: 0,
+        reclaimed_bytes,
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    for asset in orphaned.iter() {
+        if let Ok(Some(_document)) = container.asset.delete_one(&asset._id.to_hex()).await {
+            report.deleted_assets += 1;
+        }
+    }
+    for key in orphaned_keys {
+        // an upload of the same content may have claimed the blob since it was marked
+        match container.asset.find_one_by_key(&key).await {
+            Ok(None) => {}
+            _ => continue,
+        }
+        if storage.delete(&key).await.is_ok() {
+            report.deleted_objects += 1;
+        }
+    }
+    for key in stray_objects {
+        if storage.delete(&key).await.is_ok() {
+            report.deleted_objects += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// What a sweep would remove, worked out before anything is deleted.
+struct Sweep {
+    referenced_assets: usize,
+    pending_assets: usize,
+    orphaned: Vec<AssetDeserializeModel>,
+    orphaned_keys: Vec<String>,
+    stray_objects: Vec<String>,
+}
+
+/// Splits the asset records into referenced, pending and orphaned ones. A key shared by
+/// a kept record and an orphaned one stays, so deduplicated blobs survive as long as
+/// any record still needs them. Stray objects are the ones older than the cutoff that
+/// no record claims.
+fn classify(
+    assets: Vec<AssetDeserializeModel>,
+    referenced: &HashSet<String>,
+    objects: &HashMap<String, StoredObject>,
+    cutoff: DateTime<Utc>,
+) -> Sweep {
+    let mut referenced_assets = 0;
+    let mut pending_assets = 0;
+    let mut kept_keys = HashSet::new();
+    let mut orphaned = Vec::new();
+    for asset in assets {
+        let keys = asset_keys(&asset);
+        if keys.iter().any(|key| referenced.contains(*key)) {
+            referenced_assets += 1;
+        } else if DateTime::<Utc>::from(asset.created_at) > cutoff {
+            pending_assets += 1;
+        } else {
+            orphaned.push(asset);
+            continue;
+        }
+        kept_keys.extend(keys.into_iter().map(String::from));
+    }
+
+    let mut orphaned_keys: Vec<String> = orphaned
+        .iter()
+        .flat_map(asset_keys)
+        .filter(|key| !kept_keys.contains(*key))
+        .map(String::from)
+        .collect();
+    orphaned_keys.sort();
+    orphaned_keys.dedup();
+    let mut stray_objects: Vec<String> = objects
+        .values()
+        .filter(|object| {
+            !kept_keys.contains(&object.key)
+                && !orphaned_keys.contains(&object.key)
+                && object.modified < cutoff
+        })
+        .map(|object| object.key.clone())
+        .collect();
+    stray_objects.sort();
+
+    Sweep {
+        referenced_assets,
+        pending_assets,
+        orphaned,
+        orphaned_keys,
+        stray_objects,
+    }
+}
+
+/// Sweeps every `interval_hours`, the first run happening one interval after startup.
+pub async fn run_periodically(
+    container: CollectionsContainer,
+    storage: Box<dyn StorageBackend>,
+    interval_hours: u64,
+) {
+    let every = std::time::Duration::from_secs(interval_hours * 3600);
+    let mut ticks = interval_at(Instant::now() + every, every);
+    loop {
+        ticks.tick().await;
+        match collect_garbage(
+            &container,
+            storage.as_ref(),
+            false,
+            default_grace_period_hours(),
+        )
+        .await
+        {
+            Ok(report) => tracing::info!(
+                deleted_assets = report.deleted_assets,
+                deleted_objects = report.deleted_objects,
+                reclaimed_bytes = report.reclaimed_bytes,
+                "asset gc done"
+            ),
+            Err(error) => tracing::error!(error = %error, "asset gc failed"),
+        }
+    }
+}
+
+async fn mark(
+    mut cursor: Cursor,
+    referenced: &mut HashSet<String>,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    while let Some(document) = cursor.next().await {
+        let document =
+            document.map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+        collect_keys(&Bson::Document(document), referenced);
+    }
+    Ok(())
+}
+
+fn collect_keys(value: &Bson, referenced: &mut HashSet<String>) {
+    match value {
+        Bson::String(url) => {
+            if let Some(key) = media_key(url) {
+                referenced.insert(key);
+            }
+        }
+        Bson::Array(values) => values
+            .iter()
+            .for_each(|value| collect_keys(value, referenced)),
+        Bson::Document(document) => document
+            .values()
+            .for_each(|value| collect_keys(value, referenced)),
+        _ => {}
+    }
+}
+
+/// A record that cannot be read aborts the sweep, its objects would otherwise look
+/// unreferenced.
+async fn load_assets(
+    mut cursor: Cursor,
+) -> Result<Vec<AssetDeserializeModel>, ContentBuilderCustomResponseError> {
+    let mut assets = Vec::new();
+    while let Some(document) = cursor.next().await {
+        let document =
+            document.map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+        assets.push(
+            bson::from_document::<AssetDeserializeModel>(document)
+                .map_err(|_bson_de_error| ContentBuilderCustomResponseError::InternalError)?,
+        );
+    }
+    Ok(assets)
+}
+
+fn asset_keys(asset: &AssetDeserializeModel) -> Vec<&str> {
+    vec![Some(&asset.key), asset.thumbnail.as_ref(), asset.medium.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect()
+}
+
+fn orphaned_asset(asset: &AssetDeserializeModel) -> OrphanedAssetModel {
+    OrphanedAssetModel {
+        id: asset._id.to_hex(),
+        kind: asset.kind.clone(),
+        key: asset.key.clone(),
+        name: asset.name.clone(),
+        size: asset.size,
+        created_at: DateTime::<Utc>::from(asset.created_at).to_rfc3339(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::assets::media_url;
+    use bson::{doc, oid::ObjectId};
+
+    fn asset(key: &str, created_at: DateTime<Utc>) -> AssetDeserializeModel {
+        AssetDeserializeModel {
+            _id: ObjectId::new(),
+            kind: "image".to_string(),
+            key: key.to_string(),
+            name: "wireframe.png".to_string(),
+            content_type: "image/png".to_string(),
+            size: 3,
+            owner: None,
+            created_at: created_at.into(),
+            hash: None,
+            thumbnail: None,
+            medium: None,
+        }
+    }
+
+    fn objects(keys: &[&str], modified: DateTime<Utc>) -> HashMap<String, StoredObject> {
+        keys.iter()
+            .map(|key| {
+                (
+                    key.to_string(),
+                    StoredObject {
+                        key: key.to_string(),
+                        size: 3,
+                        modified,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn referenced(keys: &[&str]) -> HashSet<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn keys_are_collected_from_nested_arrays_and_documents() {
+        let document = doc! {
+            "name": "Checkout",
+            "image": { "src": media_url("blobs/aa/image"), "name": "image.png" },
+            "wireframes": [
+                { "src": media_url("blobs/bb/wireframe"), "thumbnail": media_url("renditions/bb/thumbnail") },
+                [ media_url("private/cc/source"), "https://cdn.example.com/elsewhere.png" ],
+            ],
+            "prototype": [{ "layout": { "x": 1.0 }, "hotspots": [] }],
+            "price": 10.0,
+        };
+        let mut keys = HashSet::new();
+        collect_keys(&Bson::Document(document), &mut keys);
+        assert_eq!(
+            keys,
+            referenced(&[
+                "blobs/aa/image",
+                "blobs/bb/wireframe",
+                "renditions/bb/thumbnail",
+                "private/cc/source",
+            ])
+        );
+    }
+
+    #[test]
+    fn referenced_and_recent_assets_are_kept_and_old_unreferenced_ones_orphaned() {
+        let cutoff = Utc::now() - Duration::hours(24);
+        let old = cutoff - Duration::hours(1);
+        let mut thumbnailed = asset("blobs/aa/used", old);
+        thumbnailed.thumbnail = Some("renditions/aa/thumbnail".to_string());
+        let mut orphan = asset("blobs/bb/orphan", old);
+        orphan.medium = Some("renditions/bb/medium".to_string());
+        let sweep = classify(
+            vec![thumbnailed, asset("blobs/cc/fresh", Utc::now()), orphan],
+            &referenced(&["renditions/aa/thumbnail"]),
+            &HashMap::new(),
+            cutoff,
+        );
+        assert_eq!(sweep.referenced_assets, 1);
+        assert_eq!(sweep.pending_assets, 1);
+        assert_eq!(sweep.orphaned.len(), 1);
+        assert_eq!(sweep.orphaned[0].key, "blobs/bb/orphan");
+        assert_eq!(
+            sweep.orphaned_keys,
+            vec![
+                "blobs/bb/orphan".to_string(),
+                "renditions/bb/medium".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn a_blob_shared_with_a_kept_asset_is_never_deleted() {
+        let cutoff = Utc::now() - Duration::hours(24);
+        let old = cutoff - Duration::hours(1);
+        let mut kept = asset("blobs/aa/shared", old);
+        kept.thumbnail = Some("renditions/aa/kept".to_string());
+        let mut orphan = asset("blobs/aa/shared", old);
+        orphan.thumbnail = Some("renditions/aa/orphan".to_string());
+        let mut pending = asset("blobs/bb/shared", Utc::now());
+        pending.thumbnail = Some("renditions/bb/shared".to_string());
+        let sweep = classify(
+            vec![orphan, kept, asset("blobs/bb/shared", old), pending],
+            &referenced(&["renditions/aa/kept"]),
+            &objects(&["blobs/aa/shared", "blobs/bb/shared"], old),
+            cutoff,
+        );
+        assert_eq!(sweep.orphaned.len(), 2);
+        assert_eq!(
+            sweep.orphaned_keys,
+            vec!["renditions/aa/orphan".to_string()]
+        );
+        assert!(sweep.stray_objects.is_empty());
+    }
+
+    #[test]
+    fn old_objects_no_record_claims_are_stray() {
+        let cutoff = Utc::now() - Duration::hours(24);
+        let old = cutoff - Duration::hours(1);
+        let mut stored = objects(&["blobs/aa/kept", "blobs/bb/orphan", "blobs/cc/stray"], old);
+        stored.extend(objects(&["blobs/dd/uploading"], Utc::now()));
+        let sweep = classify(
+            vec![
+                asset("blobs/aa/kept", Utc::now()),
+                asset("blobs/bb/orphan", old),
+            ],
+            &HashSet::new(),
+            &stored,
+            cutoff,
+        );
+        assert_eq!(sweep.orphaned_keys, vec!["blobs/bb/orphan".to_string()]);
+        assert_eq!(sweep.stray_objects, vec!["blobs/cc/stray".to_string()]);
+    }
+}
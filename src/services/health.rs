@@ -0,0 +1,137 @@
+use crate::models::indexes::IndexSpec;
+use crate::services::indexes::{verify_indexes, IndexReport};
+use actix_rt::time::timeout;
+use bson::doc;
+use mongodb::{error::Error, Database};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// A probe that waits longer than this is as good as down to the platform.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub ok: bool,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CheckReport {
+    fn new(started: Instant, error: Option<String>) -> CheckReport {
+        CheckReport {
+            ok: error.is_none(),
+            latency_ms: started.elapsed().as_millis(),
+            error,
+        }
+    }
+}
+
+/// `degraded` when the service answers but slower or less strictly than it should,
+/// which is no reason to take it out of rotation.
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub degraded: bool,
+    pub database: CheckReport,
+    pub indexes: CheckReport,
+}
+
+impl ReadinessReport {
+    fn new(database: CheckReport, indexes: CheckReport) -> ReadinessReport {
+        ReadinessReport {
+            ready: database.ok,
+            degraded: database.ok && !indexes.ok,
+            database,
+            indexes,
+        }
+    }
+}
+
+pub async fn ping(db: &Database) -> Result<(), Error> {
+    db.run_command(doc! {"ping": 1}, None).await?;
+    Ok(())
+}
+
+/// Ready once the database answers. Missing or failed indexes only degrade it, as they
+/// do at startup, since the service runs without them. Driver errors name the cluster
+/// hosts, they are logged rather than returned to the caller.
+pub async fn readiness(db: &Database, declared: &[(&str, Vec<IndexSpec>)]) -> ReadinessReport {
+    let started = Instant::now();
+    let database = match timeout(CHECK_TIMEOUT, ping(db)).await {
+        Ok(Ok(())) => CheckReport::new(started, None),
+        Ok(Err(mongodb_error)) => {
+            tracing::warn!("readiness ping failed: {}", mongodb_error);
+            CheckReport::new(started, Some("database unreachable".to_string()))
+        }
+        Err(_elapsed) => CheckReport::new(started, Some("database timed out".to_string())),
+    };
+    let started = Instant::now();
+    let indexes = if !database.ok {
+        CheckReport::new(started, Some("skipped".to_string()))
+    } else {
+        match timeout(CHECK_TIMEOUT, verify_indexes(db, declared)).await {
+            Ok(report) if report.is_complete() => CheckReport::new(started, None),
+            Ok(report) => CheckReport::new(started, Some(incomplete_indexes(&report))),
+            Err(_elapsed) => CheckReport::new(started, Some("index check timed out".to_string())),
+        }
+    };
+    ReadinessReport::new(database, indexes)
+}
+
+fn incomplete_indexes(report: &IndexReport) -> String {
+    format!(
+        "missing {}",
+        report
+            .missing
+            .iter()
+            .chain(report.failed.iter())
+            .map(|finding| finding.split(':').next().unwrap_or_default())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(error: Option<&str>) -> CheckReport {
+        CheckReport::new(Instant::now(), error.map(String::from))
+    }
+
+    #[test]
+    fn incomplete_indexes_degrade_without_unreadying() {
+        let report = ReadinessReport::new(check(None), check(Some("missing projects.name_1")));
+        assert!(report.ready);
+        assert!(report.degraded);
+    }
+
+    #[test]
+    fn an_unreachable_database_is_not_ready() {
+        let report =
+            ReadinessReport::new(check(Some("database unreachable")), check(Some("skipped")));
+        assert!(!report.ready);
+        assert!(!report.degraded);
+    }
+
+    #[test]
+    fn a_healthy_service_is_ready() {
+        let report = ReadinessReport::new(check(None), check(None));
+        assert!(report.ready);
+        assert!(!report.degraded);
+    }
+
+    #[test]
+    fn missing_and_failed_indexes_are_named() {
+        let report = IndexReport {
+            missing: vec!["projects.name_1".to_string()],
+            failed: vec!["features: cannot list indexes, timed out".to_string()],
+            ..IndexReport::default()
+        };
+        assert_eq!(
+            incomplete_indexes(&report),
+            "missing projects.name_1, features"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crate::controllers::schema::{
+    FileWithId, Hotspot, HotspotRequestObject, ProtoTypeObject, Region,
+};
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use bson::oid::ObjectId;
+
+/// Checks the hotspots sent for one node: each one lies on a wireframe of the node's
+/// feature, fits inside it, and leads along one of the node's connections. Any
+/// hotspot that does not makes the whole list `BadClientData`.
+pub fn validate_hotspots(
+    requested: Vec<HotspotRequestObject>,
+    node: &ProtoTypeObject,
+    wireframes: &[FileWithId],
+) -> Result<Vec<Hotspot>, ContentBuilderCustomResponseError> {
+    let mut hotspots: Vec<Hotspot> = Vec::new();
+    for hotspot in requested {
+        let wireframe_id = ObjectId::with_string(&hotspot.wireframe_id)
+            .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+        let to = ObjectId::with_string(&hotspot.to)
+            .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+        let id = match hotspot.id {
+            Some(id) => ObjectId::with_string(&id)
+                .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?,
+            None => ObjectId::new(),
+        };
+        if !fits(&hotspot.region)
+            || !wireframes
+                .iter()
+                .any(|wireframe| wireframe._id == wireframe_id)
+            || !node
+                .connections
+                .iter()
+                .any(|connection| connection.to == to)
+        {
+            return Err(ContentBuilderCustomResponseError::BadClientData);
+        }
+        hotspots.push(Hotspot {
+            _id: id,
+            wireframe_id,
+            to,
+            region: hotspot.region,
+        });
+    }
+    Ok(hotspots)
+}
+
+/// Not empty and inside the image. NaN fails every comparison, so it never fits.
+fn fits(region: &Region) -> bool {
+    region.x >= 0.0
+        && region.y >= 0.0
+        && region.width > 0.0
+        && region.height > 0.0
+        && region.x + region.width <= 1.0
+        && region.y + region.height <= 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::schema::{Connections, Relations};
+
+    fn wireframe() -> FileWithId {
+        FileWithId {
+            _id: ObjectId::new(),
+            name: "login".to_string(),
+            src: "/api/v1/builder/media/wireframes/1/login.png".to_string(),
+            thumbnail: None,
+            medium: None,
+            source: None,
+        }
+    }
+
+    fn node(connected_to: &ObjectId) -> ProtoTypeObject {
+        ProtoTypeObject {
+            feature_id: ObjectId::new(),
+            connections: vec![Connections {
+                to: connected_to.clone(),
+                releations: Relations {
+                    back: false,
+                    forword: true,
+                },
+            }],
+            layout: None,
+            hotspots: vec![],
+        }
+    }
+
+    fn region(x: f64, y: f64, width: f64, height: f64) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn request(wireframe_id: &ObjectId, to: &ObjectId, region: Region) -> HotspotRequestObject {
+        HotspotRequestObject {
+            id: None,
+            wireframe_id: wireframe_id.to_hex(),
+            to: to.to_hex(),
+            region,
+        }
+    }
+
+    #[test]
+    fn hotspots_on_a_wireframe_along_a_connection_are_kept() {
+        let (wireframes, target) = (vec![wireframe()], ObjectId::new());
+        let existing = ObjectId::new();
+        let mut kept = request(&wireframes[0]._id, &target, region(0.5, 0.5, 0.5, 0.5));
+        kept.id = Some(existing.to_hex());
+        let hotspots = validate_hotspots(
+            vec![
+                kept,
+                request(&wireframes[0]._id, &target, region(0.0, 0.0, 0.1, 0.1)),
+            ],
+            &node(&target),
+            &wireframes,
+        )
+        .unwrap();
+        assert_eq!(hotspots.len(), 2);
+        assert_eq!(hotspots[0]._id, existing);
+        assert_eq!(hotspots[1].to, target);
+    }
+
+    #[test]
+    fn regions_must_fit_inside_the_wireframe() {
+        let (wireframes, target) = (vec![wireframe()], ObjectId::new());
+        for outside in [
+            region(-0.1, 0.0, 0.5, 0.5),
+            region(0.0, -0.1, 0.5, 0.5),
+            region(0.0, 0.0, 0.0, 0.5),
+            region(0.0, 0.0, 0.5, -0.5),
+            region(0.6, 0.0, 0.5, 0.5),
+            region(0.0, 0.6, 0.5, 0.5),
+            region(f64::NAN, 0.0, 0.5, 0.5),
+        ] {
+            assert!(validate_hotspots(
+                vec![request(&wireframes[0]._id, &target, outside)],
+                &node(&target),
+                &wireframes,
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn hotspots_must_sit_on_a_wireframe_of_the_feature() {
+        let (wireframes, target) = (vec![wireframe()], ObjectId::new());
+        let elsewhere = request(&ObjectId::new(), &target, region(0.0, 0.0, 0.5, 0.5));
+        assert!(validate_hotspots(vec![elsewhere], &node(&target), &wireframes).is_err());
+    }
+
+    #[test]
+    fn hotspots_must_follow_a_connection() {
+        let wireframes = vec![wireframe()];
+        let unconnected = request(
+            &wireframes[0]._id,
+            &ObjectId::new(),
+            region(0.0, 0.0, 0.5, 0.5),
+        );
+        assert!(
+            validate_hotspots(vec![unconnected], &node(&ObjectId::new()), &wireframes).is_err()
+        );
+    }
+
+    #[test]
+    fn malformed_ids_are_refused() {
+        let (wireframes, target) = (vec![wireframe()], ObjectId::new());
+        let mut malformed = request(&wireframes[0]._id, &target, region(0.0, 0.0, 0.5, 0.5));
+        malformed.id = Some("hotspot-1".to_string());
+        assert!(validate_hotspots(vec![malformed], &node(&target), &wireframes).is_err());
+    }
+}
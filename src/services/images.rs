@@ -0,0 +1,42 @@
+use image::{imageops::FilterType, GenericImageView, ImageError, ImageFormat, ImageOutputFormat};
+
+/// Longest edge, in pixels, of each rendition.
+pub const THUMBNAIL_SIZE: u32 = 240;
+pub const MEDIUM_SIZE: u32 = 960;
+
+const JPEG_QUALITY: u8 = 82;
+
+/// Extension and content type renditions of this image are encoded with. Photos stay
+/// jpeg, everything else becomes png so transparency survives. `None` when the bytes
+/// are not a raster format the pipeline can decode, e.g. svg.
+pub fn rendition_format(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Jpeg => Some(("jpg", "image/jpeg")),
+        ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP => Some(("png", "image/png")),
+        _ => None,
+    }
+}
+
+/// Decodes the image once and scales it to fit each bound. Images already smaller than
+/// a bound are re-encoded at their own size rather than upscaled.
+pub fn render(bytes: &[u8], bounds: &[u32]) -> Result<Vec<Vec<u8>>, ImageError> {
+    let jpeg = image::guess_format(bytes)? == ImageFormat::Jpeg;
+    let source = image::load_from_memory(bytes)?;
+    bounds
+        .iter()
+        .map(|&bound| {
+            let resized = if source.width() > bound || source.height() > bound {
+                source.resize(bound, bound, FilterType::Lanczos3)
+            } else {
+                source.clone()
+            };
+            let mut encoded = Vec::new();
+            if jpeg {
+                resized.write_to(&mut encoded, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+            } else {
+                resized.write_to(&mut encoded, ImageOutputFormat::Png)?;
+            }
+            Ok(encoded)
+        })
+        .collect()
+}
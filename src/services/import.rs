@@ -0,0 +1,445 @@
+use crate::controllers::schema::{
+    Connections, DesignExport, FeatureDeserializeModel, FileWithId, Hotspot, ProtoTypeObject,
+    Region, Relations, UnmappedFrame,
+};
+use bson::oid::ObjectId;
+use std::collections::HashMap;
+
+pub struct ImportPlan {
+    /// Wireframes to write, with the feature each one belongs to. A frame imported
+    /// before keeps the id of its wireframe, it replaces that wireframe.
+    pub wireframes: Vec<(ObjectId, FileWithId)>,
+    pub nodes: Vec<ProtoTypeObject>,
+    pub unmapped_frames: Vec<UnmappedFrame>,
+    pub skipped_links: usize,
+}
+
+/// Maps frames to features (explicit mapping first, then case-insensitive name match)
+/// and turns the links between mapped frames into prototype connections and hotspots.
+/// Link regions that end up empty once clamped to their frame are dropped.
+/// Returns `None` when the explicit mapping names an invalid or unknown feature.
+pub fn plan_import(
+    export: &DesignExport,
+    mapping: &HashMap<String, String>,
+    features: &[FeatureDeserializeModel],
+) -> Option<ImportPlan> {
+    let mut frame_features: HashMap<&str, ObjectId> = HashMap::new();
+    let mut frame_wireframes: HashMap<&str, ObjectId> = HashMap::new();
+    let mut wireframes = Vec::new();
+    let mut unmapped_frames = Vec::new();
+
+    for frame in &export.frames {
+        let feature_id = match mapping.get(&frame.id) {
+            Some(feature_id) => {
+                let feature_id = ObjectId::with_string(feature_id).ok()?;
+                if !features.iter().any(|feature| feature._id == feature_id) {
+                    return None;
+                }
+                Some(feature_id)
+            }
+            None => features
+                .iter()
+                .find(|feature| feature.name.trim().eq_ignore_ascii_case(frame.name.trim()))
+                .map(|feature| feature._id.clone()),
+        };
+        match feature_id {
+            Some(feature_id) => {
+                let source = frame_source(export, &frame.id);
+                let imported_before = source.as_ref().and_then(|source| {
+                    features
+                        .iter()
+                        .filter(|feature| feature._id == feature_id)
+                        .flat_map(|feature| feature.wireframes.iter().flatten())
+                        .find(|wireframe| wireframe.source.as_ref() == Some(source))
+                });
+                let wireframe = FileWithId {
+                    _id: match imported_before {
+                        Some(wireframe) => wireframe._id.clone(),
+                        None => ObjectId::new(),
+                    },
+                    name: frame.image.name.clone(),
+                    src: frame.image.src.clone(),
+                    thumbnail: frame.image.thumbnail.clone(),
+                    medium: frame.image.medium.clone(),
+                    source,
+                };
+                frame_features.insert(frame.id.as_str(), feature_id.clone());
+                frame_wireframes.insert(frame.id.as_str(), wireframe._id.clone());
+                wireframes.push((feature_id, wireframe));
+            }
+            None => unmapped_frames.push(UnmappedFrame {
+                id: frame.id.clone(),
+                name: frame.name.clone(),
+            }),
+        }
+    }
+
+    let mut nodes: Vec<ProtoTypeObject> = Vec::new();
+    for feature_id in export
+        .frames
+        .iter()
+        .filter_map(|frame| frame_features.get(frame.id.as_str()))
+    {
+        if !nodes.iter().any(|node| &node.feature_id == feature_id) {
+            nodes.push(ProtoTypeObject {
+                feature_id: feature_id.clone(),
+                connections: vec![],
+                layout: None,
+                hotspots: vec![],
+            });
+        }
+    }
+
+    let mut skipped_links = 0;
+    for link in &export.links {
+        let (from, to) = match (
+            frame_features.get(link.from.as_str()),
+            frame_features.get(link.to.as_str()),
+        ) {
+            (Some(from), Some(to)) if from != to => (from.clone(), to.clone()),
+            _ => {
+                skipped_links += 1;
+                continue;
+            }
+        };
+        let node = nodes
+            .iter_mut()
+            .find(|node| node.feature_id == from)
+            .expect("every mapped feature has a node");
+        match node
+            .connections
+            .iter_mut()
+            .find(|connection| connection.to == to)
+        {
+            Some(connection) => {
+                connection.releations.forword = true;
+                connection.releations.back |= link.back;
+            }
+            None => node.connections.push(Connections {
+                to: to.clone(),
+                releations: Relations {
+                    back: link.back,
+                    forword: true,
+                },
+            }),
+        }
+        let frame = export
+            .frames
+            .iter()
+            .find(|frame| frame.id == link.from)
+            .expect("mapped frames come from the export");
+        if let (Some(region), Some(width), Some(height)) = (link.region, frame.width, frame.height)
+        {
+            if width > 0.0 && height > 0.0 {
+                let x = (region.x / width).clamp(0.0, 1.0);
+                let y = (region.y / height).clamp(0.0, 1.0);
+                let region = Region {
+                    x,
+                    y,
+                    width: (region.width / width).clamp(0.0, 1.0 - x),
+                    height: (region.height / height).clamp(0.0, 1.0 - y),
+                };
+                // `prototype/hotspots` refuses empty regions, so does the import
+                if region.width > 0.0 && region.height > 0.0 {
+                    node.hotspots.push(Hotspot {
+                        _id: ObjectId::new(),
+                        wireframe_id: frame_wireframes[link.from.as_str()].clone(),
+                        to,
+                        region,
+                    });
+                }
+            }
+        }
+    }
+
+    Some(ImportPlan {
+        wireframes,
+        nodes,
+        unmapped_frames,
+        skipped_links,
+    })
+}
+
+/// Identifies a frame across imports of the same design file, by the file key or, for
+/// exports without one, the file name. Frame ids are only unique within a file, a frame
+/// of an export carrying neither is never matched to an earlier import.
+fn frame_source(export: &DesignExport, frame_id: &str) -> Option<String> {
+    export
+        .file_key
+        .as_deref()
+        .or(export.name.as_deref())
+        .filter(|file| !file.trim().is_empty())
+        .map(|file| format!("{}#{}", file, frame_id))
+}
+
+/// Folds imported nodes into an existing prototype, keeping existing layout and
+/// connections. Hotspots on the imported wireframes are replaced by the imported ones,
+/// so importing the same design again does not duplicate them. Returns the merged
+/// nodes and how many connections were added.
+pub fn merge_nodes(
+    existing: Vec<ProtoTypeObject>,
+    imported: Vec<ProtoTypeObject>,
+    imported_wireframes: &[ObjectId],
+) -> (Vec<ProtoTypeObject>, usize) {
+    let mut merged = existing;
+    let mut added = 0;
+    for node in imported {
+        match merged
+            .iter_mut()
+            .find(|existing| existing.feature_id == node.feature_id)
+        {
+            Some(existing) => {
+                for connection in node.connections {
+                    if !existing
+                        .connections
+                        .iter()
+                        .any(|current| current.to == connection.to)
+                    {
+                        existing.connections.push(connection);
+                        added += 1;
+                    }
+                }
+                existing
+                    .hotspots
+                    .retain(|hotspot| !imported_wireframes.contains(&hotspot.wireframe_id));
+                existing.hotspots.extend(node.hotspots);
+            }
+            None => {
+                added += node.connections.len();
+                merged.push(node);
+            }
+        }
+    }
+    (merged, added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::schema::{DesignFrame, DesignLink, File};
+
+    fn feature(name: &str, wireframes: Option<Vec<FileWithId>>) -> FeatureDeserializeModel {
+        FeatureDeserializeModel {
+            _id: ObjectId::new(),
+            name: name.to_string(),
+            description: String::new(),
+            feature_type: String::new(),
+            image: image("icon.png"),
+            wireframes,
+            price: 0.0,
+            repo: String::new(),
+        }
+    }
+
+    fn image(name: &str) -> File {
+        File {
+            name: name.to_string(),
+            src: format!("https://cdn.example.com/{}", name),
+            thumbnail: None,
+            medium: None,
+        }
+    }
+
+    fn frame(id: &str, name: &str) -> DesignFrame {
+        DesignFrame {
+            id: id.to_string(),
+            name: name.to_string(),
+            image: image(&format!("{}.png", name.to_lowercase())),
+            width: Some(400.0),
+            height: Some(800.0),
+        }
+    }
+
+    fn link(from: &str, to: &str, region: Option<Region>) -> DesignLink {
+        DesignLink {
+            from: from.to_string(),
+            to: to.to_string(),
+            back: false,
+            region,
+        }
+    }
+
+    fn export(links: Vec<DesignLink>) -> DesignExport {
+        DesignExport {
+            file_key: None,
+            name: Some("Onboarding".to_string()),
+            frames: vec![
+                frame("1:2", "Login"),
+                frame("1:7", "Home"),
+                frame("1:9", "Splash"),
+            ],
+            links,
+        }
+    }
+
+    #[test]
+    fn frames_map_by_name_and_links_become_connections_and_hotspots() {
+        let features = vec![feature("login", None), feature(" Home ", None)];
+        let plan = plan_import(
+            &export(vec![link(
+                "1:2",
+                "1:7",
+                Some(Region {
+                    x: 40.0,
+                    y: 700.0,
+                    width: 320.0,
+                    height: 50.0,
+                }),
+            )]),
+            &HashMap::new(),
+            &features,
+        )
+        .unwrap();
+        assert_eq!(plan.wireframes.len(), 2);
+        assert_eq!(plan.unmapped_frames.len(), 1);
+        assert_eq!(plan.unmapped_frames[0].id, "1:9");
+        assert_eq!(plan.nodes.len(), 2);
+        let login = &plan.nodes[0];
+        assert_eq!(login.feature_id, features[0]._id);
+        assert_eq!(login.connections.len(), 1);
+        assert_eq!(login.connections[0].to, features[1]._id);
+        assert_eq!(login.hotspots.len(), 1);
+        let region = login.hotspots[0].region;
+        assert_eq!((region.x, region.width), (0.1, 0.8));
+        assert_eq!(login.hotspots[0].wireframe_id, plan.wireframes[0].1._id);
+    }
+
+    #[test]
+    fn explicit_mapping_wins_and_must_name_a_known_feature() {
+        let features = vec![feature("login", None), feature("home", None)];
+        let mut mapping = HashMap::new();
+        mapping.insert("1:9".to_string(), features[1]._id.to_string());
+        let plan = plan_import(&export(vec![]), &mapping, &features).unwrap();
+        assert!(plan.unmapped_frames.is_empty());
+        assert_eq!(plan.wireframes.len(), 3);
+
+        mapping.insert("1:9".to_string(), ObjectId::new().to_string());
+        assert!(plan_import(&export(vec![]), &mapping, &features).is_none());
+        mapping.insert("1:9".to_string(), "not an id".to_string());
+        assert!(plan_import(&export(vec![]), &mapping, &features).is_none());
+    }
+
+    #[test]
+    fn links_to_unmapped_frames_are_skipped() {
+        let features = vec![feature("login", None)];
+        let plan = plan_import(
+            &export(vec![link("1:2", "1:9", None), link("1:2", "1:2", None)]),
+            &HashMap::new(),
+            &features,
+        )
+        .unwrap();
+        assert_eq!(plan.skipped_links, 2);
+        assert!(plan.nodes[0].connections.is_empty());
+    }
+
+    #[test]
+    fn regions_empty_once_clamped_are_dropped() {
+        let features = vec![feature("login", None), feature("home", None)];
+        let plan = plan_import(
+            &export(vec![link(
+                "1:2",
+                "1:7",
+                Some(Region {
+                    x: 400.0,
+                    y: 100.0,
+                    width: 50.0,
+                    height: 50.0,
+                }),
+            )]),
+            &HashMap::new(),
+            &features,
+        )
+        .unwrap();
+        assert_eq!(plan.nodes[0].connections.len(), 1);
+        assert!(plan.nodes[0].hotspots.is_empty());
+    }
+
+    #[test]
+    fn frames_imported_before_keep_their_wireframe() {
+        let previous = FileWithId {
+            _id: ObjectId::new(),
+            name: "old-login.png".to_string(),
+            src: "https://cdn.example.com/old-login.png".to_string(),
+            thumbnail: None,
+            medium: None,
+            source: Some("Onboarding#1:2".to_string()),
+        };
+        let features = vec![feature("login", Some(vec![previous.clone()]))];
+        let plan = plan_import(&export(vec![]), &HashMap::new(), &features).unwrap();
+        assert_eq!(plan.wireframes[0].1._id, previous._id);
+        assert_eq!(plan.wireframes[0].1.name, "login.png");
+    }
+
+    #[test]
+    fn frames_are_told_apart_by_the_file_they_come_from() {
+        let previous = FileWithId {
+            _id: ObjectId::new(),
+            name: "old-login.png".to_string(),
+            src: "https://cdn.example.com/old-login.png".to_string(),
+            thumbnail: None,
+            medium: None,
+            source: Some("hY5wWx3kTfLq#1:2".to_string()),
+        };
+        let features = vec![feature("login", Some(vec![previous.clone()]))];
+        let mut same_file = export(vec![]);
+        same_file.file_key = Some("hY5wWx3kTfLq".to_string());
+        let plan = plan_import(&same_file, &HashMap::new(), &features).unwrap();
+        assert_eq!(plan.wireframes[0].1._id, previous._id);
+
+        let mut other_file = export(vec![]);
+        other_file.file_key = Some("Qm2pZr8aLcVe".to_string());
+        let plan = plan_import(&other_file, &HashMap::new(), &features).unwrap();
+        assert_ne!(plan.wireframes[0].1._id, previous._id);
+        assert_eq!(
+            plan.wireframes[0].1.source.as_deref(),
+            Some("Qm2pZr8aLcVe#1:2")
+        );
+    }
+
+    #[test]
+    fn frames_of_unidentified_files_are_never_matched() {
+        let previous = FileWithId {
+            _id: ObjectId::new(),
+            name: "old-login.png".to_string(),
+            src: "https://cdn.example.com/old-login.png".to_string(),
+            thumbnail: None,
+            medium: None,
+            source: Some("#1:2".to_string()),
+        };
+        let features = vec![feature("login", Some(vec![previous.clone()]))];
+        let mut unnamed = export(vec![]);
+        unnamed.name = None;
+        let plan = plan_import(&unnamed, &HashMap::new(), &features).unwrap();
+        assert_ne!(plan.wireframes[0].1._id, previous._id);
+        assert_eq!(plan.wireframes[0].1.source, None);
+    }
+
+    #[test]
+    fn merge_keeps_existing_connections_and_replaces_imported_hotspots() {
+        let features = vec![feature("login", None), feature("home", None)];
+        let region = Some(Region {
+            x: 40.0,
+            y: 700.0,
+            width: 320.0,
+            height: 50.0,
+        });
+        let first = plan_import(
+            &export(vec![link("1:2", "1:7", region)]),
+            &HashMap::new(),
+            &features,
+        )
+        .unwrap();
+        let wireframes: Vec<ObjectId> = first
+            .wireframes
+            .iter()
+            .map(|(_, wireframe)| wireframe._id.clone())
+            .collect();
+        let (merged, added) = merge_nodes(vec![], first.nodes.clone(), &wireframes);
+        assert_eq!((merged.len(), added), (2, 1));
+
+        let (merged, added) = merge_nodes(merged, first.nodes, &wireframes);
+        assert_eq!(added, 0);
+        assert_eq!(merged[0].connections.len(), 1);
+        assert_eq!(merged[0].hotspots.len(), 1);
+    }
+}
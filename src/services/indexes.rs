@@ -0,0 +1,262 @@
+use crate::models::indexes::IndexSpec;
+use bson::{doc, Bson, Document};
+use mongodb::{
+    error::{CommandError, Error, ErrorKind},
+    Database,
+};
+use std::fmt;
+
+const NAMESPACE_NOT_FOUND: i32 = 26;
+
+#[derive(Debug, Default)]
+pub struct IndexReport {
+    pub verified: usize,
+    pub created: Vec<String>,
+    pub missing: Vec<String>,
+    pub drifted: Vec<String>,
+    pub undeclared: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl IndexReport {
+    /// Queries can rely on every declared index, drifted ones included.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for IndexReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Indexes: {} verified, {} created",
+            self.verified,
+            self.created.len()
+        )?;
+        for (label, findings) in [
+            ("created", &self.created),
+            ("missing", &self.missing),
+            ("drifted", &self.drifted),
+            ("undeclared", &self.undeclared),
+            ("failed", &self.failed),
+        ] {
+            for finding in findings {
+                write!(formatter, "\n  {:<10} {}", label, finding)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates the declared indexes missing from each collection and reports the rest of
+/// the difference: declared indexes whose keys or uniqueness changed, and indexes that
+/// nothing declares. Existing indexes are never dropped or rebuilt, a drifted index is
+/// for an operator to replace. A unique index that cannot be built because of duplicate
+/// data is reported as failed, the service still starts.
+pub async fn ensure_indexes(db: &Database, declared: &[(&str, Vec<IndexSpec>)]) -> IndexReport {
+    reconcile(db, declared, true).await
+}
+
+/// Compares without creating anything, declared indexes that do not exist yet are
+/// reported as missing.
+pub async fn verify_indexes(db: &Database, declared: &[(&str, Vec<IndexSpec>)]) -> IndexReport {
+    reconcile(db, declared, false).await
+}
+
+async fn reconcile(
+    db: &Database,
+    declared: &[(&str, Vec<IndexSpec>)],
+    create: bool,
+) -> IndexReport {
+    let mut report = IndexReport::default();
+    for (collection, specs) in declared {
+        let existing = match list_indexes(db, collection).await {
+            Ok(existing) => existing,
+            Err(mongodb_error) => {
+                report.failed.push(format!(
+                    "{}: cannot list indexes, {}",
+                    collection, mongodb_error
+                ));
+                continue;
+            }
+        };
+        for spec in specs {
+            let name = spec.name();
+            let found = existing.iter().find(|index| {
+                index.get_str("name") == Ok(name.as_str())
+                    || index
+                        .get_document("key")
+                        .is_ok_and(|keys| same_keys(keys, &spec.keys))
+            });
+            match found {
+                Some(index) => {
+                    let index_name = index.get_str("name").unwrap_or_default();
+                    let unique = index.get_bool("unique").unwrap_or(false);
+                    let keys_match = index
+                        .get_document("key")
+                        .is_ok_and(|keys| same_keys(keys, &spec.keys));
+                    if index_name != name {
+                        report.drifted.push(format!(
+                            "{}.{} is declared as {}",
+                            collection, index_name, name
+                        ));
+                    } else if !keys_match || unique != spec.unique {
+                        report.drifted.push(format!(
+                            "{}.{} should be {}{:?}",
+                            collection,
+                            name,
+                            if spec.unique { "unique " } else { "" },
+                            spec.keys
+                        ));
+                    } else {
+                        report.verified += 1;
+                    }
+                }
+                None if !create => report.missing.push(format!("{}.{}", collection, name)),
+                None => match create_index(db, collection, spec).await {
+                    Ok(()) => report.created.push(format!("{}.{}", collection, name)),
+                    Err(mongodb_error) => report
+                        .failed
+                        .push(format!("{}.{}: {}", collection, name, mongodb_error)),
+                },
+            }
+        }
+        for index in existing.iter() {
+            let index_name = index.get_str("name").unwrap_or_default();
+            let keys = index.get_document("key").ok();
+            let is_declared = specs.iter().any(|spec| {
+                spec.name() == index_name || keys.is_some_and(|keys| same_keys(keys, &spec.keys))
+            });
+            if index_name != "_id_" && !is_declared {
+                report
+                    .undeclared
+                    .push(format!("{}.{}", collection, index_name));
+            }
+        }
+    }
+    report
+}
+
+async fn list_indexes(db: &Database, collection: &str) -> Result<Vec<Document>, Error> {
+    let reply = match db.run_command(doc! {"listIndexes": collection}, None).await {
+        Ok(reply) => reply,
+        Err(mongodb_error) => {
+            return match mongodb_error.kind.as_ref() {
+                // the collection is created along with its first index
+                ErrorKind::CommandError(CommandError { code, .. })
+                    if *code == NAMESPACE_NOT_FOUND =>
+                {
+                    Ok(Vec::new())
+                }
+                _ => Err(mongodb_error),
+            };
+        }
+    };
+    Ok(reply
+        .get_document("cursor")
+        .and_then(|cursor| cursor.get_array("firstBatch"))
+        .map(|indexes| {
+            indexes
+                .iter()
+                .filter_map(Bson::as_document)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+async fn create_index(db: &Database, collection: &str, spec: &IndexSpec) -> Result<(), Error> {
+    db.run_command(
+        doc! {
+            "createIndexes": collection,
+            "indexes": [
+                {
+                    "key": spec.keys.clone(),
+                    "name": spec.name(),
+                    "unique": spec.unique
+                }
+            ]
+        },
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Same fields in the same order with the same directions. Indexes built from the shell
+/// store directions as doubles.
+fn same_keys(existing: &Document, declared: &Document) -> bool {
+    existing.len() == declared.len()
+        && existing.iter().zip(declared.iter()).all(
+            |((existing_field, existing_direction), (field, direction))| {
+                existing_field == field
+                    && direction_of(existing_direction) == direction_of(direction)
+            },
+        )
+}
+
+fn direction_of(value: &Bson) -> Option<String> {
+    match value {
+        Bson::Int32(direction) => Some(direction.to_string()),
+        Bson::Int64(direction) => Some(direction.to_string()),
+        Bson::Double(direction) => Some((*direction as i64).to_string()),
+        Bson::String(kind) => Some(kind.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_fields_and_directions_match() {
+        assert!(same_keys(
+            &doc! {"template_id": 1, "revision": -1},
+            &doc! {"template_id": 1, "revision": -1}
+        ));
+    }
+
+    #[test]
+    fn directions_match_whatever_their_number_type() {
+        assert!(same_keys(
+            &doc! {"template_id": 1.0, "revision": -1_i64},
+            &doc! {"template_id": 1, "revision": -1}
+        ));
+        assert!(same_keys(&doc! {"name": "text"}, &doc! {"name": "text"}));
+    }
+
+    #[test]
+    fn field_order_matters() {
+        assert!(!same_keys(
+            &doc! {"revision": -1, "template_id": 1},
+            &doc! {"template_id": 1, "revision": -1}
+        ));
+    }
+
+    #[test]
+    fn different_directions_or_fields_do_not_match() {
+        assert!(!same_keys(
+            &doc! {"template_id": 1, "revision": 1},
+            &doc! {"template_id": 1, "revision": -1}
+        ));
+        assert!(!same_keys(
+            &doc! {"template_id": 1},
+            &doc! {"template_id": 1, "revision": -1}
+        ));
+        assert!(!same_keys(&doc! {"name": 1}, &doc! {"name": "text"}));
+    }
+
+    #[test]
+    fn drifted_indexes_still_count_as_complete() {
+        let mut report = IndexReport {
+            drifted: vec!["prototype_revisions.template_id_1_revision_-1".to_string()],
+            ..IndexReport::default()
+        };
+        assert!(report.is_complete());
+        report
+            .failed
+            .push("projects.name_1: duplicate key".to_string());
+        assert!(!report.is_complete());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::controllers::schema::{DeletePolicy, ProjectNotice};
+use crate::middleware::error::{ContentBuilderCustomResponseError, Dependent};
+use crate::services::consistency::price_of;
+use crate::CollectionsContainer;
+use bson::{oid::ObjectId, Document};
+use chrono::Utc;
+use futures::stream::StreamExt;
+use mongodb::{error::Error, Cursor};
+
+/// Clears the references to a feature that is about to be deleted, or refuses when
+/// the policy is restrictive and something still uses it.
+pub async fn release_feature(
+    container: &CollectionsContainer,
+    feature_id: &ObjectId,
+    policy: DeletePolicy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    let mut dependents = Vec::new();
+    if blocks(Released::Feature, Referrer::Templates, policy) {
+        dependents.extend(
+            dependents_of(
+                container.template.find_by_feature_id(feature_id).await,
+                Referrer::Templates,
+            )
+            .await?,
+        );
+    }
+    if blocks(Released::Feature, Referrer::Projects, policy) {
+        dependents.extend(
+            dependents_of(
+                container.project.find_by_feature_id(feature_id).await,
+                Referrer::Projects,
+            )
+            .await?,
+        );
+    }
+    if blocks(Released::Feature, Referrer::Prototypes, policy) {
+        dependents.extend(
+            dependents_of(
+                container.prototype.find_by_feature_id(feature_id).await,
+                Referrer::Prototypes,
+            )
+            .await?,
+        );
+    }
+    refuse_if_any(dependents)?;
+    if policy == DeletePolicy::Restrict {
+        // nothing references the feature, there is nothing to release
+        return Ok(());
+    }
+
+    let (name, price) = match container.feature.find_one_by_id(&feature_id.to_hex()).await {
+        Ok(Some(feature)) => (
+            feature.get_str("name").unwrap_or_default().to_string(),
+            price_of(&feature, "price").unwrap_or_default(),
+        ),
+        Ok(None) => return Ok(()),
+        Err(_mongodb_error) => return Err(ContentBuilderCustomResponseError::InternalError),
+    };
+    container
+        .template
+        .pull_feature_from_all(feature_id)
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    container
+        .project
+        .pull_feature_from_all(
+            feature_id,
+            price,
+            ProjectNotice {
+                kind: "feature_removed".to_string(),
+                id: feature_id.clone(),
+                name,
+                created_at: Utc::now().into(),
+            },
+        )
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    container
+        .prototype
+        .remove_feature_from_all(feature_id)
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    Ok(())
+}
+
+pub async fn release_template(
+    container: &CollectionsContainer,
+    template_id: &ObjectId,
+    policy: DeletePolicy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    release_templates(container, vec![template_id.clone()], policy).await
+}
+
+/// Not atomic: the templates of the category are released and deleted one by one, so a
+/// failure part way leaves some of them deleted and the category in place. Every step
+/// only touches what is still live, deleting the category again carries on from there.
+pub async fn release_category(
+    container: &CollectionsContainer,
+    category_id: &ObjectId,
+    policy: DeletePolicy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    let templates = documents(container.template.find_by_category_id(category_id).await).await?;
+    if blocks(Released::Category, Referrer::Templates, policy) {
+        return refuse_if_any(
+            templates
+                .iter()
+                .filter_map(|template| dependent(Referrer::Templates.collection(), template))
+                .collect(),
+        );
+    }
+
+    let template_ids: Vec<ObjectId> = templates
+        .iter()
+        .filter_map(|template| template.get_object_id("_id").ok().cloned())
+        .collect();
+    release_templates(container, template_ids.clone(), policy).await?;
+    for template_id in template_ids {
+        container
+            .template
+            .delete_one(&template_id.to_hex())
+            .await
+            .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    }
+    Ok(())
+}
+
+async fn release_templates(
+    container: &CollectionsContainer,
+    template_ids: Vec<ObjectId>,
+    policy: DeletePolicy,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    let mut dependents = Vec::new();
+    if blocks(Released::Template, Referrer::Projects, policy) {
+        dependents.extend(
+            dependents_of(
+                container
+                    .project
+                    .find_by_template_ids(template_ids.clone())
+                    .await,
+                Referrer::Projects,
+            )
+            .await?,
+        );
+    }
+    if blocks(Released::Template, Referrer::Prototypes, policy) {
+        dependents.extend(
+            dependents_of(
+                container
+                    .prototype
+                    .find_by_template_ids(template_ids.clone())
+                    .await,
+                Referrer::Prototypes,
+            )
+            .await?,
+        );
+    }
+    refuse_if_any(dependents)?;
+
+    container
+        .prototype
+        .delete_by_template_ids(template_ids)
+        .await
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Released {
+    Feature,
+    Template,
+    Category,
+}
+
+/// Collections holding references a delete has to deal with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Referrer {
+    Templates,
+    Projects,
+    Prototypes,
+}
+
+impl Referrer {
+    fn collection(self) -> &'static str {
+        match self {
+            Referrer::Templates => "templates",
+            Referrer::Projects => "projects",
+            Referrer::Prototypes => "prototypes",
+        }
+    }
+}
+
+/// Whether references from `referrer` keep `released` from being deleted, rather than
+/// being released along with it. Projects are never rewritten to drop their template,
+/// so they block templates and categories under both policies, see `DeletePolicy`.
+fn blocks(released: Released, referrer: Referrer, policy: DeletePolicy) -> bool {
+    match (released, referrer) {
+        (Released::Template, Referrer::Projects) | (Released::Category, Referrer::Projects) => {
+            true
+        }
+        (Released::Template, Referrer::Templates) => false,
+        _ => policy == DeletePolicy::Restrict,
+    }
+}
+
+fn refuse_if_any(dependents: Vec<Dependent>) -> Result<(), ContentBuilderCustomResponseError> {
+    if dependents.is_empty() {
+        Ok(())
+    } else {
+        Err(ContentBuilderCustomResponseError::Conflict(dependents))
+    }
+}
+
+pub async fn documents(
+    cursor: Result<Cursor, Error>,
+) -> Result<Vec<Document>, ContentBuilderCustomResponseError> {
+    let mut cursor =
+        cursor.map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+    let mut documents = Vec::new();
+    while let Some(document) = cursor.next().await {
+        documents.push(
+            document.map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?,
+        );
+    }
+    Ok(documents)
+}
+
+async fn dependents_of(
+    cursor: Result<Cursor, Error>,
+    referrer: Referrer,
+) -> Result<Vec<Dependent>, ContentBuilderCustomResponseError> {
+    Ok(documents(cursor)
+        .await?
+        .iter()
+        .filter_map(|document| dependent(referrer.collection(), document))
+        .collect())
+}
+
+pub fn dependent(collection: &str, document: &Document) -> Option<Dependent> {
+    Some(Dependent {
+        collection: collection.to_string(),
+        id: document.get_object_id("_id").ok()?.to_hex(),
+        name: document.get_str("name").ok().map(String::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    const REFERRERS: [Referrer; 3] = [
+        Referrer::Templates,
+        Referrer::Projects,
+        Referrer::Prototypes,
+    ];
+
+    #[test]
+    fn everything_blocks_under_restrict() {
+        for released in [Released::Feature, Released::Category] {
+            for referrer in REFERRERS {
+                assert!(blocks(released, referrer, DeletePolicy::Restrict));
+            }
+        }
+        assert!(blocks(
+            Released::Template,
+            Referrer::Projects,
+            DeletePolicy::Restrict
+        ));
+        assert!(blocks(
+            Released::Template,
+            Referrer::Prototypes,
+            DeletePolicy::Restrict
+        ));
+    }
+
+    #[test]
+    fn cascade_releases_features_entirely() {
+        for referrer in REFERRERS {
+            assert!(!blocks(Released::Feature, referrer, DeletePolicy::Cascade));
+        }
+    }
+
+    #[test]
+    fn cascade_takes_prototypes_and_templates_but_never_rewrites_projects() {
+        assert!(!blocks(
+            Released::Template,
+            Referrer::Prototypes,
+            DeletePolicy::Cascade
+        ));
+        assert!(!blocks(
+            Released::Category,
+            Referrer::Templates,
+            DeletePolicy::Cascade
+        ));
+        for released in [Released::Template, Released::Category] {
+            for policy in [DeletePolicy::Restrict, DeletePolicy::Cascade] {
+                assert!(blocks(released, Referrer::Projects, policy));
+            }
+        }
+    }
+
+    #[test]
+    fn dependents_are_named_after_their_collection() {
+        let id = ObjectId::new();
+        let named = dependent(
+            Referrer::Projects.collection(),
+            &doc! {"_id": id.clone(), "name": "Shop"},
+        )
+        .unwrap();
+        assert_eq!(named.collection, "projects");
+        assert_eq!(named.id, id.to_hex());
+        assert_eq!(named.name.as_deref(), Some("Shop"));
+        let unnamed = dependent(Referrer::Prototypes.collection(), &doc! {"_id": id}).unwrap();
+        assert_eq!(unnamed.name, None);
+        assert!(dependent("templates", &doc! {"_id": "not an object id"}).is_none());
+    }
+
+    #[test]
+    fn any_dependent_refuses_the_delete() {
+        assert!(refuse_if_any(Vec::new()).is_ok());
+        let dependent = dependent("templates", &doc! {"_id": ObjectId::new()}).unwrap();
+        match refuse_if_any(vec![dependent.clone()]) {
+            Err(ContentBuilderCustomResponseError::Conflict(dependents)) => {
+                assert_eq!(dependents.len(), 1);
+                assert_eq!(dependents[0].id, dependent.id);
+            }
+            _ => panic!("expected a conflict"),
+        }
+    }
+}
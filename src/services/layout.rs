@@ -0,0 +1,349 @@
+use crate::controllers::schema::{NodeLayout, Position, ProtoTypeObject, Size};
+use std::collections::HashMap;
+
+pub const NODE_WIDTH: f64 = 240.0;
+pub const NODE_HEIGHT: f64 = 480.0;
+const LAYER_GAP: f64 = 160.0;
+const NODE_GAP: f64 = 80.0;
+const ORDERING_SWEEPS: usize = 8;
+
+/// Layered (Sugiyama style) layout of a prototype graph, flowing left to right.
+///
+/// Returns one layout per node, in the same order as `nodes`. Nodes that already
+/// have a layout keep it unless `overwrite` is set; newly placed nodes are then
+/// moved below the existing ones so they never overlap.
+pub fn layered_layout(nodes: &[ProtoTypeObject], overwrite: bool) -> Vec<NodeLayout> {
+    let index: HashMap<String, usize> = nodes
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(position, node)| (node.feature_id.to_string(), position))
+        .collect();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    for (from, node) in nodes.iter().enumerate() {
+        for connection in &node.connections {
+            if let Some(&to) = index.get(&connection.to.to_string()) {
+                if to != from && !edges.contains(&(from, to)) {
+                    edges.push((from, to));
+                }
+            }
+        }
+    }
+
+    let edges = remove_cycles(nodes.len(), edges);
+    let layers = assign_layers(nodes.len(), &edges);
+    let (ranks, order) = order_layers(&edges, &layers);
+
+    let tallest = ranks.iter().map(|rank| rank.len()).max().unwrap_or(0) as f64;
+    let mut computed: Vec<Position> = vec![Position { x: 0.0, y: 0.0 }; nodes.len()];
+    for (layer, rank) in ranks.iter().enumerate() {
+        let offset = (tallest - rank.len() as f64) * (NODE_HEIGHT + NODE_GAP) / 2.0;
+        for &vertex in rank.iter().filter(|&&vertex| vertex < nodes.len()) {
+            computed[vertex] = Position {
+                x: layer as f64 * (NODE_WIDTH + LAYER_GAP),
+                y: offset + order[vertex] as f64 * (NODE_HEIGHT + NODE_GAP),
+            };
+        }
+    }
+
+    let placed_bottom = nodes
+        .iter()
+        .filter_map(|node| node.layout.as_ref())
+        .map(|layout| {
+            layout.position.y + layout.size.map(|size| size.height).unwrap_or(NODE_HEIGHT)
+        })
+        .fold(None, |bottom: Option<f64>, y| Some(bottom.map_or(y, |b| b.max(y))));
+    let shift = match placed_bottom {
+        Some(bottom) if !overwrite => bottom + NODE_GAP,
+        _ => 0.0,
+    };
+
+    nodes
+        .iter()
+        .zip(computed)
+        .map(|(node, position)| match (&node.layout, overwrite) {
+            (Some(layout), false) => layout.clone(),
+            (existing, _) => NodeLayout {
+                position: Position {
+                    x: position.x,
+                    y: position.y + shift,
+                },
+                size: existing
+                    .as_ref()
+                    .and_then(|layout| layout.size)
+                    .or(Some(Size {
+                        width: NODE_WIDTH,
+                        height: NODE_HEIGHT,
+                    })),
+                lane: existing.as_ref().and_then(|layout| layout.lane.clone()),
+            },
+        })
+        .collect()
+}
+
+/// Breaks cycles by reversing every edge that closes a cycle during a depth first walk.
+fn remove_cycles(count: usize, edges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for &(from, to) in &edges {
+        adjacency[from].push(to);
+    }
+    // 0 = unvisited, 1 = on the current path, 2 = done
+    let mut state = vec![0u8; count];
+    let mut back_edges: Vec<(usize, usize)> = Vec::new();
+    for root in 0..count {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+        state[root] = 1;
+        while let Some((vertex, next)) = stack.pop() {
+            if next < adjacency[vertex].len() {
+                stack.push((vertex, next + 1));
+                let target = adjacency[vertex][next];
+                match state[target] {
+                    0 => {
+                        state[target] = 1;
+                        stack.push((target, 0));
+                    }
+                    1 => back_edges.push((vertex, target)),
+                    _ => {}
+                }
+            } else {
+                state[vertex] = 2;
+            }
+        }
+    }
+    let mut acyclic: Vec<(usize, usize)> = Vec::new();
+    for edge in edges {
+        let edge = if back_edges.contains(&edge) {
+            (edge.1, edge.0)
+        } else {
+            edge
+        };
+        if !acyclic.contains(&edge) {
+            acyclic.push(edge);
+        }
+    }
+    acyclic
+}
+
+/// Longest path layering: every node sits one layer after its deepest predecessor.
+fn assign_layers(count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut incoming = vec![0usize; count];
+    for &(_, to) in edges {
+        incoming[to] += 1;
+    }
+    let mut layers = vec![0usize; count];
+    let mut queue: Vec<usize> = (0..count).filter(|&vertex| incoming[vertex] == 0).collect();
+    while let Some(vertex) = queue.pop() {
+        for &(from, to) in edges.iter().filter(|&&(from, _)| from == vertex) {
+            layers[to] = layers[to].max(layers[from] + 1);
+            incoming[to] -= 1;
+            if incoming[to] == 0 {
+                queue.push(to);
+            }
+        }
+    }
+    layers
+}
+
+/// Splits long edges with dummy vertices and orders every layer with the barycenter
+/// heuristic. Returns the layers (dummies come after the real vertices) and each vertex's rank.
+fn order_layers(edges: &[(usize, usize)], layers: &[usize]) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let mut layer_of: Vec<usize> = layers.to_vec();
+    let mut segments: Vec<(usize, usize)> = Vec::new();
+    for &(from, to) in edges {
+        let mut previous = from;
+        for layer in layer_of[from] + 1..layer_of[to] {
+            let dummy = layer_of.len();
+            layer_of.push(layer);
+            segments.push((previous, dummy));
+            previous = dummy;
+        }
+        segments.push((previous, to));
+    }
+
+    let depth = layer_of.iter().max().map_or(0, |&max| max + 1);
+    let mut ranks: Vec<Vec<usize>> = vec![Vec::new(); depth];
+    for (vertex, &layer) in layer_of.iter().enumerate() {
+        ranks[layer].push(vertex);
+    }
+    let mut order = vec![0usize; layer_of.len()];
+    let refresh = |ranks: &Vec<Vec<usize>>, order: &mut Vec<usize>| {
+        for rank in ranks {
+            for (position, &vertex) in rank.iter().enumerate() {
+                order[vertex] = position;
+            }
+        }
+    };
+    refresh(&ranks, &mut order);
+
+    for sweep in 0..ORDERING_SWEEPS {
+        let downward = sweep % 2 == 0;
+        let layer_indices: Vec<usize> = if downward {
+            (1..depth).collect()
+        } else {
+            (0..depth.saturating_sub(1)).rev().collect()
+        };
+        for layer in layer_indices {
+            let mut weighted: Vec<(f64, usize)> = ranks[layer]
+                .iter()
+                .map(|&vertex| {
+                    let neighbours: Vec<usize> = segments
+                        .iter()
+                        .filter_map(|&(from, to)| {
+                            if downward && to == vertex {
+                                Some(from)
+                            } else if !downward && from == vertex {
+                                Some(to)
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+                    let barycenter = if neighbours.is_empty() {
+                        order[vertex] as f64
+                    } else {
+                        neighbours.iter().map(|&n| order[n] as f64).sum::<f64>()
+                            / neighbours.len() as f64
+                    };
+                    (barycenter, vertex)
+                })
+                .collect();
+            weighted.sort_by(|a, b| {
+                a.0.partial_cmp(&b.0)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(order[a.1].cmp(&order[b.1]))
+            });
+            ranks[layer] = weighted.into_iter().map(|(_, vertex)| vertex).collect();
+            refresh(&ranks, &mut order);
+        }
+    }
+
+    (ranks, order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::schema::{Connections, Relations};
+    use bson::oid::ObjectId;
+
+    fn nodes(count: usize) -> Vec<ProtoTypeObject> {
+        (0..count)
+            .map(|_| ProtoTypeObject {
+                feature_id: ObjectId::new(),
+                connections: vec![],
+                layout: None,
+                hotspots: vec![],
+            })
+            .collect()
+    }
+
+    fn connect(nodes: &mut [ProtoTypeObject], from: usize, to: usize) {
+        let to = nodes[to].feature_id.clone();
+        nodes[from].connections.push(Connections {
+            to,
+            releations: Relations {
+                back: false,
+                forword: true,
+            },
+        });
+    }
+
+    fn layer(layout: &NodeLayout) -> usize {
+        (layout.position.x / (NODE_WIDTH + LAYER_GAP)).round() as usize
+    }
+
+    #[test]
+    fn chain_flows_left_to_right() {
+        let mut graph = nodes(3);
+        connect(&mut graph, 0, 1);
+        connect(&mut graph, 1, 2);
+        let layouts = layered_layout(&graph, false);
+        assert_eq!(
+            layouts.iter().map(layer).collect::<Vec<usize>>(),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn node_sits_after_its_deepest_predecessor() {
+        let mut graph = nodes(3);
+        connect(&mut graph, 0, 1);
+        connect(&mut graph, 1, 2);
+        connect(&mut graph, 0, 2);
+        let layouts = layered_layout(&graph, false);
+        assert_eq!(layer(&layouts[2]), 2);
+    }
+
+    #[test]
+    fn cycles_are_laid_out() {
+        let mut graph = nodes(3);
+        connect(&mut graph, 0, 1);
+        connect(&mut graph, 1, 2);
+        connect(&mut graph, 2, 0);
+        let layouts = layered_layout(&graph, false);
+        assert_eq!(layouts.len(), 3);
+        let mut layers: Vec<usize> = layouts.iter().map(layer).collect();
+        layers.sort_unstable();
+        assert_eq!(layers, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn siblings_do_not_overlap() {
+        let mut graph = nodes(4);
+        connect(&mut graph, 0, 1);
+        connect(&mut graph, 0, 2);
+        connect(&mut graph, 0, 3);
+        let layouts = layered_layout(&graph, false);
+        let mut rows: Vec<f64> = layouts[1..]
+            .iter()
+            .map(|layout| layout.position.y)
+            .collect();
+        rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for pair in rows.windows(2) {
+            assert!(pair[1] - pair[0] >= NODE_HEIGHT + NODE_GAP);
+        }
+    }
+
+    #[test]
+    fn placed_nodes_are_kept_and_new_ones_go_below() {
+        let mut graph = nodes(2);
+        connect(&mut graph, 0, 1);
+        graph[0].layout = Some(NodeLayout {
+            position: Position { x: 40.0, y: 100.0 },
+            size: Some(Size {
+                width: 200.0,
+                height: 300.0,
+            }),
+            lane: Some("onboarding".to_string()),
+        });
+        let layouts = layered_layout(&graph, false);
+        assert_eq!(layouts[0].position, Position { x: 40.0, y: 100.0 });
+        assert!(layouts[1].position.y >= 100.0 + 300.0 + NODE_GAP);
+    }
+
+    #[test]
+    fn overwrite_replaces_positions_but_keeps_size_and_lane() {
+        let mut graph = nodes(1);
+        graph[0].layout = Some(NodeLayout {
+            position: Position { x: 40.0, y: 100.0 },
+            size: Some(Size {
+                width: 200.0,
+                height: 300.0,
+            }),
+            lane: Some("onboarding".to_string()),
+        });
+        let layouts = layered_layout(&graph, true);
+        assert_eq!(layouts[0].position, Position { x: 0.0, y: 0.0 });
+        assert_eq!(
+            layouts[0].size,
+            Some(Size {
+                width: 200.0,
+                height: 300.0
+            })
+        );
+        assert_eq!(layouts[0].lane.as_deref(), Some("onboarding"));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::services::integrity::documents;
+use crate::CollectionsContainer;
+use bson::{Bson, Document};
+use mongodb::event::command::{
+    CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramTimer, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+/// Past this many cursors left open, newer ones go unrecorded.
+const MAX_OPEN_CURSORS: usize = 10_000;
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Requests served, by route pattern and status code.",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time to build the response, by route pattern.",
+        &["method", "route"]
+    )
+    .unwrap()
+});
+
+static MONGODB_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "mongodb_operation_duration_seconds",
+        "Time spent in each collection method, e.g. ProjectsCollection refactor_one_by_id.",
+        &["collection", "operation"]
+    )
+    .unwrap()
+});
+
+static MONGODB_CURSOR_DOCUMENTS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "mongodb_cursor_documents",
+        "Documents read through each find and aggregate cursor, by collection.",
+        &["collection", "command"],
+        vec![0.0, 1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 5000.0]
+    )
+    .unwrap()
+});
+
+static MONGODB_COMMAND_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "mongodb_command_failures_total",
+        "Database commands that failed, by command name.",
+        &["command"]
+    )
+    .unwrap()
+});
+
+static PROJECTS_BY_STATE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "builder_projects",
+        "Live projects, by state. Refreshed on every scrape.",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Records the time until it is dropped, so it covers every return path of the
+/// collection method that holds it.
+pub fn operation_timer(collection: &str, operation: &str) -> HistogramTimer {
+    MONGODB_OPERATION_DURATION
+        .with_label_values(&[collection, operation])
+        .start_timer()
+}
+
+/// Business gauges are read from the database on scrape rather than kept in step with
+/// every write.
+pub async fn refresh_business_gauges(container: &CollectionsContainer) {
+    match documents(container.project.count_by_state().await).await {
+        Ok(states) => {
+            PROJECTS_BY_STATE.reset();
+            for state in states {
+                PROJECTS_BY_STATE
+                    .with_label_values(&[state.get_str("_id").unwrap_or("none")])
+                    .set(count_of(&state));
+            }
+        }
+        Err(error) => tracing::warn!("cannot count projects by state: {}", error),
+    }
+}
+
+fn count_of(document: &Document) -> i64 {
+    match document.get("count") {
+        Some(Bson::Int32(count)) => *count as i64,
+        Some(Bson::Int64(count)) => *count,
+        _ => 0,
+    }
+}
+
+/// Every registered metric in the Prometheus text format.
+pub fn render() -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+enum PendingCommand {
+    Open { collection: String, command: String },
+    More { cursor_id: i64 },
+}
+
+struct OpenCursor {
+    collection: String,
+    command: String,
+    documents: usize,
+}
+
+#[derive(Default)]
+struct CursorTracker {
+    pending: HashMap<i32, PendingCommand>,
+    open: HashMap<i64, OpenCursor>,
+}
+
+impl CursorTracker {
+    fn started(&mut self, request_id: i32, command_name: &str, command: &Document) {
+        let pending = match command_name {
+            "find" | "aggregate" => match command.get_str(command_name) {
+                Ok(collection) => PendingCommand::Open {
+                    collection: collection.to_string(),
+                    command: command_name.to_string(),
+                },
+                // database level aggregations have no collection
+                Err(_bson_error) => return,
+            },
+            "getMore" => match command.get_i64("getMore") {
+                Ok(cursor_id) => PendingCommand::More { cursor_id },
+                Err(_bson_error) => return,
+            },
+            "killCursors" => {
+                if let Ok(cursor_ids) = command.get_array("cursors") {
+                    for cursor_id in cursor_ids {
+                        if let Bson::Int64(cursor_id) = cursor_id {
+                            self.close(*cursor_id);
+                        }
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        self.pending.insert(request_id, pending);
+    }
+
+    fn succeeded(&mut self, request_id: i32, reply: &Document) {
+        let pending = match self.pending.remove(&request_id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let cursor = match reply.get_document("cursor") {
+            Ok(cursor) => cursor,
+            Err(_bson_error) => return,
+        };
+        let batch = cursor
+            .get_array("firstBatch")
+            .or_else(|_bson_error| cursor.get_array("nextBatch"))
+            .map(Vec::len)
+            .unwrap_or(0);
+        let next_cursor_id = cursor.get_i64("id").unwrap_or(0);
+        let mut open = match pending {
+            PendingCommand::Open {
+                collection,
+                command,
+            } => OpenCursor {
+                collection,
+                command,
+                documents: 0,
+            },
+            PendingCommand::More { cursor_id } => match self.open.remove(&cursor_id) {
+                Some(open) => open,
+                None => return,
+            },
+        };
+        open.documents += batch;
+        if next_cursor_id == 0 {
+            observe_cursor(&open);
+        } else if self.open.len() < MAX_OPEN_CURSORS {
+            self.open.insert(next_cursor_id, open);
+        }
+    }
+
+    fn failed(&mut self, request_id: i32) {
+        if let Some(PendingCommand::More { cursor_id }) = self.pending.remove(&request_id) {
+            self.close(cursor_id);
+        }
+    }
+
+    fn close(&mut self, cursor_id: i64) {
+        if let Some(cursor) = self.open.remove(&cursor_id) {
+            observe_cursor(&cursor);
+        }
+    }
+}
+
+fn observe_cursor(cursor: &OpenCursor) {
+    MONGODB_CURSOR_DOCUMENTS
+        .with_label_values(&[&cursor.collection, &cursor.command])
+        .observe(cursor.documents as f64);
+}
+
+/// Follows cursors through the driver's command monitoring: a cursor is counted from its
+/// first batch through every `getMore`, and recorded once exhausted or killed.
+#[derive(Default)]
+pub struct CommandMetrics {
+    tracker: Mutex<CursorTracker>,
+}
+
+impl CommandEventHandler for CommandMetrics {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        if let Ok(mut tracker) = self.tracker.lock() {
+            tracker.started(event.request_id, &event.command_name, &event.command);
+        }
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        if let Ok(mut tracker) = self.tracker.lock() {
+            tracker.succeeded(event.request_id, &event.reply);
+        }
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        MONGODB_COMMAND_FAILURES
+            .with_label_values(&[&event.command_name])
+            .inc();
+        if let Ok(mut tracker) = self.tracker.lock() {
+            tracker.failed(event.request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+
+    /// Metrics are process wide, each test counts under a collection of its own.
+    fn cursor_count(collection: &str) -> u64 {
+        MONGODB_CURSOR_DOCUMENTS
+            .with_label_values(&[collection, "find"])
+            .get_sample_count()
+    }
+
+    fn cursor_documents(collection: &str) -> f64 {
+        MONGODB_CURSOR_DOCUMENTS
+            .with_label_values(&[collection, "find"])
+            .get_sample_sum()
+    }
+
+    #[test]
+    fn operations_are_timed_until_the_timer_drops() {
+        let operation = MONGODB_OPERATION_DURATION
+            .with_label_values(&["MetricsTestCollection", "find_one_by_id"]);
+        let before = operation.get_sample_count();
+        drop(operation_timer("MetricsTestCollection", "find_one_by_id"));
+        assert_eq!(operation.get_sample_count(), before + 1);
+        assert!(render()
+            .unwrap()
+            .contains("collection=\"MetricsTestCollection\",operation=\"find_one_by_id\""));
+    }
+
+    #[test]
+    fn a_single_batch_cursor_is_recorded_once() {
+        let mut tracker = CursorTracker::default();
+        tracker.started(1, "find", &doc! {"find": "metrics_single"});
+        tracker.succeeded(
+            1,
+            &doc! {"cursor": {"id": 0_i64, "firstBatch": [{"_id": 1}, {"_id": 2}]}},
+        );
+        assert_eq!(cursor_count("metrics_single"), 1);
+        assert_eq!(cursor_documents("metrics_single"), 2.0);
+        assert!(tracker.pending.is_empty() && tracker.open.is_empty());
+    }
+
+    #[test]
+    fn get_more_batches_add_up_until_the_cursor_is_exhausted() {
+        let mut tracker = CursorTracker::default();
+        tracker.started(1, "find", &doc! {"find": "metrics_batches"});
+        tracker.succeeded(1, &doc! {"cursor": {"id": 42_i64, "firstBatch": [{}, {}]}});
+        tracker.started(2, "getMore", &doc! {"getMore": 42_i64});
+        tracker.succeeded(2, &doc! {"cursor": {"id": 42_i64, "nextBatch": [{}, {}]}});
+        assert_eq!(cursor_count("metrics_batches"), 0);
+        tracker.started(3, "getMore", &doc! {"getMore": 42_i64});
+        tracker.succeeded(3, &doc! {"cursor": {"id": 0_i64, "nextBatch": [{}]}});
+        assert_eq!(cursor_count("metrics_batches"), 1);
+        assert_eq!(cursor_documents("metrics_batches"), 5.0);
+    }
+
+    #[test]
+    fn killed_and_failed_cursors_are_recorded_with_what_they_read() {
+        let mut tracker = CursorTracker::default();
+        tracker.started(1, "find", &doc! {"find": "metrics_killed"});
+        tracker.succeeded(1, &doc! {"cursor": {"id": 7_i64, "firstBatch": [{}]}});
+        tracker.started(
+            2,
+            "killCursors",
+            &doc! {"killCursors": "metrics_killed", "cursors": [7_i64]},
+        );
+        assert_eq!(cursor_count("metrics_killed"), 1);
+
+        tracker.started(3, "find", &doc! {"find": "metrics_failed"});
+        tracker.succeeded(3, &doc! {"cursor": {"id": 8_i64, "firstBatch": [{}, {}]}});
+        tracker.started(4, "getMore", &doc! {"getMore": 8_i64});
+        tracker.failed(4);
+        assert_eq!(cursor_count("metrics_failed"), 1);
+        assert_eq!(cursor_documents("metrics_failed"), 2.0);
+        assert!(tracker.open.is_empty());
+    }
+
+    #[test]
+    fn other_commands_are_not_tracked() {
+        let mut tracker = CursorTracker::default();
+        tracker.started(1, "insert", &doc! {"insert": "metrics_other"});
+        tracker.started(2, "aggregate", &doc! {"aggregate": 1});
+        assert!(tracker.pending.is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::controllers::schema::MigrationRecord;
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use crate::services::integrity::documents;
+use crate::CollectionsContainer;
+use bson::{doc, oid::ObjectId, Document};
+use chrono::Utc;
+use mongodb::{error::Error, results::UpdateResult, Cursor};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Collections migrations can rewrite, not all of them have one yet.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Categories,
+    Features,
+    Templates,
+    Projects,
+    Prototypes,
+}
+
+/// One versioned rewrite of the documents of a collection.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub target: Target,
+    /// Documents the step looks at, trashed ones included.
+    pub filter: fn() -> Document,
+    /// Rewrites one document in place. An error leaves the document untouched and is
+    /// reported against its id. Must be idempotent, a failed run is retried in full.
+    pub up: fn(&mut Document) -> Result<(), String>,
+}
+
+/// Applied in version order. Append only: a shipped migration is never edited or
+/// renumbered, a correction is a new migration.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    name: "drop_template_prototype_id",
+    target: Target::Templates,
+    filter: with_template_prototype_id,
+    up: drop_template_prototype_id,
+}];
+
+/// Templates used to point at their prototype, prototypes now point at their template.
+fn with_template_prototype_id() -> Document {
+    doc! {"prototype_id": {"$exists": true}}
+}
+
+fn drop_template_prototype_id(template: &mut Document) -> Result<(), String> {
+    template.remove("prototype_id");
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentError {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStepReport {
+    pub version: i32,
+    pub name: &'static str,
+    pub matched_documents: usize,
+    pub migrated_documents: usize,
+    pub errors: Vec<DocumentError>,
+    pub applied: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    pub already_applied: Vec<i32>,
+    pub steps: Vec<MigrationStepReport>,
+}
+
+impl MigrationReport {
+    pub fn failed(&self) -> bool {
+        self.steps.iter().any(|step| !step.errors.is_empty())
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for step in self.steps.iter() {
+            writeln!(
+                formatter,
+                "{:>4} {}: {} matched, {} {}{}",
+                step.version,
+                step.name,
+                step.matched_documents,
+                step.migrated_documents,
+                if self.dry_run { "would change" } else { "changed" },
+                if step.applied { ", recorded" } else { "" }
+            )?;
+            for error in step.errors.iter() {
+                writeln!(formatter, "       {}: {}", error.id, error.error)?;
+            }
+        }
+        write!(
+            formatter,
+            "{} pending migrations, {} already applied{}",
+            self.steps.len(),
+            self.already_applied.len(),
+            if self.dry_run { ", dry run" } else { "" }
+        )
+    }
+}
+
+/// Splits the migrations into the versions already recorded and the ones still to
+/// run, the latter in version order whatever order they were declared in.
+fn plan<'a>(
+    migrations: &'a [Migration],
+    applied: &HashSet<i32>,
+) -> (Vec<i32>, Vec<&'a Migration>) {
+    let (done, mut pending): (Vec<&Migration>, Vec<&Migration>) = migrations
+        .iter()
+        .partition(|migration| applied.contains(&migration.version));
+    pending.sort_by_key(|migration| migration.version);
+    let mut already_applied: Vec<i32> = done.iter().map(|migration| migration.version).collect();
+    already_applied.sort_unstable();
+    (already_applied, pending)
+}
+
+/// The rewritten document, or `None` when the step leaves it as it is.
+fn apply(migration: &Migration, document: &Document) -> Result<Option<Document>, String> {
+    let mut migrated = document.clone();
+    (migration.up)(&mut migrated)?;
+    Ok(if &migrated == document {
+        None
+    } else {
+        Some(migrated)
+    })
+}
+
+/// Runs every migration not yet recorded. A step with document errors is not recorded
+/// and stops the run, later steps may rely on its output. In dry run mode nothing is
+/// written and every pending step is reported, each against the unmigrated data.
+/// Instances must not migrate concurrently, recording is keyed by version but the
+/// rewrites themselves are not locked.
+pub async fn migrate(
+    container: &CollectionsContainer,
+    dry_run: bool,
+) -> Result<MigrationReport, ContentBuilderCustomResponseError> {
+    let applied: HashSet<i32> = documents(container.migration.find_all().await)
+        .await?
+        .iter()
+        .filter_map(|record| record.get_i32("_id").ok())
+        .collect();
+    let (already_applied, pending) = plan(MIGRATIONS, &applied);
+    let mut report = MigrationReport {
+        dry_run,
+        already_applied,
+        steps: Vec::new(),
+    };
+    for migration in pending {
+        let mut step = run(container, migration, dry_run).await?;
+        let failed = !step.errors.is_empty();
+        if !failed && !dry_run {
+            container
+                .migration
+                .insert_one(MigrationRecord {
+                    _id: migration.version,
+                    name: migration.name.to_string(),
+                    applied_at: Utc::now().into(),
+                    migrated_documents: step.migrated_documents as i64,
+                })
+                .await
+                .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?;
+            step.applied = true;
+        }
+        report.steps.push(step);
+        if failed && !dry_run {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+async fn run(
+    container: &CollectionsContainer,
+    migration: &Migration,
+    dry_run: bool,
+) -> Result<MigrationStepReport, ContentBuilderCustomResponseError> {
+    // loaded up front so rewritten documents are never seen twice by the same cursor
+    let matched =
+        documents(find(container, migration.target, (migration.filter)()).await).await?;
+    let mut step = MigrationStepReport {
+        version: migration.version,
+        name: migration.name,
+        matched_documents: matched.len(),
+        migrated_documents: 0,
+        errors: Vec::new(),
+        applied: false,
+    };
+    for document in matched {
+        let id = match document.get_object_id("_id") {
+            Ok(id) => id.clone(),
+            Err(_) => {
+                step.errors.push(DocumentError {
+                    id: format!("{:?}", document.get("_id")),
+                    error: "_id is not an ObjectId".to_string(),
+                });
+                continue;
+            }
+        };
+        let migrated = match apply(migration, &document) {
+            Ok(Some(migrated)) => migrated,
+            Ok(None) => continue,
+            Err(error) => {
+                step.errors.push(DocumentError {
+                    id: id.to_hex(),
+                    error,
+                });
+                continue;
+            }
+        };
+        if !dry_run {
+            if let Err(mongodb_error) = replace(container, migration.target, &id, migrated).await
+            {
+                step.errors.push(DocumentError {
+                    id: id.to_hex(),
+                    error: mongodb_error.to_string(),
+                });
+                continue;
+            }
+        }
+        step.migrated_documents += 1;
+    }
+    Ok(step)
+}
+
+async fn find(
+    container: &CollectionsContainer,
+    target: Target,
+    filter: Document,
+) -> Result<Cursor, Error> {
+    match target {
+        Target::Categories => container.category.find_documents(filter).await,
+        Target::Features => container.feature.find_documents(filter).await,
+        Target::Templates => container.template.find_documents(filter).await,
+        Target::Projects => container.project.find_documents(filter).await,
+        Target::Prototypes => container.prototype.find_documents(filter).await,
+    }
+}
+
+async fn replace(
+    container: &CollectionsContainer,
+    target: Target,
+    id: &ObjectId,
+    document: Document,
+) -> Result<UpdateResult, Error> {
+    match target {
+        Target::Categories => container.category.replace_document(id, document).await,
+        Target::Features => container.feature.replace_document(id, document).await,
+        Target::Templates => container.template.replace_document(id, document).await,
+        Target::Projects => container.project.replace_document(id, document).await,
+        Target::Prototypes => container.prototype.replace_document(id, document).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_document: &mut Document) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn failing(_document: &mut Document) -> Result<(), String> {
+        Err("cannot migrate".to_string())
+    }
+
+    fn migration(version: i32, up: fn(&mut Document) -> Result<(), String>) -> Migration {
+        Migration {
+            version,
+            name: "test",
+            target: Target::Templates,
+            filter: Document::new,
+            up,
+        }
+    }
+
+    #[test]
+    fn shipped_versions_are_unique_and_ascending() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|pair| pair[0].version < pair[1].version));
+        assert!(MIGRATIONS.iter().all(|migration| migration.version > 0));
+    }
+
+    #[test]
+    fn pending_migrations_run_in_version_order() {
+        let migrations = [migration(3, noop), migration(1, noop), migration(2, noop)];
+        let (already_applied, pending) = plan(&migrations, &HashSet::new());
+        assert!(already_applied.is_empty());
+        assert_eq!(
+            pending
+                .iter()
+                .map(|migration| migration.version)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn recorded_migrations_are_skipped() {
+        let migrations = [migration(3, noop), migration(1, noop), migration(2, noop)];
+        let (already_applied, pending) = plan(&migrations, &[3, 1].iter().cloned().collect());
+        assert_eq!(already_applied, vec![1, 3]);
+        assert_eq!(
+            pending
+                .iter()
+                .map(|migration| migration.version)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        let (_, pending) = plan(&migrations, &[1, 2, 3].iter().cloned().collect());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn shipped_migrations_are_idempotent() {
+        let template = doc! {
+            "_id": ObjectId::new(),
+            "name": "Shop",
+            "prototype_id": ObjectId::new(),
+        };
+        for migration in MIGRATIONS {
+            let once = apply(migration, &template)
+                .unwrap()
+                .unwrap_or_else(|| template.clone());
+            assert_eq!(apply(migration, &once), Ok(None), "{}", migration.name);
+        }
+    }
+
+    #[test]
+    fn template_prototype_ids_are_dropped() {
+        let id = ObjectId::new();
+        let template = doc! {"_id": id.clone(), "name": "Shop", "prototype_id": ObjectId::new()};
+        assert_eq!(
+            apply(&MIGRATIONS[0], &template),
+            Ok(Some(doc! {"_id": id, "name": "Shop"}))
+        );
+        assert!(with_template_prototype_id().contains_key("prototype_id"));
+    }
+
+    #[test]
+    fn unchanged_and_failing_documents_are_not_rewritten() {
+        let template = doc! {"_id": ObjectId::new(), "name": "Shop"};
+        assert_eq!(apply(&migration(1, noop), &template), Ok(None));
+        assert_eq!(
+            apply(&migration(1, failing), &template),
+            Err("cannot migrate".to_string())
+        );
+    }
+}
@@ -0,0 +1,20 @@
+pub mod api_keys;
+pub mod assets;
+pub mod authorization;
+pub mod consistency;
+pub mod directory;
+pub mod export;
+pub mod gc;
+pub mod health;
+pub mod hotspots;
+pub mod images;
+pub mod import;
+pub mod indexes;
+pub mod integrity;
+pub mod layout;
+pub mod metrics;
+pub mod migrations;
+pub mod revisions;
+pub mod storage;
+pub mod trash;
+pub mod validation;
@@ -0,0 +1,165 @@
+use crate::controllers::schema::{
+    ConnectionDiff, ProtoType, ProtoTypeDiffResponseModel, ProtoTypeRevisionDeserializeModel,
+};
+
+/// Nodes are compared by feature id and connections by their (from, to) pair, so
+/// moving a node or toggling a relation flag is not reported as a change.
+pub fn diff_revisions(
+    from: &ProtoTypeRevisionDeserializeModel,
+    to: &ProtoTypeRevisionDeserializeModel,
+) -> ProtoTypeDiffResponseModel {
+    let from_nodes = node_ids(&from.snapshot);
+    let to_nodes = node_ids(&to.snapshot);
+    let from_connections = connections(&from.snapshot);
+    let to_connections = connections(&to.snapshot);
+    ProtoTypeDiffResponseModel {
+        template: to.template_id.to_string(),
+        from: from.revision,
+        to: to.revision,
+        added_nodes: difference(&to_nodes, &from_nodes),
+        removed_nodes: difference(&from_nodes, &to_nodes),
+        added_connections: difference(&to_connections, &from_connections),
+        removed_connections: difference(&from_connections, &to_connections),
+    }
+}
+
+fn node_ids(prototype: &ProtoType) -> Vec<String> {
+    prototype
+        .prototype
+        .iter()
+        .map(|node| node.feature_id.to_string())
+        .collect()
+}
+
+fn connections(prototype: &ProtoType) -> Vec<ConnectionDiff> {
+    prototype
+        .prototype
+        .iter()
+        .flat_map(|node| {
+            node.connections.iter().map(move |connection| ConnectionDiff {
+                from: node.feature_id.to_string(),
+                to: connection.to.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn difference<T: PartialEq + Clone>(left: &[T], right: &[T]) -> Vec<T> {
+    let mut result: Vec<T> = Vec::new();
+    for item in left {
+        if !right.contains(item) && !result.contains(item) {
+            result.push(item.clone());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::schema::{
+        Connections, NodeLayout, Position, ProtoTypeObject, Relations,
+    };
+    use bson::oid::ObjectId;
+    use chrono::Utc;
+
+    fn node(feature_id: &ObjectId, to: &[&ObjectId]) -> ProtoTypeObject {
+        ProtoTypeObject {
+            feature_id: feature_id.clone(),
+            connections: to
+                .iter()
+                .map(|to| Connections {
+                    to: (*to).clone(),
+                    releations: Relations {
+                        back: false,
+                        forword: true,
+                    },
+                })
+                .collect(),
+            layout: None,
+            hotspots: vec![],
+        }
+    }
+
+    fn revision(
+        template_id: &ObjectId,
+        revision: i64,
+        nodes: Vec<ProtoTypeObject>,
+    ) -> ProtoTypeRevisionDeserializeModel {
+        ProtoTypeRevisionDeserializeModel {
+            _id: ObjectId::new(),
+            template_id: template_id.clone(),
+            revision,
+            created_at: Utc::now().into(),
+            message: None,
+            snapshot: ProtoType {
+                template_id: template_id.clone(),
+                prototype: nodes,
+                viewport: None,
+                lanes: None,
+            },
+        }
+    }
+
+    #[test]
+    fn reports_added_and_removed_nodes_and_connections() {
+        let template_id = ObjectId::new();
+        let (login, home, settings) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let from = revision(
+            &template_id,
+            1,
+            vec![node(&login, &[&home]), node(&home, &[])],
+        );
+        let to = revision(
+            &template_id,
+            2,
+            vec![node(&home, &[&settings]), node(&settings, &[])],
+        );
+        let diff = diff_revisions(&from, &to);
+        assert_eq!((diff.from, diff.to), (1, 2));
+        assert_eq!(diff.added_nodes, vec![settings.to_string()]);
+        assert_eq!(diff.removed_nodes, vec![login.to_string()]);
+        assert_eq!(
+            diff.added_connections,
+            vec![ConnectionDiff {
+                from: home.to_string(),
+                to: settings.to_string(),
+            }]
+        );
+        assert_eq!(
+            diff.removed_connections,
+            vec![ConnectionDiff {
+                from: login.to_string(),
+                to: home.to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn moves_and_relation_flags_are_not_changes() {
+        let template_id = ObjectId::new();
+        let (login, home) = (ObjectId::new(), ObjectId::new());
+        let from = revision(
+            &template_id,
+            1,
+            vec![node(&login, &[&home]), node(&home, &[])],
+        );
+        let mut moved = vec![node(&home, &[]), node(&login, &[&home])];
+        moved[1].layout = Some(NodeLayout {
+            position: Position { x: 400.0, y: 0.0 },
+            size: None,
+            lane: None,
+        });
+        moved[1].connections[0].releations.back = true;
+        let diff = diff_revisions(&from, &revision(&template_id, 2, moved));
+        assert!(diff.added_nodes.is_empty());
+        assert!(diff.removed_nodes.is_empty());
+        assert!(diff.added_connections.is_empty());
+        assert!(diff.removed_connections.is_empty());
+    }
+
+    #[test]
+    fn duplicates_are_reported_once() {
+        assert_eq!(difference(&[1, 1, 2], &[2]), vec![1]);
+    }
+}
@@ -0,0 +1,142 @@
+use super::{is_valid_key, StorageBackend, StorageError, StoredObject};
+use actix_web::{error::BlockingError, web};
+use async_trait::async_trait;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> LocalStorage {
+        LocalStorage {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        if is_valid_key(key) {
+            Ok(self.root.join(key))
+        } else {
+            Err(StorageError::Backend(format!("invalid key {}", key)))
+        }
+    }
+}
+
+fn io_error(error: std::io::Error) -> StorageError {
+    match error.kind() {
+        ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Io(error.to_string()),
+    }
+}
+
+async fn blocking<T, F>(task: F) -> Result<T, StorageError>
+where
+    F: FnOnce() -> Result<T, StorageError> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(task).await.map_err(|error| match error {
+        BlockingError::Error(error) => error,
+        BlockingError::Canceled => StorageError::Io("blocking task canceled".to_string()),
+    })
+}
+
+fn walk(root: &Path, directory: &Path, objects: &mut Vec<StoredObject>) -> Result<(), StorageError> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(io_error(error)),
+    };
+    for entry in entries {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_dir() {
+            walk(root, &path, objects)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let metadata = fs::metadata(&path).map_err(io_error)?;
+            objects.push(StoredObject {
+                key: relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                size: metadata.len(),
+                modified: metadata.modified().map_err(io_error)?.into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        blocking(move || {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(io_error)?;
+            }
+            fs::write(&path, bytes).map_err(io_error)
+        })
+        .await
+    }
+
+    async fn put_file(&self, key: &str, path: PathBuf, _content_type: &str) -> Result<(), StorageError> {
+        let target = self.path(key)?;
+        blocking(move || {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(io_error)?;
+            }
+            fs::copy(&path, &target).map(|_| ()).map_err(io_error)
+        })
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.path(key)?;
+        blocking(move || fs::read(&path).map_err(io_error)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        blocking(move || match fs::remove_file(&path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(io_error(error)),
+            _ => Ok(()),
+        })
+        .await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        let path = self.path(key)?;
+        blocking(move || Ok(path.is_file())).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, StorageError> {
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+        blocking(move || {
+            let mut objects = Vec::new();
+            walk(&root, &root, &mut objects)?;
+            objects.retain(|object| object.key.starts_with(&prefix));
+            objects.sort_by(|a, b| a.key.cmp(&b.key));
+            Ok(objects)
+        })
+        .await
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, StorageError> {
+        let path = self.path(key)?;
+        blocking(move || Ok(fs::metadata(&path).map_err(io_error)?.len())).await
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.path(key).ok()
+    }
+}
@@ -0,0 +1,132 @@
+pub mod local;
+pub mod s3;
+
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use derive_more::Display;
+use std::{env, fs, path::PathBuf};
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+#[derive(Debug, Display)]
+pub enum StorageError {
+    #[display(fmt = "object not found")]
+    NotFound,
+
+    #[display(fmt = "storage io error: {}", _0)]
+    Io(String),
+
+    #[display(fmt = "storage backend error: {}", _0)]
+    Backend(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// Where uploaded bytes live. Keys are `/` separated paths such as
+/// `wireframes/<asset id>/login.png`.
+#[async_trait(?Send)]
+pub trait StorageBackend {
+    fn name(&self) -> &'static str;
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), StorageError>;
+
+    /// Stores the content of a file on local disk. Backends that can copy or stream a
+    /// file override it, the default reads the whole file.
+    async fn put_file(&self, key: &str, path: PathBuf, content_type: &str) -> Result<(), StorageError> {
+        let bytes = web::block(move || fs::read(path))
+            .await
+            .map_err(|error| StorageError::Io(error.to_string()))?;
+        self.put(key, bytes, content_type).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, StorageError>;
+
+    async fn size(&self, key: &str) -> Result<u64, StorageError>;
+
+    /// `length` bytes of an object from `start`. Backends that can read part of an
+    /// object override it, the default reads the whole object.
+    async fn get_range(&self, key: &str, start: u64, length: u64) -> Result<Vec<u8>, StorageError> {
+        let bytes = self.get(key).await?;
+        let start = (start as usize).min(bytes.len());
+        let end = start.saturating_add(length as usize).min(bytes.len());
+        Ok(bytes[start..end].to_vec())
+    }
+
+    /// Path of the object on local disk when the backend keeps one, so the media
+    /// route can stream it instead of buffering the whole object.
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Storage settings read once at startup and handed to every worker, which builds
+/// its own backend from them.
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    Local {
+        root: String,
+    },
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+impl StorageConfig {
+    pub fn from_env() -> StorageConfig {
+        match env::var("STORAGE_BACKEND").as_deref() {
+            Ok("s3") => StorageConfig::S3 {
+                endpoint: env::var("S3_ENDPOINT")
+                    .unwrap_or_else(|_| "http://127.0.0.1:9000".to_string()),
+                bucket: env::var("S3_BUCKET").unwrap_or_else(|_| "builder".to_string()),
+                region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key: env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set"),
+                secret_key: env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set"),
+            },
+            _ => StorageConfig::Local {
+                root: env::var("STORAGE_LOCAL_ROOT")
+                    .unwrap_or_else(|_| "./static/uploads".to_string()),
+            },
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn StorageBackend> {
+        match self {
+            StorageConfig::Local { root } => Box::new(LocalStorage::new(root)),
+            StorageConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+            } => Box::new(S3Storage::new(
+                endpoint, bucket, region, access_key, secret_key,
+            )),
+        }
+    }
+}
+
+/// Rejects keys that could escape the storage root or the bucket prefix.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('/')
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
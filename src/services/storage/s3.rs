@@ -0,0 +1,568 @@
+use super::{is_valid_key, StorageBackend, StorageError, StoredObject};
+use actix_web::{
+    client::Client,
+    http::{header, HeaderMap, Method},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_OBJECT_SIZE: usize = 1024 * 1024 * 1024;
+
+/// S3 compatible object storage (AWS, MinIO, ...) addressed with path style urls
+/// and signed with AWS signature version 4.
+pub struct S3Storage {
+    client: Client,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> S3Storage {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        S3Storage {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).finish(),
+            endpoint,
+            host,
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: Vec<(String, String)>,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<(u16, Vec<u8>), StorageError> {
+        let headers: Vec<(&str, &str)> = content_type
+            .map(|content_type| ("content-type", content_type))
+            .into_iter()
+            .collect();
+        let (status, _headers, body) = self.exchange(method, key, query, body, &headers).await?;
+        Ok((status, body))
+    }
+
+    /// Sends a signed request. `headers` are sent unsigned next to the signed ones.
+    async fn exchange(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: Vec<(String, String)>,
+        body: Vec<u8>,
+        headers: &[(&str, &str)],
+    ) -> Result<(u16, HeaderMap, Vec<u8>), StorageError> {
+        let path = match key {
+            Some(key) if is_valid_key(key) => {
+                format!("/{}/{}", uri_encode(&self.bucket, false), uri_encode(key, true))
+            }
+            Some(key) => return Err(StorageError::Backend(format!("invalid key {}", key))),
+            None => format!("/{}", uri_encode(&self.bucket, false)),
+        };
+        let mut query = query
+            .into_iter()
+            .map(|(name, value)| (uri_encode(&name, false), uri_encode(&value, false)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method.as_str(),
+            path,
+            query,
+            self.host,
+            payload_hash,
+            amz_date,
+            SIGNED_HEADERS,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes()),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, SIGNED_HEADERS, signature
+        );
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.endpoint, path, query)
+        };
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("host", self.host.as_str())
+            .header("x-amz-content-sha256", payload_hash.as_str())
+            .header("x-amz-date", amz_date.as_str())
+            .header("authorization", authorization.as_str());
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut response = request
+            .send_body(body)
+            .await
+            .map_err(|error| StorageError::Backend(error.to_string()))?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let bytes = response
+            .body()
+            .limit(MAX_OBJECT_SIZE)
+            .await
+            .map_err(|error| StorageError::Backend(error.to_string()))?;
+        Ok((status, headers, bytes.to_vec()))
+    }
+}
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("hmac accepts keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// RFC 3986 encoding as required by signature version 4.
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn status_error(status: u16, body: &[u8]) -> StorageError {
+    match status {
+        404 => StorageError::NotFound,
+        _ => StorageError::Backend(format!(
+            "status {}: {}",
+            status,
+            String::from_utf8_lossy(body)
+        )),
+    }
+}
+
+/// Pulls the text of every `<tag>` element out of an S3 xml response.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|chunk| chunk.split(close.as_str()).next())
+        .map(|value| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+        .collect()
+}
+
+#[async_trait(?Send)]
+impl StorageBackend for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        match self
+            .send(Method::PUT, Some(key), vec![], bytes, Some(content_type))
+            .await?
+        {
+            (200..=299, _) => Ok(()),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        match self.send(Method::GET, Some(key), vec![], vec![], None).await? {
+            (200..=299, body) => Ok(body),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match self
+            .send(Method::DELETE, Some(key), vec![], vec![], None)
+            .await?
+        {
+            (200..=299, _) | (404, _) => Ok(()),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        match self.send(Method::HEAD, Some(key), vec![], vec![], None).await? {
+            (200..=299, _) => Ok(true),
+            (404, _) => Ok(false),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, StorageError> {
+        match self
+            .exchange(Method::HEAD, Some(key), vec![], vec![], &[])
+            .await?
+        {
+            (200..=299, headers, _) => headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse().ok())
+                .ok_or_else(|| StorageError::Backend(format!("no size for {}", key))),
+            (status, _, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Asks the bucket for the range only, so serving part of a large build never
+    /// downloads the rest of it.
+    async fn get_range(&self, key: &str, start: u64, length: u64) -> Result<Vec<u8>, StorageError> {
+        let range = format!("bytes={}-{}", start, start + length.max(1) - 1);
+        match self
+            .exchange(Method::GET, Some(key), vec![], vec![], &[("range", &range)])
+            .await?
+        {
+            (206, _, body) => Ok(body),
+            // a server that ignores the range answers with the whole object
+            (200, _, body) => {
+                let start = (start as usize).min(body.len());
+                let end = start.saturating_add(length as usize).min(body.len());
+                Ok(body[start..end].to_vec())
+            }
+            (status, _, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, StorageError> {
+        let mut objects = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_string()),
+            ];
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token".to_string(), token));
+            }
+            let xml = match self.send(Method::GET, None, query, vec![], None).await? {
+                (200..=299, body) => String::from_utf8_lossy(&body).into_owned(),
+                (status, body) => return Err(status_error(status, &body)),
+            };
+            objects.extend(xml.split("<Contents>").skip(1).filter_map(|contents| {
+                Some(StoredObject {
+                    key: xml_values(contents, "Key").pop()?,
+                    size: xml_values(contents, "Size")
+                        .pop()
+                        .and_then(|size| size.parse().ok())
+                        .unwrap_or_default(),
+                    // an unreadable date counts as fresh so the object is never swept early
+                    modified: xml_values(contents, "LastModified")
+                        .pop()
+                        .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                        .map(|date| date.with_timezone(&Utc))
+                        .unwrap_or_else(Utc::now),
+                })
+            }));
+            let truncated = xml_values(&xml, "IsTruncated")
+                .first()
+                .is_some_and(|value| value == "true");
+            continuation = xml_values(&xml, "NextContinuationToken").into_iter().next();
+            if !truncated || continuation.is_none() {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::Mutex,
+    };
+
+    const ACCESS_KEY: &str = "stand-in-access";
+    const BUCKET: &str = "builder";
+    const PAGE_SIZE: usize = 2;
+
+    type Objects = Mutex<BTreeMap<String, Vec<u8>>>;
+
+    #[test]
+    fn unreserved_characters_are_kept() {
+        assert_eq!(uri_encode("AZaz09-_.~", false), "AZaz09-_.~");
+    }
+
+    #[test]
+    fn everything_else_is_percent_encoded() {
+        assert_eq!(uri_encode("a b+c=d&é", false), "a%20b%2Bc%3Dd%26%C3%A9");
+        assert_eq!(uri_encode("blobs/ab/a b", true), "blobs/ab/a%20b");
+        assert_eq!(uri_encode("blobs/ab", false), "blobs%2Fab");
+    }
+
+    fn percent_decode(value: &str) -> String {
+        let bytes = value.as_bytes();
+        let mut decoded = Vec::new();
+        let mut index = 0;
+        while index < bytes.len() {
+            if bytes[index] == b'%' {
+                decoded.push(u8::from_str_radix(&value[index + 1..index + 3], 16).unwrap());
+                index += 3;
+            } else {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    /// Answers the handful of calls the backend makes the way MinIO does, listing
+    /// `PAGE_SIZE` objects per page. Requests without a signature for `ACCESS_KEY`, or
+    /// whose body does not match its signed hash, are refused.
+    async fn stand_in(
+        request: HttpRequest,
+        body: web::Bytes,
+        objects: web::Data<Objects>,
+    ) -> HttpResponse {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let credential = format!("AWS4-HMAC-SHA256 Credential={}/", ACCESS_KEY);
+        if !header("authorization").starts_with(&credential)
+            || header("x-amz-content-sha256") != hex::encode(Sha256::digest(&body))
+        {
+            return HttpResponse::Forbidden().finish();
+        }
+        let path = percent_decode(request.uri().path());
+        let key = match path.strip_prefix(&format!("/{}", BUCKET)) {
+            Some("") => None,
+            Some(key) => Some(key.trim_start_matches('/').to_string()),
+            None => return HttpResponse::NotFound().finish(),
+        };
+        let mut objects = objects.lock().unwrap();
+        match (request.method().as_str(), key) {
+            ("PUT", Some(key)) => {
+                objects.insert(key, body.to_vec());
+                HttpResponse::Ok().finish()
+            }
+            ("GET", Some(key)) | ("HEAD", Some(key)) => match objects.get(&key) {
+                Some(bytes) => match header("range").strip_prefix("bytes=") {
+                    Some(range) => {
+                        let (start, end) = range.split_once('-').unwrap();
+                        let start: usize = start.parse().unwrap();
+                        let end = end.parse::<usize>().unwrap().min(bytes.len() - 1);
+                        HttpResponse::PartialContent()
+                            .header(
+                                "content-range",
+                                format!("bytes {}-{}/{}", start, end, bytes.len()),
+                            )
+                            .body(bytes[start..=end].to_vec())
+                    }
+                    None => HttpResponse::Ok().body(bytes.clone()),
+                },
+                None => HttpResponse::NotFound().body("<Error><Code>NoSuchKey</Code></Error>"),
+            },
+            ("DELETE", Some(key)) => {
+                objects.remove(&key);
+                HttpResponse::NoContent().finish()
+            }
+            ("GET", None) => {
+                let query =
+                    web::Query::<HashMap<String, String>>::from_query(request.query_string())
+                        .unwrap();
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let after = query.get("continuation-token").cloned().unwrap_or_default();
+                let matching: Vec<(&String, &Vec<u8>)> = objects
+                    .iter()
+                    .filter(|(key, _)| key.starts_with(&prefix) && key.as_str() > after.as_str())
+                    .collect();
+                let page = &matching[..matching.len().min(PAGE_SIZE)];
+                let mut xml = format!(
+                    "<ListBucketResult><IsTruncated>{}</IsTruncated>",
+                    matching.len() > PAGE_SIZE
+                );
+                if matching.len() > PAGE_SIZE {
+                    xml.push_str(&format!(
+                        "<NextContinuationToken>{}</NextContinuationToken>",
+                        page[PAGE_SIZE - 1].0
+                    ));
+                }
+                for (key, bytes) in page {
+                    xml.push_str(&format!(
+                        "<Contents><Key>{}</Key><Size>{}</Size>\
+                         <LastModified>2021-03-01T10:00:00.000Z</LastModified></Contents>",
+                        key.replace('&', "&amp;"),
+                        bytes.len()
+                    ));
+                }
+                xml.push_str("</ListBucketResult>");
+                HttpResponse::Ok().content_type("application/xml").body(xml)
+            }
+            _ => HttpResponse::MethodNotAllowed().finish(),
+        }
+    }
+
+    fn start() -> test::TestServer {
+        let objects = web::Data::new(Objects::default());
+        test::start(move || {
+            App::new()
+                .app_data(objects.clone())
+                .default_service(web::to(stand_in))
+        })
+    }
+
+    fn storage(server: &test::TestServer, access_key: &str) -> S3Storage {
+        S3Storage::new(
+            &server.url(""),
+            BUCKET,
+            "us-east-1",
+            access_key,
+            "stand-in-secret",
+        )
+    }
+
+    #[actix_rt::test]
+    async fn objects_round_trip() {
+        let server = start();
+        let storage = storage(&server, ACCESS_KEY);
+        let key = "blobs/ab/login screen & more.png";
+        assert!(!storage.exists(key).await.unwrap());
+        storage
+            .put(key, b"png".to_vec(), "image/png")
+            .await
+            .unwrap();
+        assert!(storage.exists(key).await.unwrap());
+        assert_eq!(storage.get(key).await.unwrap(), b"png".to_vec());
+        storage.delete(key).await.unwrap();
+        assert!(!storage.exists(key).await.unwrap());
+        assert!(matches!(
+            storage.get(key).await,
+            Err(StorageError::NotFound)
+        ));
+        storage.delete(key).await.unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn ranges_are_read_from_the_bucket() {
+        let server = start();
+        let storage = storage(&server, ACCESS_KEY);
+        let key = "private/ab/build.apk";
+        storage
+            .put(key, b"0123456789".to_vec(), "application/zip")
+            .await
+            .unwrap();
+        assert_eq!(storage.size(key).await.unwrap(), 10);
+        assert_eq!(storage.get_range(key, 2, 3).await.unwrap(), b"234".to_vec());
+        assert_eq!(storage.get_range(key, 8, 2).await.unwrap(), b"89".to_vec());
+        assert!(matches!(
+            storage.size("private/ab/missing").await,
+            Err(StorageError::NotFound)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn listing_follows_continuation_tokens() {
+        let server = start();
+        let storage = storage(&server, ACCESS_KEY);
+        for key in &[
+            "blobs/aa/1",
+            "blobs/ab/2",
+            "blobs/ac/3",
+            "private/ad/4",
+            "renditions/ae/5",
+        ] {
+            storage.put(key, vec![0; 3], "image/png").await.unwrap();
+        }
+        let mut keys: Vec<String> = storage
+            .list("blobs/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["blobs/aa/1", "blobs/ab/2", "blobs/ac/3"]);
+        let objects = storage.list("").await.unwrap();
+        assert_eq!(objects.len(), 5);
+        assert!(objects.iter().all(|object| object.size == 3));
+    }
+
+    #[actix_rt::test]
+    async fn refused_requests_are_errors() {
+        let server = start();
+        let storage = storage(&server, "someone-else");
+        assert!(matches!(
+            storage.put("blobs/ab/1", vec![1], "image/png").await,
+            Err(StorageError::Backend(_))
+        ));
+        assert!(storage.exists("blobs/ab/1").await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn invalid_keys_never_reach_the_bucket() {
+        let server = start();
+        let storage = storage(&server, ACCESS_KEY);
+        assert!(storage.get("../other-bucket/secret").await.is_err());
+        assert!(storage
+            .put("/blobs/ab", vec![1], "image/png")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use crate::controllers::schema::{TrashCollection, TrashItemModel, TrashPurgeReportModel};
+use crate::middleware::error::{ContentBuilderCustomResponseError, Dependent};
+use crate::models::indexes::is_duplicate_key;
+use crate::services::integrity::{dependent, documents};
+use crate::CollectionsContainer;
+use actix_rt::time::{interval_at, Instant};
+use bson::{oid::ObjectId, Document};
+use chrono::{Duration, Utc};
+use mongodb::{error::Error, results::DeleteResult};
+use std::env;
+
+pub fn default_retention_days() -> i64 {
+    env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30)
+}
+
+/// Everything in the trash, most recently deleted first.
+pub async fn list_trash(
+    container: &CollectionsContainer,
+) -> Result<Vec<TrashItemModel>, ContentBuilderCustomResponseError> {
+    let mut items = Vec::new();
+    for (collection, cursor) in [
+        (TrashCollection::Categories, container.category.find_trashed().await),
+        (TrashCollection::Features, container.feature.find_trashed().await),
+        (TrashCollection::Templates, container.template.find_trashed().await),
+        (TrashCollection::Projects, container.project.find_trashed().await),
+        (TrashCollection::Prototypes, container.prototype.find_trashed().await),
+    ] {
+        for document in documents(cursor).await? {
+            let (id, deleted_at) = match (
+                document.get_object_id("_id"),
+                document.get_datetime("deleted_at"),
+            ) {
+                (Ok(id), Ok(deleted_at)) => (id.to_hex(), *deleted_at),
+                _ => return Err(ContentBuilderCustomResponseError::InternalError),
+            };
+            items.push((
+                deleted_at,
+                TrashItemModel {
+                    collection,
+                    id,
+                    name: document.get_str("name").ok().map(String::from),
+                    deleted_at: deleted_at.to_rfc3339(),
+                },
+            ));
+        }
+    }
+    items.sort_by(|(left, _), (right, _)| right.cmp(left));
+    Ok(items.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Takes an entity out of the trash. Templates need their category back first, projects
+/// and prototypes their template, and a prototype cannot come back over the one its
+/// template has since been given.
+pub async fn restore(
+    container: &CollectionsContainer,
+    collection: TrashCollection,
+    id: &str,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    ObjectId::with_string(id)
+        .map_err(|_oid_error| ContentBuilderCustomResponseError::BadClientData)?;
+    let document = match collection {
+        TrashCollection::Categories => container.category.find_trashed_by_id(id).await,
+        TrashCollection::Features => container.feature.find_trashed_by_id(id).await,
+        TrashCollection::Templates => container.template.find_trashed_by_id(id).await,
+        TrashCollection::Projects => container.project.find_trashed_by_id(id).await,
+        TrashCollection::Prototypes => container.prototype.find_trashed_by_id(id).await,
+    }
+    .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)?
+    .ok_or(ContentBuilderCustomResponseError::NotFound)?;
+
+    check_restorable(container, collection, &document).await?;
+
+    match collection {
+        TrashCollection::Categories => container.category.restore_one(id).await,
+        TrashCollection::Features => container.feature.restore_one(id).await,
+        TrashCollection::Templates => container.template.restore_one(id).await,
+        TrashCollection::Projects => container.project.restore_one(id).await,
+        TrashCollection::Prototypes => container.prototype.restore_one(id).await,
+    }
+    .map_err(|mongodb_error| {
+        // a live category took the name while this one was in the trash
+        if is_duplicate_key(&mongodb_error) {
+            ContentBuilderCustomResponseError::AlreadyExists
+        } else {
+            ContentBuilderCustomResponseError::InternalError
+        }
+    })?
+    .map(|_document| ())
+    .ok_or(ContentBuilderCustomResponseError::NotFound)
+}
+
+async fn check_restorable(
+    container: &CollectionsContainer,
+    collection: TrashCollection,
+    document: &Document,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    if let Some((parent, parent_id)) = parent_of(collection, document) {
+        let id = parent_id.to_hex();
+        let (live, trashed) = match parent {
+            TrashCollection::Categories => (
+                container.category.find_one_by_id(&id).await,
+                container.category.find_trashed_by_id(&id).await,
+            ),
+            _ => (
+                container.template.find_one_by_id(&id).await,
+                container.template.find_trashed_by_id(&id).await,
+            ),
+        };
+        if let Some(missing) = missing_parent(parent, &parent_id, live, trashed)? {
+            return Err(ContentBuilderCustomResponseError::MissingDependencies(vec![missing]));
+        }
+    }
+
+    if collection == TrashCollection::Prototypes {
+        if let Ok(template_id) = document.get_object_id("template_id") {
+            live_prototype_conflict(
+                container
+                    .prototype
+                    .find_one_by_template_id(&template_id.to_hex())
+                    .await,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The entity a trashed document needs live before it can be restored: the category of a
+/// template, the template of a project or prototype.
+fn parent_of(collection: TrashCollection, document: &Document) -> Option<(TrashCollection, ObjectId)> {
+    let (parent, field) = match collection {
+        TrashCollection::Templates => (TrashCollection::Categories, "category"),
+        TrashCollection::Projects => (TrashCollection::Templates, "template"),
+        TrashCollection::Prototypes => (TrashCollection::Templates, "template_id"),
+        TrashCollection::Categories | TrashCollection::Features => return None,
+    };
+    document
+        .get_object_id(field)
+        .ok()
+        .map(|parent_id| (parent, parent_id.clone()))
+}
+
+/// A template has a single prototype, a trashed one cannot come back over a live one.
+fn live_prototype_conflict(
+    live: Result<Option<Document>, Error>,
+) -> Result<(), ContentBuilderCustomResponseError> {
+    match live {
+        Ok(Some(prototype)) => Err(ContentBuilderCustomResponseError::Conflict(
+            dependent(TrashCollection::Prototypes.as_str(), &prototype)
+                .into_iter()
+                .collect(),
+        )),
+        Ok(None) => Ok(()),
+        Err(_mongodb_error) => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// `None` when the parent is live, otherwise the parent, named when it is in the trash.
+fn missing_parent(
+    collection: TrashCollection,
+    id: &ObjectId,
+    live: Result<Option<Document>, Error>,
+    trashed: Result<Option<Document>, Error>,
+) -> Result<Option<Dependent>, ContentBuilderCustomResponseError> {
+    match (live, trashed) {
+        (Ok(Some(_document)), _) => Ok(None),
+        (Ok(None), Ok(trashed)) => Ok(Some(Dependent {
+            collection: collection.as_str().to_string(),
+            id: id.to_hex(),
+            name: trashed
+                .as_ref()
+                .and_then(|document| document.get_str("name").ok())
+                .map(String::from),
+        })),
+        _ => Err(ContentBuilderCustomResponseError::InternalError),
+    }
+}
+
+/// Order the trash is purged in, whatever references another entity goes before it so
+/// a purge stopped part way never leaves a reference to a purged document behind.
+const PURGE_ORDER: [TrashCollection; 5] = [
+    TrashCollection::Prototypes,
+    TrashCollection::Projects,
+    TrashCollection::Templates,
+    TrashCollection::Features,
+    TrashCollection::Categories,
+];
+
+/// Permanently deletes whatever has been in the trash for longer than the retention
+/// window.
+pub async fn purge_expired(
+    container: &CollectionsContainer,
+    retention_days: i64,
+) -> Result<TrashPurgeReportModel, ContentBuilderCustomResponseError> {
+    let cutoff = Utc::now() - Duration::days(retention_days);
+    let mut report = TrashPurgeReportModel {
+        retention_days,
+        ..TrashPurgeReportModel::default()
+    };
+    for collection in PURGE_ORDER {
+        match collection {
+            TrashCollection::Prototypes => {
+                report.prototypes = purged(container.prototype.purge_deleted_before(cutoff).await)?
+            }
+            TrashCollection::Projects => {
+                report.projects = purged(container.project.purge_deleted_before(cutoff).await)?
+            }
+            TrashCollection::Templates => {
+                report.templates = purged(container.template.purge_deleted_before(cutoff).await)?
+            }
+            TrashCollection::Features => {
+                report.features = purged(container.feature.purge_deleted_before(cutoff).await)?
+            }
+            TrashCollection::Categories => {
+                report.categories = purged(container.category.purge_deleted_before(cutoff).await)?
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn purged(result: Result<DeleteResult, Error>) -> Result<i64, ContentBuilderCustomResponseError> {
+    result
+        .map(|result| result.deleted_count)
+        .map_err(|_mongodb_error| ContentBuilderCustomResponseError::InternalError)
+}
+
+/// Purges every `interval_hours`, the first run happening one interval after startup.
+pub async fn run_periodically(container: CollectionsContainer, interval_hours: u64) {
+    let every = std::time::Duration::from_secs(interval_hours * 3600);
+    let mut ticks = interval_at(Instant::now() + every, every);
+    loop {
+        ticks.tick().await;
+        match purge_expired(&container, default_retention_days()).await {
+            Ok(report) => tracing::info!(
+                categories = report.categories,
+                features = report.features,
+                templates = report.templates,
+                projects = report.projects,
+                prototypes = report.prototypes,
+                "trash purge done"
+            ),
+            Err(error) => tracing::error!(error = %error, "trash purge failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::doc;
+    use mongodb::error::ErrorKind;
+
+    fn failure() -> Error {
+        Error::from(ErrorKind::Io(std::io::Error::other("connection reset")))
+    }
+
+    #[test]
+    fn parents_are_read_from_their_field() {
+        let category = ObjectId::new();
+        let template = ObjectId::new();
+        assert_eq!(
+            parent_of(
+                TrashCollection::Templates,
+                &doc! {"category": category.clone()}
+            ),
+            Some((TrashCollection::Categories, category))
+        );
+        assert_eq!(
+            parent_of(
+                TrashCollection::Projects,
+                &doc! {"template": template.clone()}
+            ),
+            Some((TrashCollection::Templates, template.clone()))
+        );
+        assert_eq!(
+            parent_of(
+                TrashCollection::Prototypes,
+                &doc! {"template_id": template.clone()}
+            ),
+            Some((TrashCollection::Templates, template.clone()))
+        );
+        assert_eq!(
+            parent_of(TrashCollection::Features, &doc! {"template": template}),
+            None
+        );
+        assert_eq!(parent_of(TrashCollection::Templates, &doc! {}), None);
+    }
+
+    #[test]
+    fn live_parent_is_not_missing() {
+        let id = ObjectId::new();
+        let missing = missing_parent(
+            TrashCollection::Templates,
+            &id,
+            Ok(Some(doc! {"_id": id.clone()})),
+            Ok(None),
+        );
+        assert!(matches!(missing, Ok(None)));
+    }
+
+    #[test]
+    fn trashed_parent_is_missing_and_named() {
+        let id = ObjectId::new();
+        let missing = missing_parent(
+            TrashCollection::Categories,
+            &id,
+            Ok(None),
+            Ok(Some(doc! {"_id": id.clone(), "name": "Social"})),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(missing.collection, "categories");
+        assert_eq!(missing.id, id.to_hex());
+        assert_eq!(missing.name.as_deref(), Some("Social"));
+    }
+
+    #[test]
+    fn purged_parent_is_missing_without_a_name() {
+        let id = ObjectId::new();
+        let missing = missing_parent(TrashCollection::Templates, &id, Ok(None), Ok(None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(missing.collection, "templates");
+        assert_eq!(missing.id, id.to_hex());
+        assert_eq!(missing.name, None);
+    }
+
+    #[test]
+    fn failed_lookups_are_internal_errors() {
+        let id = ObjectId::new();
+        assert!(matches!(
+            missing_parent(TrashCollection::Templates, &id, Err(failure()), Ok(None)),
+            Err(ContentBuilderCustomResponseError::InternalError)
+        ));
+        assert!(matches!(
+            missing_parent(TrashCollection::Templates, &id, Ok(None), Err(failure())),
+            Err(ContentBuilderCustomResponseError::InternalError)
+        ));
+    }
+
+    #[test]
+    fn prototype_cannot_come_back_over_a_live_one() {
+        let live = ObjectId::new();
+        match live_prototype_conflict(Ok(Some(doc! {"_id": live.clone()}))) {
+            Err(ContentBuilderCustomResponseError::Conflict(dependents)) => {
+                assert_eq!(dependents.len(), 1);
+                assert_eq!(dependents[0].collection, "prototypes");
+                assert_eq!(dependents[0].id, live.to_hex());
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(live_prototype_conflict(Ok(None)).is_ok());
+        assert!(matches!(
+            live_prototype_conflict(Err(failure())),
+            Err(ContentBuilderCustomResponseError::InternalError)
+        ));
+    }
+
+    /// Collections the documents of `collection` hold ids of, which `PURGE_ORDER` must respect.
+    fn references(collection: TrashCollection) -> &'static [TrashCollection] {
+        match collection {
+            TrashCollection::Prototypes => &[TrashCollection::Templates, TrashCollection::Features],
+            TrashCollection::Projects => &[TrashCollection::Templates, TrashCollection::Features],
+            TrashCollection::Templates => &[TrashCollection::Categories, TrashCollection::Features],
+            TrashCollection::Features | TrashCollection::Categories => &[],
+        }
+    }
+
+    #[test]
+    fn purge_goes_through_every_collection_once() {
+        for collection in [
+            TrashCollection::Categories,
+            TrashCollection::Features,
+            TrashCollection::Templates,
+            TrashCollection::Projects,
+            TrashCollection::Prototypes,
+        ] {
+            assert_eq!(
+                PURGE_ORDER
+                    .iter()
+                    .filter(|&&purged| purged == collection)
+                    .count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn referencing_collections_are_purged_first() {
+        let position = |collection: TrashCollection| {
+            PURGE_ORDER
+                .iter()
+                .position(|&purged| purged == collection)
+                .unwrap()
+        };
+        for &collection in PURGE_ORDER.iter() {
+            for &referenced in references(collection) {
+                assert!(
+                    position(collection) < position(referenced),
+                    "{} is purged after {}",
+                    collection.as_str(),
+                    referenced.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn restore_parents_are_referenced() {
+        let id = ObjectId::new();
+        for (collection, document) in [
+            (TrashCollection::Templates, doc! {"category": id.clone()}),
+            (TrashCollection::Projects, doc! {"template": id.clone()}),
+            (
+                TrashCollection::Prototypes,
+                doc! {"template_id": id.clone()},
+            ),
+        ] {
+            let (parent, _) = parent_of(collection, &document).unwrap();
+            assert!(references(collection).contains(&parent));
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use crate::middleware::error::ContentBuilderCustomResponseError;
+use std::io::Cursor;
+
+const MB: u64 = 1024 * 1024;
+
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+const ARCHIVE_TYPES: &[&str] = &[
+    "application/zip",
+    "application/gzip",
+    "application/x-tar",
+    "application/x-7z-compressed",
+    "application/vnd.android.package-archive",
+    "application/vnd.android.aab",
+    "application/x-ios-app",
+];
+const DOCUMENT_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+];
+
+/// What a given kind of upload may contain. Content types are detected from the file
+/// bytes, the client supplied name only refines zip containers such as apk or docx.
+#[derive(Debug)]
+pub struct UploadPolicy {
+    pub allowed: &'static [&'static [&'static str]],
+    pub max_size: u64,
+    pub max_dimensions: Option<(u32, u32)>,
+}
+
+pub const IMAGE_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[IMAGE_TYPES],
+    max_size: 5 * MB,
+    max_dimensions: Some((4096, 4096)),
+};
+
+pub const WIREFRAME_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[IMAGE_TYPES],
+    max_size: 10 * MB,
+    max_dimensions: Some((8192, 8192)),
+};
+
+pub const SPECIFICATION_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[DOCUMENT_TYPES],
+    max_size: 20 * MB,
+    max_dimensions: None,
+};
+
+pub const MVP_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[ARCHIVE_TYPES],
+    max_size: 500 * MB,
+    max_dimensions: None,
+};
+
+pub const DESIGN_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[IMAGE_TYPES, &["application/pdf", "application/zip"]],
+    max_size: 100 * MB,
+    max_dimensions: Some((16384, 16384)),
+};
+
+pub const DELIVERABLE_POLICY: UploadPolicy = UploadPolicy {
+    allowed: &[IMAGE_TYPES, DOCUMENT_TYPES, ARCHIVE_TYPES],
+    max_size: 500 * MB,
+    max_dimensions: Some((16384, 16384)),
+};
+
+/// Largest `max_size` of the policies above, no multipart file is buffered past it.
+pub const MAX_UPLOAD_SIZE: u64 = 500 * MB;
+
+impl UploadPolicy {
+    pub fn check_size(&self, size: u64) -> Result<(), ContentBuilderCustomResponseError> {
+        if size > self.max_size {
+            Err(ContentBuilderCustomResponseError::PayloadTooLarge)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the detected content type of an accepted upload.
+    pub fn check_content(
+        &self,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<&'static str, ContentBuilderCustomResponseError> {
+        self.check_size(bytes.len() as u64)?;
+        let content_type = sniff(name, bytes)
+            .filter(|content_type| self.allowed.iter().any(|types| types.contains(content_type)))
+            .ok_or(ContentBuilderCustomResponseError::UnsupportedMediaType)?;
+        if let Some((max_width, max_height)) = self
+            .max_dimensions
+            .filter(|_| content_type.starts_with("image/"))
+        {
+            let (width, height) = image::io::Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .ok()
+                .and_then(|reader| reader.into_dimensions().ok())
+                .ok_or(ContentBuilderCustomResponseError::UnsupportedMediaType)?;
+            if width > max_width || height > max_height {
+                return Err(ContentBuilderCustomResponseError::PayloadTooLarge);
+            }
+        }
+        Ok(content_type)
+    }
+}
+
+/// Content type from the leading bytes of a file.
+pub fn sniff(name: &str, bytes: &[u8]) -> Option<&'static str> {
+    let content_type = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        zip_container(name)
+    } else if bytes.starts_with(b"\x1f\x8b") {
+        "application/gzip"
+    } else if bytes.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        "application/x-7z-compressed"
+    } else if bytes.len() >= 262 && &bytes[257..262] == b"ustar" {
+        "application/x-tar"
+    } else {
+        return None;
+    };
+    Some(content_type)
+}
+
+fn zip_container(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "apk" => "application/vnd.android.package-archive",
+        "aab" => "application/vnd.android.aab",
+        "ipa" => "application/x-ios-app",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        _ => "application/zip",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: &[&UploadPolicy] = &[
+        &IMAGE_POLICY,
+        &WIREFRAME_POLICY,
+        &SPECIFICATION_POLICY,
+        &MVP_POLICY,
+        &DESIGN_POLICY,
+        &DELIVERABLE_POLICY,
+    ];
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::DynamicImage::new_rgb8(width, height)
+            .write_to(&mut bytes, image::ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn multipart_limit_covers_every_policy() {
+        assert_eq!(
+            POLICIES.iter().map(|policy| policy.max_size).max(),
+            Some(MAX_UPLOAD_SIZE)
+        );
+    }
+
+    #[test]
+    fn sniffs_images_and_documents_from_their_bytes() {
+        assert_eq!(sniff("a.jpg", &png(1, 1)), Some("image/png"));
+        assert_eq!(sniff("a.png", b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(sniff("a", b"GIF89a"), Some("image/gif"));
+        assert_eq!(sniff("a", b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff("a.png", b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff("a", b"\x1f\x8b\x08"), Some("application/gzip"));
+        assert_eq!(sniff("a.png", b"<svg></svg>"), None);
+        assert_eq!(sniff("a.png", b""), None);
+    }
+
+    #[test]
+    fn zip_containers_are_named_by_extension() {
+        let zip = b"PK\x03\x04rest";
+        assert_eq!(
+            sniff("build.APK", zip),
+            Some("application/vnd.android.package-archive")
+        );
+        assert_eq!(sniff("build.ipa", zip), Some("application/x-ios-app"));
+        assert_eq!(
+            sniff("spec.docx", zip),
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        );
+        assert_eq!(sniff("design.fig", zip), Some("application/zip"));
+    }
+
+    #[test]
+    fn tar_is_found_past_its_header() {
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff("build.tar", &tar), Some("application/x-tar"));
+    }
+
+    #[test]
+    fn size_is_checked_against_the_policy() {
+        assert!(IMAGE_POLICY.check_size(5 * MB).is_ok());
+        assert!(matches!(
+            IMAGE_POLICY.check_size(5 * MB + 1),
+            Err(ContentBuilderCustomResponseError::PayloadTooLarge)
+        ));
+    }
+
+    #[test]
+    fn content_must_be_allowed_by_the_policy() {
+        assert_eq!(
+            IMAGE_POLICY.check_content("a.png", &png(2, 2)).unwrap(),
+            "image/png"
+        );
+        assert!(matches!(
+            IMAGE_POLICY.check_content("a.pdf", b"%PDF-1.7"),
+            Err(ContentBuilderCustomResponseError::UnsupportedMediaType)
+        ));
+        assert!(matches!(
+            SPECIFICATION_POLICY.check_content("spec.pdf", &png(2, 2)),
+            Err(ContentBuilderCustomResponseError::UnsupportedMediaType)
+        ));
+        assert_eq!(
+            DESIGN_POLICY
+                .check_content("design.pdf", b"%PDF-1.7")
+                .unwrap(),
+            "application/pdf"
+        );
+    }
+
+    #[test]
+    fn images_over_the_dimensions_are_too_large() {
+        assert!(matches!(
+            IMAGE_POLICY.check_content("a.png", &png(4097, 1)),
+            Err(ContentBuilderCustomResponseError::PayloadTooLarge)
+        ));
+        assert!(WIREFRAME_POLICY
+            .check_content("a.png", &png(4097, 1))
+            .is_ok());
+    }
+
+    #[test]
+    fn undecodable_images_are_refused() {
+        let mut truncated = png(2, 2);
+        truncated.truncate(12);
+        assert!(matches!(
+            IMAGE_POLICY.check_content("a.png", &truncated),
+            Err(ContentBuilderCustomResponseError::UnsupportedMediaType)
+        ));
+    }
+}